@@ -0,0 +1,75 @@
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::dns_record::{DnsRecord, RecordData};
+use cdn_dns::dns_message::message_ref::DnsMessageRef;
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// A referral like response, with a question, answers,
+/// authorities and glue
+fn response_bytes() -> Vec<u8> {
+    let name = |text: &str| text.parse::<DnsName>().unwrap();
+    let qname = name("cdn.esi.dz");
+    let mut builder = DnsMessage::query(&qname, QueryType::A).id(6666);
+    for i in 1..=4 {
+        builder = builder.answer(DnsRecord::new_a(&format!("172.16.{}.2", i), &qname));
+    }
+    for i in 1..=4 {
+        let host = name(&format!("ns{}.esi.dz", i));
+        builder = builder
+            .authority(DnsRecord::with_data(
+                name("esi.dz"),
+                3600,
+                RecordData::NS { host: host.clone() },
+            ))
+            .additional(DnsRecord::new_a(&format!("10.0.0.{}", i), &host));
+    }
+    let buffer = builder.build().into_buf().unwrap();
+    buffer.buf[..buffer.pos()].to_vec()
+}
+
+fn message_parsing(c: &mut Criterion) {
+    let bytes = response_bytes();
+    let hostname = "cdn.esi.dz".parse::<DnsName>().unwrap();
+
+    c.bench_function("owned/full message", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::from_bytes(black_box(&bytes));
+            buffer.set_strict(true);
+            DnsMessage::from_buf(&mut buffer).unwrap()
+        })
+    });
+
+    c.bench_function("owned/first question", |b| {
+        b.iter(|| {
+            let mut buffer = PacketBuffer::from_bytes(black_box(&bytes));
+            buffer.set_strict(true);
+            let message = DnsMessage::from_buf(&mut buffer).unwrap();
+            message.questions[0].qname == hostname
+        })
+    });
+
+    c.bench_function("view/first question", |b| {
+        b.iter(|| {
+            let message = DnsMessageRef::new(black_box(&bytes)).unwrap();
+            let question = message.first_question().unwrap().unwrap();
+            question.qname.eq_name(&hostname)
+        })
+    });
+
+    c.bench_function("view/all records", |b| {
+        b.iter(|| {
+            let message = DnsMessageRef::new(black_box(&bytes)).unwrap();
+            let records = message
+                .answers()
+                .chain(message.authorities())
+                .chain(message.resources());
+            records
+                .map(|record| record.unwrap().ttl as u64)
+                .sum::<u64>()
+        })
+    });
+}
+
+criterion_group!(benches, message_parsing);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+#![no_main]
+
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::DnsMessage;
+use libfuzzer_sys::fuzz_target;
+
+// Parsing arbitrary bytes must never panic, in either mode.
+fuzz_target!(|data: &[u8]| {
+    for strict in [true, false] {
+        let mut buffer = PacketBuffer::from_bytes(data);
+        buffer.set_strict(strict);
+        if DnsMessage::from_buf(&mut buffer).is_err() {
+            let _ = DnsMessage::format_error(&mut buffer);
+        }
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::DnsMessage;
+use libfuzzer_sys::fuzz_target;
+
+// Whatever the strict parser accepts must be written back and
+// parsed again into the same message.
+fuzz_target!(|data: &[u8]| {
+    let mut buffer = PacketBuffer::from_bytes(data);
+    buffer.set_strict(true);
+    let Ok(mut message) = DnsMessage::from_buf(&mut buffer) else {
+        return;
+    };
+    let Ok(written) = message.into_buf() else {
+        return;
+    };
+
+    let mut buffer = PacketBuffer::from_bytes(&written.buf[..written.pos()]);
+    buffer.set_strict(true);
+    let parsed = DnsMessage::from_buf(&mut buffer).expect("failed to parse a written message");
+    assert_eq!(parsed.to_string(), message.to_string());
+});
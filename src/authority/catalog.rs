@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::dns_message::dns_name::DnsName;
+use crate::errors::Result;
+use crate::settings::config::ZoneSettings;
+
+use super::journal::Diff;
+use super::zone::{serial_lt, Zone};
+
+/// The zones served by the server. Zones are shared as
+/// snapshots, replacing a zone does not disturb the queries
+/// being answered from the previous one.
+#[derive(Default)]
+pub struct Catalog {
+    zones: RwLock<HashMap<DnsName, Arc<Zone>>>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the zones of the configuration, the first invalid
+    /// zone fails the whole catalog. A secondary zone without a
+    /// file yet is left to its first transfer.
+    ///
+    /// takes: `&[ZoneSettings]`
+    ///
+    /// returns: `Result<Catalog>`
+    pub fn load(settings: &[ZoneSettings]) -> Result<Self> {
+        let catalog = Self::new();
+        for zone in settings {
+            if zone.primary.is_some() && !Path::new(&zone.file).exists() {
+                continue;
+            }
+            catalog.insert(Zone::load(zone.origin.clone(), Path::new(&zone.file))?);
+        }
+        Ok(catalog)
+    }
+
+    /// Adds `zone`, replacing the zone of the same origin
+    pub fn insert(&self, zone: Zone) {
+        let mut zones = self.zones.write().unwrap();
+        zones.insert(zone.origin().clone(), Arc::new(zone));
+    }
+
+    /// Replaces the zone of the same origin by `zone`, recording
+    /// the changes in its journal for IXFR when `zone` is a newer
+    /// version. A zone that is not newer starts a new history.
+    pub fn update(&self, zone: Zone) {
+        let mut zones = self.zones.write().unwrap();
+        let zone = match zones.get(zone.origin()) {
+            Some(old) if serial_lt(old.serial(), zone.serial()) => {
+                let mut journal = old.journal().clone();
+                journal.push(Diff::between(old, &zone));
+                zone.with_journal(journal)
+            }
+            _ => zone,
+        };
+        zones.insert(zone.origin().clone(), Arc::new(zone));
+    }
+
+    /// Removes the zone whose origin is `origin`
+    pub fn remove(&self, origin: &DnsName) -> Option<Arc<Zone>> {
+        self.zones.write().unwrap().remove(origin)
+    }
+
+    /// The zone whose origin is `origin`
+    pub fn get(&self, origin: &DnsName) -> Option<Arc<Zone>> {
+        self.zones.read().unwrap().get(origin).cloned()
+    }
+
+    /// The closest zone enclosing `qname`
+    pub fn find(&self, qname: &DnsName) -> Option<Arc<Zone>> {
+        let zones = self.zones.read().unwrap();
+        qname
+            .ancestors()
+            .find_map(|origin| zones.get(&origin).cloned())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.read().unwrap().is_empty()
+    }
+}
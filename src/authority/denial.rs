@@ -0,0 +1,252 @@
+//! Authenticated denial of existence: the NSEC (RFC 4034) or
+//! NSEC3 (RFC 5155) chain of a zone, and the records proving
+//! that a name or a type does not exist.
+
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::presentation::to_base32hex;
+use crate::dns_message::QueryType;
+use crate::errors::Result;
+
+use super::zone::Zone;
+
+/// The NSEC3 hash algorithm, SHA-1
+const SHA1: u8 = 1;
+
+/// The parameters of an NSEC3 chain
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nsec3Params {
+    pub salt: Vec<u8>,
+    pub iterations: u16,
+}
+
+/// The chain of a version of a zone, built once per serial
+#[derive(Debug)]
+pub struct Chain {
+    serial: u32,
+    links: Links,
+}
+
+#[derive(Debug)]
+enum Links {
+    /// the names holding data of the zone, in canonical order
+    Nsec(Vec<DnsName>),
+    /// the hashes of those names and of the empty non-terminals
+    /// above them, in order
+    Nsec3 {
+        params: Nsec3Params,
+        hashes: Vec<(Vec<u8>, DnsName)>,
+    },
+}
+
+impl Chain {
+    /// Builds the chain of `zone`, NSEC3 when `nsec3` is given
+    pub fn new(zone: &Zone, nsec3: Option<&Nsec3Params>) -> Self {
+        let names: Vec<DnsName> = zone
+            .names()
+            .filter(|name| !zone.is_occluded(name))
+            .cloned()
+            .collect();
+        let links = match nsec3 {
+            None => Links::Nsec(names),
+            Some(params) => {
+                let mut all: Vec<DnsName> = names
+                    .iter()
+                    .flat_map(|name| {
+                        name.ancestors()
+                            .take_while(|ancestor| ancestor.is_subdomain_of(zone.origin()))
+                    })
+                    .collect();
+                all.sort();
+                all.dedup();
+                let mut hashes: Vec<(Vec<u8>, DnsName)> = all
+                    .into_iter()
+                    .map(|name| (nsec3_hash(&name, params), name))
+                    .collect();
+                hashes.sort();
+                Links::Nsec3 {
+                    params: params.clone(),
+                    hashes,
+                }
+            }
+        };
+        Self {
+            serial: zone.serial(),
+            links,
+        }
+    }
+
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// Proves that `qname` does not exist, nor a wildcard that
+    /// could answer for it
+    pub fn nxdomain(&self, zone: &Zone, qname: &DnsName) -> Result<Vec<DnsRecord>> {
+        let encloser = zone.closest_encloser(qname);
+        let wildcard = encloser.prepend(b"*")?;
+        Ok(match self.links {
+            Links::Nsec(_) => vec![self.covering(zone, qname), self.covering(zone, &wildcard)],
+            Links::Nsec3 { .. } => vec![
+                self.matching(zone, &encloser),
+                self.covering(zone, &next_closer(qname, &encloser)),
+                self.covering(zone, &wildcard),
+            ],
+        })
+    }
+
+    /// Proves that `name`, which exists, holds no record of the
+    /// requested type: its record lists its types
+    pub fn nodata(&self, zone: &Zone, name: &DnsName) -> Vec<DnsRecord> {
+        vec![self.matching(zone, name)]
+    }
+
+    /// Proves that `qname`, answered from a wildcard, does not
+    /// exist itself
+    pub fn wildcard_answer(&self, zone: &Zone, qname: &DnsName) -> Vec<DnsRecord> {
+        match self.links {
+            Links::Nsec(_) => vec![self.covering(zone, qname)],
+            Links::Nsec3 { .. } => {
+                let encloser = zone.closest_encloser(qname);
+                vec![self.covering(zone, &next_closer(qname, &encloser))]
+            }
+        }
+    }
+
+    /// Proves that `qname` does not exist, and that the wildcard
+    /// answering for it holds no record of the requested type
+    pub fn wildcard_nodata(&self, zone: &Zone, qname: &DnsName) -> Result<Vec<DnsRecord>> {
+        let encloser = zone.closest_encloser(qname);
+        let wildcard = encloser.prepend(b"*")?;
+        Ok(match self.links {
+            Links::Nsec(_) => vec![self.covering(zone, qname), self.matching(zone, &wildcard)],
+            Links::Nsec3 { .. } => vec![
+                self.matching(zone, &encloser),
+                self.covering(zone, &next_closer(qname, &encloser)),
+                self.matching(zone, &wildcard),
+            ],
+        })
+    }
+
+    /// The record of `name`, or the one covering it when `name`
+    /// has none, as empty non-terminals in an NSEC chain
+    fn matching(&self, zone: &Zone, name: &DnsName) -> DnsRecord {
+        let index = match self.links {
+            Links::Nsec(ref names) => names.binary_search(name).ok(),
+            Links::Nsec3 {
+                ref params,
+                ref hashes,
+            } => {
+                let hash = nsec3_hash(name, params);
+                hashes.binary_search_by(|(other, _)| other.cmp(&hash)).ok()
+            }
+        };
+        match index {
+            Some(index) => self.record(zone, index),
+            None => self.covering(zone, name),
+        }
+    }
+
+    /// The record whose owner comes right before `name` in the
+    /// chain, the last one covering the names before the first
+    fn covering(&self, zone: &Zone, name: &DnsName) -> DnsRecord {
+        let (after, len) = match self.links {
+            Links::Nsec(ref names) => (names.partition_point(|other| other <= name), names.len()),
+            Links::Nsec3 {
+                ref params,
+                ref hashes,
+            } => {
+                let hash = nsec3_hash(name, params);
+                (
+                    hashes.partition_point(|(other, _)| *other <= hash),
+                    hashes.len(),
+                )
+            }
+        };
+        self.record(zone, (after + len - 1) % len)
+    }
+
+    /// The NSEC or NSEC3 record of the link at `index`
+    fn record(&self, zone: &Zone, index: usize) -> DnsRecord {
+        let ttl = zone.negative_soa().ttl();
+        let mut record = match self.links {
+            Links::Nsec(ref names) => {
+                let name = &names[index];
+                let mut types = types_at(zone, name);
+                types.extend([QueryType::RRSIG, QueryType::NSEC]);
+                let next = names[(index + 1) % names.len()].clone();
+                DnsRecord::with_data(name.clone(), ttl, RecordData::NSEC { next, types })
+            }
+            Links::Nsec3 {
+                ref params,
+                ref hashes,
+            } => {
+                let (hash, name) = &hashes[index];
+                let mut types = types_at(zone, name);
+                if *name == *zone.origin() {
+                    types.push(QueryType::NSEC3PARAM);
+                }
+                // the RRsets of a delegation are not signed, but
+                // its DS RRset
+                let is_unsigned = zone.is_cut(name) && !types.contains(&QueryType::DS);
+                if !types.is_empty() && !is_unsigned {
+                    types.push(QueryType::RRSIG);
+                }
+                let owner = zone
+                    .origin()
+                    .prepend(to_base32hex(hash).as_bytes())
+                    .expect("a hash makes a valid label");
+                let data = RecordData::NSEC3 {
+                    hash_algorithm: SHA1,
+                    flags: 0,
+                    iterations: params.iterations,
+                    salt: params.salt.clone(),
+                    next_hashed: hashes[(index + 1) % hashes.len()].0.clone(),
+                    types,
+                };
+                DnsRecord::with_data(owner, ttl, data)
+            }
+        };
+        record.class = zone.class();
+        if let RecordData::NSEC { ref mut types, .. } | RecordData::NSEC3 { ref mut types, .. } =
+            record.data
+        {
+            types.sort_by_key(|qtype| u16::from(*qtype));
+        }
+        record
+    }
+}
+
+/// The types of the records at `name`, the apex also holds the
+/// DNSKEY RRset of the signer
+fn types_at(zone: &Zone, name: &DnsName) -> Vec<QueryType> {
+    let mut types: Vec<QueryType> = Vec::new();
+    for record in zone.records_at(name) {
+        if !types.contains(&record.qtype()) {
+            types.push(record.qtype());
+        }
+    }
+    if *name == *zone.origin() {
+        types.push(QueryType::DNSKEY);
+    }
+    types
+}
+
+/// The ancestor of `qname` right below its closest encloser
+fn next_closer(qname: &DnsName, encloser: &DnsName) -> DnsName {
+    qname.trim_to(encloser.label_count() + 1)
+}
+
+/// The NSEC3 hash of `name` (RFC 5155 section 5)
+pub fn nsec3_hash(name: &DnsName, params: &Nsec3Params) -> Vec<u8> {
+    let mut data = name.to_lowercase().to_wire();
+    data.extend_from_slice(&params.salt);
+    let mut hash = digest(&SHA1_FOR_LEGACY_USE_ONLY, &data).as_ref().to_vec();
+    for _ in 0..params.iterations {
+        hash.extend_from_slice(&params.salt);
+        hash = digest(&SHA1_FOR_LEGACY_USE_ONLY, &hash).as_ref().to_vec();
+    }
+    hash
+}
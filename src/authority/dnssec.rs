@@ -0,0 +1,497 @@
+//! Online DNSSEC signing (RFC 4033 to 4035): the answers of a
+//! zone are signed as they are sent, with keys loaded from local
+//! files, and negative answers carry NSEC or NSEC3 proofs.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::presentation::from_hex;
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::errors::{failed_signing, invalid_dnssec_key, Result};
+use crate::settings::config::DnssecSettings;
+
+use super::denial::{Chain, Nsec3Params};
+use super::handle_query;
+use super::zone::Zone;
+
+/// The DNSSEC algorithm numbers of the supported keys
+pub const ECDSAP256SHA256: u8 = 13;
+pub const ED25519: u8 = 15;
+
+/// The DNSKEY flags of a zone signing key, a key signing key
+/// also sets the secure entry point bit
+pub const ZONE_KEY: u16 = 256;
+pub const SECURE_ENTRY_POINT: u16 = 1;
+
+/// Signatures start to be valid a bit before they are made, for
+/// resolvers whose clock is late
+const INCEPTION_OFFSET: u32 = 3600;
+
+/// A private key and the DNSKEY record it publishes
+pub struct SigningKey {
+    flags: u16,
+    algorithm: u8,
+    public_key: Vec<u8>,
+    pair: Pair,
+}
+
+enum Pair {
+    EcdsaP256(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+impl SigningKey {
+    /// Loads the PKCS#8 PEM private key at `path`, either an
+    /// ECDSA P-256 or an Ed25519 key
+    ///
+    /// takes: `(&str, u16)` = (path, DNSKEY flags)
+    ///
+    /// returns: `Result<SigningKey>`
+    pub fn load(path: &str, flags: u16) -> Result<Self> {
+        let invalid = |reason: &str| invalid_dnssec_key(path, reason);
+        let text = fs::read_to_string(path).map_err(|err| invalid(&err.to_string()))?;
+        let body: String = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let der = BASE64.decode(body).map_err(|_| invalid("not a PEM file"))?;
+
+        if let Ok(pair) =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &der, &SystemRandom::new())
+        {
+            // the uncompressed point, without its 0x04 prefix
+            return Ok(Self {
+                flags,
+                algorithm: ECDSAP256SHA256,
+                public_key: pair.public_key().as_ref()[1..].to_vec(),
+                pair: Pair::EcdsaP256(pair),
+            });
+        }
+        match Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der) {
+            Ok(pair) => Ok(Self {
+                flags,
+                algorithm: ED25519,
+                public_key: pair.public_key().as_ref().to_vec(),
+                pair: Pair::Ed25519(pair),
+            }),
+            Err(_) => Err(invalid("not an ECDSA P-256 or Ed25519 PKCS#8 key")),
+        }
+    }
+
+    pub fn algorithm(&self) -> u8 {
+        self.algorithm
+    }
+
+    /// The key tag of the DNSKEY record (RFC 4034 appendix B)
+    pub fn key_tag(&self) -> u16 {
+        let mut rdata = vec![];
+        rdata.extend(self.flags.to_be_bytes());
+        rdata.extend([3, self.algorithm]);
+        rdata.extend(&self.public_key);
+
+        let mut sum: u32 = 0;
+        for (i, byte) in rdata.iter().enumerate() {
+            sum += match i % 2 {
+                0 => (*byte as u32) << 8,
+                _ => *byte as u32,
+            };
+        }
+        sum += sum >> 16;
+        sum as u16
+    }
+
+    pub fn dnskey(&self, origin: &DnsName, ttl: u32) -> DnsRecord {
+        let data = RecordData::DNSKEY {
+            flags: self.flags,
+            protocol: 3,
+            algorithm: self.algorithm,
+            public_key: self.public_key.clone(),
+        };
+        DnsRecord::with_data(origin.clone(), ttl, data)
+    }
+
+    /// The DS record of the key, with a SHA-256 digest, for the
+    /// parent zone
+    pub fn ds(&self, origin: &DnsName, ttl: u32) -> Result<DnsRecord> {
+        let dnskey = self.dnskey(origin, ttl);
+        let mut data = origin.to_lowercase().to_wire();
+        data.extend_from_slice(rdata(&wire(&dnskey)?, origin));
+        let data = RecordData::DS {
+            key_tag: self.key_tag(),
+            algorithm: self.algorithm,
+            digest_type: 2,
+            digest: digest(&SHA256, &data).as_ref().to_vec(),
+        };
+        Ok(DnsRecord::with_data(origin.clone(), ttl, data))
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.pair {
+            Pair::EcdsaP256(ref pair) => pair
+                .sign(&SystemRandom::new(), data)
+                .map(|signature| signature.as_ref().to_vec())
+                .map_err(|_| failed_signing("ECDSA")),
+            Pair::Ed25519(ref pair) => Ok(pair.sign(data).as_ref().to_vec()),
+        }
+    }
+}
+
+/// Signs the answers of a zone: the DNSKEY RRset with the key
+/// signing key, the other RRsets with the zone signing key.
+/// Signatures are cached until a quarter of their validity is
+/// left, or their RRset changes.
+pub struct ZoneSigner {
+    ksk: SigningKey,
+    zsk: SigningKey,
+    nsec3: Option<Nsec3Params>,
+    validity: u32,
+    signatures: Mutex<HashMap<(DnsName, QueryType), Signature>>,
+    /// the chain of the latest version of the zone
+    chain: Mutex<Option<Arc<Chain>>>,
+}
+
+/// A cached signature, along with the RRset it covers
+struct Signature {
+    rrset: Vec<DnsRecord>,
+    rrsig: DnsRecord,
+}
+
+impl ZoneSigner {
+    /// Loads the keys named by `settings`
+    ///
+    /// takes: `&DnssecSettings`
+    ///
+    /// returns: `Result<ZoneSigner>`
+    pub fn new(settings: &DnssecSettings) -> Result<Self> {
+        let nsec3 = match settings.nsec3 {
+            Some(ref nsec3) => Some(Nsec3Params {
+                salt: from_hex(&nsec3.salt)?,
+                iterations: nsec3.iterations,
+            }),
+            None => None,
+        };
+        Ok(Self {
+            ksk: SigningKey::load(&settings.ksk, ZONE_KEY | SECURE_ENTRY_POINT)?,
+            zsk: SigningKey::load(&settings.zsk, ZONE_KEY)?,
+            nsec3,
+            validity: settings.validity,
+            signatures: Mutex::new(HashMap::new()),
+            chain: Mutex::new(None),
+        })
+    }
+
+    pub fn ksk(&self) -> &SigningKey {
+        &self.ksk
+    }
+
+    /// The DNSKEY RRset of the zone, which shares the TTL of
+    /// the SOA
+    pub fn dnskeys(&self, zone: &Zone) -> Vec<DnsRecord> {
+        [&self.ksk, &self.zsk]
+            .into_iter()
+            .map(|key| {
+                let mut dnskey = key.dnskey(zone.origin(), zone.soa().ttl());
+                dnskey.class = zone.class();
+                dnskey
+            })
+            .collect()
+    }
+
+    /// Answers `request` from `zone`, the apex also holds the
+    /// DNSKEY RRset and the NSEC3PARAM record. The answer is
+    /// signed when the request sets the DO bit (RFC 3225), one
+    /// that fails to be gets SERVFAIL.
+    ///
+    /// takes: `(&DnsMessage, &Zone)`
+    ///
+    /// returns: `DnsMessage`
+    pub fn handle_query(&self, request: &DnsMessage, zone: &Zone) -> DnsMessage {
+        let apex = request
+            .questions
+            .first()
+            .filter(|question| question.qname == *zone.origin())
+            .map(|question| question.qtype);
+        let mut response = match (apex, &self.nsec3) {
+            (Some(QueryType::DNSKEY), _) => {
+                let mut response = DnsMessage::response_to(request).authoritative(true).build();
+                response.answers = self.dnskeys(zone);
+                response
+            }
+            (Some(QueryType::NSEC3PARAM), Some(nsec3)) => {
+                let data = RecordData::NSEC3PARAM {
+                    hash_algorithm: 1,
+                    flags: 0,
+                    iterations: nsec3.iterations,
+                    salt: nsec3.salt.clone(),
+                };
+                let mut record = DnsRecord::with_data(zone.origin().clone(), 0, data);
+                record.class = zone.class();
+                DnsMessage::response_to(request)
+                    .authoritative(true)
+                    .answer(record)
+                    .build()
+            }
+            _ => handle_query::handle_query(request, zone),
+        };
+
+        let dnssec_ok = request.edns().is_some_and(|edns| edns.dnssec_ok);
+        if !dnssec_ok || request.questions.is_empty() {
+            return response;
+        }
+        if let Err(err) = self.sign_response(&mut response, zone) {
+            println!("Failed to sign an answer of {}: {}", zone.origin(), err);
+            return DnsMessage::response_to(request)
+                .rescode(ResultCode::SERVFAIL)
+                .build();
+        }
+        response
+    }
+
+    /// Signs the answer and authority sections of `response`,
+    /// adding the proofs of denial its answer needs
+    fn sign_response(&self, response: &mut DnsMessage, zone: &Zone) -> Result<()> {
+        let chain = self.chain(zone);
+        let mut sname = response.questions[0].qname.clone();
+        let mut proofs: Vec<DnsRecord> = Vec::new();
+
+        for rrset in rrsets(std::mem::take(&mut response.answers)) {
+            let owner = rrset[0].domain.clone();
+            // an RRset synthesized from a wildcard is signed at
+            // the wildcard, and proves qname does not exist
+            let signer = match zone.exists(&owner) {
+                true => owner.clone(),
+                false => {
+                    proofs.extend(chain.wildcard_answer(zone, &owner));
+                    zone.closest_encloser(&owner).prepend(b"*")?
+                }
+            };
+            if let RecordData::CNAME { ref host } = rrset[0].data {
+                if owner == sname {
+                    sname = host.clone();
+                }
+            }
+            let mut rrsig = self.rrsig(zone, &signer, &rrset)?;
+            rrsig.domain = owner;
+            response.answers.extend(rrset);
+            response.answers.push(rrsig);
+        }
+
+        let cut = response
+            .authorities
+            .iter()
+            .find(|rec| rec.qtype() == QueryType::NS && zone.is_cut(&rec.domain))
+            .map(|rec| rec.domain.clone());
+        let is_negative = response
+            .authorities
+            .iter()
+            .any(|rec| rec.qtype() == QueryType::SOA);
+        if let Some(cut) = cut {
+            // the DS RRset of a secure delegation, or the proof
+            // that the cut holds none
+            let ds: Vec<DnsRecord> = zone
+                .records_at(&cut)
+                .iter()
+                .filter(|rec| rec.qtype() == QueryType::DS)
+                .cloned()
+                .collect();
+            match ds.is_empty() {
+                true => proofs.extend(chain.nodata(zone, &cut)),
+                false => proofs.extend(ds),
+            }
+        } else if response.header.rescode == ResultCode::NXDOMAIN {
+            proofs.extend(chain.nxdomain(zone, &sname)?);
+        } else if is_negative && zone.exists(&sname) {
+            proofs.extend(chain.nodata(zone, &sname));
+        } else if is_negative {
+            proofs.extend(chain.wildcard_nodata(zone, &sname)?);
+        }
+
+        let mut authorities = std::mem::take(&mut response.authorities);
+        authorities.extend(proofs);
+        for rrset in rrsets(authorities) {
+            // the NS RRset of a delegation belongs to the child
+            let is_delegation = rrset[0].qtype() == QueryType::NS && zone.is_cut(&rrset[0].domain);
+            let rrsig = match is_delegation {
+                true => None,
+                false => Some(self.rrsig(zone, &rrset[0].domain, &rrset)?),
+            };
+            response.authorities.extend(rrset);
+            response.authorities.extend(rrsig);
+        }
+        Ok(())
+    }
+
+    /// The RRSIG record of `rrset`, signed at `owner`, from the
+    /// cache while it is fresh
+    fn rrsig(&self, zone: &Zone, owner: &DnsName, rrset: &[DnsRecord]) -> Result<DnsRecord> {
+        let rrset: Vec<DnsRecord> = rrset
+            .iter()
+            .map(|rec| {
+                let mut rec = rec.clone();
+                rec.domain = owner.clone();
+                rec
+            })
+            .collect();
+        let qtype = rrset[0].qtype();
+        let now = now();
+
+        let mut signatures = self.signatures.lock().unwrap();
+        let key = (owner.to_lowercase(), qtype);
+        if let Some(signature) = signatures.get(&key) {
+            let expiration = match signature.rrsig.data {
+                RecordData::RRSIG { expiration, .. } => expiration,
+                _ => 0,
+            };
+            if signature.rrset == rrset && expiration.saturating_sub(now) > self.validity / 4 {
+                return Ok(signature.rrsig.clone());
+            }
+        }
+
+        let key_of = match qtype {
+            QueryType::DNSKEY => &self.ksk,
+            _ => &self.zsk,
+        };
+        let ttl = rrset[0].ttl();
+        let is_wildcard = owner.labels().next() == Some(b"*".as_slice());
+        let mut data = RecordData::RRSIG {
+            type_covered: qtype,
+            algorithm: key_of.algorithm,
+            labels: (owner.label_count() - is_wildcard as usize) as u8,
+            original_ttl: ttl,
+            expiration: now.wrapping_add(self.validity),
+            inception: now.wrapping_sub(INCEPTION_OFFSET),
+            key_tag: key_of.key_tag(),
+            signer: zone.origin().to_lowercase(),
+            signature: Vec::new(),
+        };
+        let mut rrsig = DnsRecord::with_data(owner.clone(), ttl, data.clone());
+        rrsig.class = zone.class();
+        let signed = key_of.sign(&signed_data(&rrsig, &rrset)?)?;
+        if let RecordData::RRSIG {
+            ref mut signature, ..
+        } = data
+        {
+            *signature = signed;
+        }
+        rrsig.data = data;
+
+        signatures.insert(
+            key,
+            Signature {
+                rrset,
+                rrsig: rrsig.clone(),
+            },
+        );
+        Ok(rrsig)
+    }
+
+    /// The NSEC or NSEC3 chain of the current version of `zone`
+    fn chain(&self, zone: &Zone) -> Arc<Chain> {
+        let mut chain = self.chain.lock().unwrap();
+        match *chain {
+            Some(ref chain) if chain.serial() == zone.serial() => chain.clone(),
+            _ => {
+                let built = Arc::new(Chain::new(zone, self.nsec3.as_ref()));
+                *chain = Some(built.clone());
+                built
+            }
+        }
+    }
+}
+
+/// Groups `records` into RRsets, in the order they first appear,
+/// dropping duplicates
+fn rrsets(records: Vec<DnsRecord>) -> Vec<Vec<DnsRecord>> {
+    let mut rrsets: Vec<Vec<DnsRecord>> = Vec::new();
+    for record in records {
+        let rrset = rrsets
+            .iter_mut()
+            .find(|rrset| rrset[0].domain == record.domain && rrset[0].qtype() == record.qtype());
+        match rrset {
+            Some(rrset) if rrset.contains(&record) => {}
+            Some(rrset) => rrset.push(record),
+            None => rrsets.push(vec![record]),
+        }
+    }
+    rrsets
+}
+
+/// The data an RRSIG record signs (RFC 4034 section 3.1.8.1):
+/// its own RDATA without the signature, followed by the records
+/// of the RRset in canonical form and order
+fn signed_data(rrsig: &DnsRecord, rrset: &[DnsRecord]) -> Result<Vec<u8>> {
+    let mut data = rdata(&wire(rrsig)?, &rrsig.domain).to_vec();
+
+    let ttl = rrset[0].ttl();
+    let mut records = rrset
+        .iter()
+        .map(|rec| wire(&canonical(rec, ttl)))
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+    let owner = &rrset[0].domain;
+    records.sort_by(|a, b| rdata(a, owner).cmp(rdata(b, owner)));
+    records.dedup();
+    for record in records {
+        data.extend(record);
+    }
+    Ok(data)
+}
+
+/// `record` in canonical form (RFC 4034 section 6.2): names in
+/// lowercase and the TTL of the RRset
+fn canonical(record: &DnsRecord, ttl: u32) -> DnsRecord {
+    let mut data = record.data.clone();
+    match &mut data {
+        RecordData::NS { host }
+        | RecordData::CNAME { host }
+        | RecordData::PTR { host }
+        | RecordData::MX { host, .. }
+        | RecordData::SRV { target: host, .. }
+        | RecordData::NAPTR {
+            replacement: host, ..
+        }
+        | RecordData::RRSIG { signer: host, .. } => *host = host.to_lowercase(),
+        RecordData::SOA { mname, rname, .. } => {
+            *mname = mname.to_lowercase();
+            *rname = rname.to_lowercase();
+        }
+        _ => {}
+    }
+    let mut canonical = DnsRecord::with_data(record.domain.to_lowercase(), ttl, data);
+    canonical.class = record.class;
+    canonical
+}
+
+/// `record` on the wire, names are never compressed
+fn wire(record: &DnsRecord) -> Result<Vec<u8>> {
+    let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+    record.clone().write(&mut buffer)?;
+    Ok(buffer.get_range(0, buffer.pos())?.to_vec())
+}
+
+/// The RDATA of a record on the wire owned by `owner`, past its
+/// name, type, class, TTL and length
+fn rdata<'a>(wire: &'a [u8], owner: &DnsName) -> &'a [u8] {
+    &wire[owner.wire_len() + 10..]
+}
+
+/// Seconds since the epoch, in the serial number arithmetic of
+/// RRSIG timestamps
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as u32)
+        .unwrap_or_default()
+}
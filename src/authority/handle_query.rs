@@ -0,0 +1,83 @@
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::DnsMessage;
+
+use super::zone::{Lookup, Zone};
+
+/// Longest chain of CNAME records followed inside a zone
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// Answers `request` from `zone` with the AA bit set. Aliases
+/// are followed while they stay in the zone, negative answers
+/// carry the SOA in the authority section and delegations are
+/// referred to with their glue.
+///
+/// takes: `(&DnsMessage, &Zone)`
+///
+/// returns: `DnsMessage`
+pub fn handle_query(request: &DnsMessage, zone: &Zone) -> DnsMessage {
+    let mut message = DnsMessage::response_to(request).authoritative(true).build();
+    let Some(question) = request.questions.first() else {
+        message.header.rescode = ResultCode::FORMERR;
+        return message;
+    };
+
+    let mut qname = question.qname.clone();
+    for _ in 0..MAX_CNAME_CHAIN {
+        match zone.lookup(&qname, question.qtype) {
+            Lookup::Answer(records) => {
+                message.answers.extend(records);
+                break;
+            }
+            Lookup::Alias(record) => {
+                let target = match record.data {
+                    RecordData::CNAME { ref host } => host.clone(),
+                    _ => break,
+                };
+                message.answers.push(record);
+                if !target.is_subdomain_of(zone.origin()) {
+                    break;
+                }
+                qname = target;
+            }
+            Lookup::Referral(ns) => {
+                // the referral itself is not authoritative
+                message.header.authoritative_answer = !message.answers.is_empty();
+                message.resources.extend(glue(zone, &ns));
+                message.authorities.extend(ns);
+                return message;
+            }
+            Lookup::NoData => {
+                message.authorities.push(zone.negative_soa());
+                break;
+            }
+            Lookup::NxDomain => {
+                message.header.rescode = ResultCode::NXDOMAIN;
+                message.authorities.push(zone.negative_soa());
+                break;
+            }
+        }
+    }
+
+    let additionals = glue(zone, &message.answers);
+    message.resources.extend(additionals);
+    message
+}
+
+/// The addresses the zone holds for the names `records` point
+/// to, for the additional section (RFC 1035 section 3.3)
+fn glue(zone: &Zone, records: &[DnsRecord]) -> Vec<DnsRecord> {
+    let mut glue: Vec<DnsRecord> = Vec::new();
+    for record in records {
+        let target = match record.data {
+            RecordData::NS { ref host } | RecordData::MX { ref host, .. } => host,
+            RecordData::SRV { ref target, .. } => target,
+            _ => continue,
+        };
+        if glue.iter().any(|rec| rec.domain == *target) {
+            continue;
+        }
+        glue.extend(zone.addresses(target));
+    }
+    glue
+}
@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::QueryType;
+
+use super::zone::Zone;
+
+/// Most versions of a zone the journal keeps the changes of,
+/// older clients get a full transfer
+const MAX_JOURNAL_LEN: usize = 64;
+
+/// The changes turning a version of a zone into the next one,
+/// in the shape IXFR sends them (RFC 1995 section 4)
+#[derive(Clone, Debug)]
+pub struct Diff {
+    pub old_soa: DnsRecord,
+    pub removed: Vec<DnsRecord>,
+    pub new_soa: DnsRecord,
+    pub added: Vec<DnsRecord>,
+}
+
+/// The most recent changes of a zone, oldest first. Each
+/// change starts at the serial the previous one ends at.
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    diffs: VecDeque<Diff>,
+}
+
+impl Diff {
+    /// The changes from `old` to `new`, the SOA records aside.
+    /// A record whose TTL changed is removed and added again.
+    ///
+    /// takes: `(&Zone, &Zone)` = (old, new)
+    ///
+    /// returns: `Diff`
+    pub fn between(old: &Zone, new: &Zone) -> Self {
+        let changes = |from: &Zone, to: &Zone| -> Vec<DnsRecord> {
+            from.records()
+                .filter(|rec| rec.qtype() != QueryType::SOA)
+                .filter(|rec| !to.records_at(&rec.domain).contains(rec))
+                .cloned()
+                .collect()
+        };
+        Self {
+            old_soa: old.soa().clone(),
+            removed: changes(old, new),
+            new_soa: new.soa().clone(),
+            added: changes(new, old),
+        }
+    }
+
+    pub fn old_serial(&self) -> u32 {
+        serial_of(&self.old_soa)
+    }
+
+    pub fn new_serial(&self) -> u32 {
+        serial_of(&self.new_soa)
+    }
+
+    /// The records of the change in IXFR order: the old SOA,
+    /// the removed records, the new SOA and the added records
+    pub fn records(&self) -> impl Iterator<Item = &DnsRecord> {
+        std::iter::once(&self.old_soa)
+            .chain(&self.removed)
+            .chain(std::iter::once(&self.new_soa))
+            .chain(&self.added)
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `diff`, the history is restarted when `diff`
+    /// does not follow the last change, and the oldest change
+    /// goes once the journal is full.
+    pub fn push(&mut self, diff: Diff) {
+        if self
+            .diffs
+            .back()
+            .is_some_and(|last| last.new_serial() != diff.old_serial())
+        {
+            self.diffs.clear();
+        }
+        self.diffs.push_back(diff);
+        if self.diffs.len() > MAX_JOURNAL_LEN {
+            self.diffs.pop_front();
+        }
+    }
+
+    /// The changes from version `serial` to the latest one
+    ///
+    /// returns: `Option<Vec<&Diff>>`, `None` when the history
+    /// does not go back to `serial`
+    pub fn since(&self, serial: u32) -> Option<Vec<&Diff>> {
+        let start = self
+            .diffs
+            .iter()
+            .position(|diff| diff.old_serial() == serial)?;
+        Some(self.diffs.range(start..).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.diffs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diffs.is_empty()
+    }
+}
+
+fn serial_of(soa: &DnsRecord) -> u32 {
+    match soa.data {
+        RecordData::SOA { serial, .. } => serial,
+        _ => 0,
+    }
+}
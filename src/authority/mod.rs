@@ -0,0 +1,10 @@
+pub mod catalog;
+pub mod denial;
+pub mod dnssec;
+pub mod handle_query;
+pub mod journal;
+pub mod secondary;
+pub mod transfer;
+pub mod update;
+pub mod zone;
+pub mod zone_file;
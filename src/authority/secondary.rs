@@ -0,0 +1,275 @@
+//! Secondary zones (RFC 1034 section 4.3.5): copies of zones
+//! mastered elsewhere, transferred with IXFR or AXFR when the
+//! SOA refresh timer fires, on NOTIFY (RFC 1996), and dropped
+//! once they expire without a successful refresh.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::tsig::{self, Signer, TsigKey};
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::dns_server::{tcp, DnsServer};
+use crate::errors::{failed_transfer, Result};
+use crate::settings::config::ZoneSettings;
+
+use super::catalog::Catalog;
+use super::zone::{serial_lt, Zone};
+use super::zone_file;
+
+/// Wait before retrying a zone that was never transferred
+const INITIAL_RETRY: Duration = Duration::from_secs(60);
+/// Longest wait for the connection to, or a message of, the
+/// primary
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Spawns the refresh task of every secondary zone of `server`
+pub fn spawn(server: &Arc<DnsServer>) {
+    for settings in server.zones().iter().filter(|zone| zone.primary.is_some()) {
+        tokio::spawn(run(server.clone(), settings.clone()));
+    }
+}
+
+/// Keeps the zone of `settings` up to date: it is refreshed
+/// right away, then every SOA refresh interval or when NOTIFY
+/// comes, and every retry interval while refreshing fails. The
+/// zone is removed from the catalog once it expires.
+pub async fn run(server: Arc<DnsServer>, settings: ZoneSettings) {
+    let origin = &settings.origin;
+    let notified = server.notifier(origin);
+    let key = settings
+        .key
+        .as_ref()
+        .and_then(|name| server.keyring().get(name));
+    // a zone loaded from its file is as fresh as the file
+    let mut refreshed_at = server
+        .catalog
+        .get(origin)
+        .and_then(|_| file_age(Path::new(&settings.file)))
+        .and_then(|age| Instant::now().checked_sub(age));
+
+    loop {
+        let wait = match refresh(&server.catalog, &settings, key).await {
+            Ok(_) => {
+                refreshed_at = Some(Instant::now());
+                timers(&server.catalog, origin).map_or(INITIAL_RETRY, |timers| timers.refresh)
+            }
+            Err(err) => {
+                println!("Failed to refresh {}: {}", origin, err);
+                let timers = timers(&server.catalog, origin);
+                if let (Some(timers), Some(at)) = (timers, refreshed_at) {
+                    if at.elapsed() >= timers.expire {
+                        println!("Zone {} expired", origin);
+                        server.catalog.remove(origin);
+                        refreshed_at = None;
+                    }
+                }
+                timers.map_or(INITIAL_RETRY, |timers| timers.retry)
+            }
+        };
+
+        match &notified {
+            Some(notified) => {
+                tokio::select! {
+                    _ = sleep(wait) => {}
+                    _ = notified.notified() => println!("Refreshing {} on NOTIFY", origin),
+                }
+            }
+            None => sleep(wait).await,
+        }
+    }
+}
+
+/// Brings the zone of `settings` up to date with its primary,
+/// a new version is saved to the file of the zone.
+///
+/// takes: `(&Catalog, &ZoneSettings, Option<&TsigKey>)`, the key
+/// signs the transfer
+///
+/// returns: `Result<bool>`, whether a new version came in
+pub async fn refresh(
+    catalog: &Catalog,
+    settings: &ZoneSettings,
+    key: Option<&TsigKey>,
+) -> Result<bool> {
+    let Some(primary) = settings.primary else {
+        return Ok(false);
+    };
+    let current = catalog.get(&settings.origin);
+    let Some(zone) = transfer(primary, &settings.origin, current.as_deref(), key).await? else {
+        return Ok(false);
+    };
+    println!("Transferred {} serial {}", zone.origin(), zone.serial());
+    zone_file::write(Path::new(&settings.file), &zone)?;
+    catalog.update(zone);
+    Ok(true)
+}
+
+/// Transfers `origin` from `primary` over TCP, incrementally
+/// from `current` when there is one. With a key, the request is
+/// signed and every message of the response must be.
+///
+/// takes: `(SocketAddr, &DnsName, Option<&Zone>, Option<&TsigKey>)`
+/// = (primary, origin, current, key)
+///
+/// returns: `Result<Option<Zone>>`, `None` when `current` is
+/// up to date
+pub async fn transfer(
+    primary: SocketAddr,
+    origin: &DnsName,
+    current: Option<&Zone>,
+    key: Option<&TsigKey>,
+) -> Result<Option<Zone>> {
+    let failed = |reason: &str| failed_transfer(&origin.to_string(), reason);
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos() as u16);
+    let mut request = match current {
+        Some(zone) => DnsMessage::query(origin, QueryType::IXFR).authority(zone.soa().clone()),
+        None => DnsMessage::query(origin, QueryType::AXFR),
+    }
+    .id(id)
+    .build();
+    let mut signer = key.cloned().map(Signer::new);
+    if let Some(signer) = &mut signer {
+        signer.sign(&mut request)?;
+    }
+
+    let mut stream = timeout(TRANSFER_TIMEOUT, TcpStream::connect(primary))
+        .await
+        .map_err(|_| failed("timed out"))??;
+    tcp::write_message(&mut stream, request).await?;
+
+    let mut records: Vec<DnsRecord> = Vec::new();
+    while !is_complete(&records, current) {
+        let message = timeout(TRANSFER_TIMEOUT, tcp::read_message(&mut stream))
+            .await
+            .map_err(|_| failed("timed out"))??;
+        if message.header.id != id {
+            return Err(failed("unexpected message id"));
+        }
+        if let Some(signer) = &mut signer {
+            signer
+                .verify(&message)
+                .map_err(|rescode| failed(&format!("TSIG {}", tsig::error_name(rescode))))?;
+        }
+        if message.header.rescode != ResultCode::NOERROR {
+            return Err(failed(&message.header.rescode.to_string()));
+        }
+        if message.answers.is_empty() {
+            return Err(failed("empty message"));
+        }
+        records.extend(message.answers);
+    }
+    apply(origin, records, current)
+}
+
+/// Whether `records` hold a whole transfer: a single SOA not
+/// newer than `current`, the zone between two SOA records, or
+/// the changes between SOA records (RFC 1995 section 4) ending
+/// with the new SOA
+fn is_complete(records: &[DnsRecord], current: Option<&Zone>) -> bool {
+    let Some(serial) = records.first().and_then(serial_of) else {
+        return false;
+    };
+    let soa_count = records[1..].iter().filter_map(serial_of).count();
+    match records {
+        [_] => current.is_some_and(|zone| !serial_lt(zone.serial(), serial)),
+        [_, .., last] => serial_of(last) == Some(serial) && soa_count % 2 == 1,
+        [] => false,
+    }
+}
+
+/// Builds the new version of the zone out of a complete transfer
+fn apply(
+    origin: &DnsName,
+    mut records: Vec<DnsRecord>,
+    current: Option<&Zone>,
+) -> Result<Option<Zone>> {
+    if records.len() == 1 {
+        return Ok(None);
+    }
+    // the closing SOA
+    records.pop();
+    let incremental = records.get(1).and_then(serial_of).is_some();
+    let Some(current) = current.filter(|_| incremental) else {
+        return Zone::new(origin.clone(), records).map(Some);
+    };
+
+    // each change is the old SOA, the removed records, the new
+    // SOA and the added records
+    let mut zone: Vec<DnsRecord> = current
+        .records()
+        .filter(|rec| rec.qtype() != QueryType::SOA)
+        .cloned()
+        .collect();
+    let mut serial = current.serial();
+    let mut soa = None;
+    let mut removing = false;
+    for record in records.into_iter().skip(1) {
+        match serial_of(&record) {
+            Some(old) if !removing => {
+                if old != serial {
+                    return Err(failed_transfer(
+                        &origin.to_string(),
+                        "changes out of sequence",
+                    ));
+                }
+                removing = true;
+            }
+            Some(new) => {
+                serial = new;
+                soa = Some(record);
+                removing = false;
+            }
+            None if removing => zone.retain(|rec| *rec != record),
+            None if !zone.contains(&record) => zone.push(record),
+            None => {}
+        }
+    }
+    zone.extend(soa);
+    Zone::new(origin.clone(), zone).map(Some)
+}
+
+/// The SOA timers of a zone
+#[derive(Clone, Copy)]
+struct Timers {
+    refresh: Duration,
+    retry: Duration,
+    expire: Duration,
+}
+
+fn timers(catalog: &Catalog, origin: &DnsName) -> Option<Timers> {
+    let zone = catalog.get(origin)?;
+    match zone.soa().data {
+        RecordData::SOA {
+            refresh,
+            retry,
+            expire,
+            ..
+        } => Some(Timers {
+            refresh: Duration::from_secs(refresh.into()),
+            retry: Duration::from_secs(retry.into()),
+            expire: Duration::from_secs(expire.into()),
+        }),
+        _ => None,
+    }
+}
+
+fn serial_of(record: &DnsRecord) -> Option<u32> {
+    match record.data {
+        RecordData::SOA { serial, .. } => Some(serial),
+        _ => None,
+    }
+}
+
+fn file_age(path: &Path) -> Option<Duration> {
+    path.metadata().ok()?.modified().ok()?.elapsed().ok()
+}
@@ -0,0 +1,109 @@
+//! Outgoing zone transfers, AXFR (RFC 5936) and IXFR (RFC 1995).
+//!
+//! A transfer is a stream of messages over TCP, the records are
+//! split over as many messages as needed. Only the first one
+//! holds the question.
+
+use std::iter;
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::errors::Result;
+
+use super::zone::{serial_lt, Zone};
+
+/// Largest message of a transfer, well below what TCP allows
+/// so that a message is not too large to be buffered
+pub const MAX_TRANSFER_MESSAGE: usize = 16384;
+
+/// The full transfer of `zone`: its SOA, every other record and
+/// the SOA again
+///
+/// takes: `(&DnsMessage, &Zone)`
+///
+/// returns: `Result<Vec<DnsMessage>>`
+pub fn axfr(request: &DnsMessage, zone: &Zone) -> Result<Vec<DnsMessage>> {
+    let soa = zone.soa();
+    let records = iter::once(soa)
+        .chain(zone.records().filter(|rec| rec.qtype() != QueryType::SOA))
+        .chain(iter::once(soa));
+    split(request, records)
+}
+
+/// The incremental transfer of `zone` from the version whose
+/// SOA is in the authority section of `request`. A client up
+/// to date gets the SOA alone, a client older than the journal
+/// gets the whole zone as with AXFR.
+///
+/// takes: `(&DnsMessage, &Zone)`
+///
+/// returns: `Result<Vec<DnsMessage>>`
+pub fn ixfr(request: &DnsMessage, zone: &Zone) -> Result<Vec<DnsMessage>> {
+    let serial = request.authorities.iter().find_map(|rec| match rec.data {
+        RecordData::SOA { serial, .. } => Some(serial),
+        _ => None,
+    });
+    let Some(serial) = serial else {
+        let message = DnsMessage::response_to(request)
+            .rescode(ResultCode::FORMERR)
+            .build();
+        return Ok(vec![message]);
+    };
+
+    let soa = zone.soa();
+    if !serial_lt(serial, zone.serial()) {
+        return split(request, iter::once(soa));
+    }
+    match zone.journal().since(serial) {
+        Some(diffs) => {
+            let changes = diffs.into_iter().flat_map(|diff| diff.records());
+            split(
+                request,
+                iter::once(soa).chain(changes).chain(iter::once(soa)),
+            )
+        }
+        None => axfr(request, zone),
+    }
+}
+
+/// Packs `records` in the answer section of as few messages as
+/// `MAX_TRANSFER_MESSAGE` allows
+fn split<'a>(
+    request: &DnsMessage,
+    records: impl Iterator<Item = &'a DnsRecord>,
+) -> Result<Vec<DnsMessage>> {
+    let first = DnsMessage::response_to(request).authoritative(true).build();
+    let mut next = first.clone();
+    next.questions.clear();
+    let first_len = wire_len(&first)?;
+    let next_len = wire_len(&next)?;
+
+    let mut scratch = PacketBuffer::with_size(u16::MAX as usize);
+    let mut messages = Vec::new();
+    let mut message = first;
+    let mut len = first_len;
+    for record in records {
+        let mut record = record.clone();
+        scratch.seek(0)?;
+        let record_len = record.write(&mut scratch)?;
+        if len + record_len > MAX_TRANSFER_MESSAGE && !message.answers.is_empty() {
+            messages.push(std::mem::replace(&mut message, next.clone()));
+            len = next_len;
+        }
+        message.answers.push(record);
+        len += record_len;
+    }
+    messages.push(message);
+
+    for message in &mut messages {
+        message.header.answers = message.answers.len() as u16;
+    }
+    Ok(messages)
+}
+
+/// Size of `message` on the wire
+fn wire_len(message: &DnsMessage) -> Result<usize> {
+    Ok(message.clone().into_buf_with_size(u16::MAX as usize)?.pos())
+}
@@ -0,0 +1,251 @@
+//! Dynamic updates (RFC 2136) of the zones served with authority.
+//!
+//! The prerequisites are checked and the updates applied to a
+//! copy of the zone, which replaces the zone only once all of
+//! them went through: an update is applied whole or not at all.
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::{DnsClass, DnsMessage, QueryType};
+
+use super::zone::{serial_lt, Zone};
+
+/// Applies the UPDATE `request` to `zone`, whose origin is the
+/// name of the zone section
+///
+/// takes: `(&DnsMessage, &Zone)`
+///
+/// returns: `Result<Option<Zone>, ResultCode>`, the new version
+/// of the zone with its serial bumped, `None` when nothing
+/// changed, or the response code of a failed update
+pub fn apply(request: &DnsMessage, zone: &Zone) -> Result<Option<Zone>, ResultCode> {
+    let mut records: Vec<DnsRecord> = zone.records().cloned().collect();
+    check_prerequisites(&request.answers, zone, &records)?;
+    prescan(&request.authorities, zone)?;
+
+    let mut changed = false;
+    for update in &request.authorities {
+        changed |= update_record(update, zone, &mut records);
+    }
+    if !changed {
+        return Ok(None);
+    }
+
+    // RFC 2136 section 3.6, the serial grows with each update
+    // unless the update set a newer SOA itself
+    let soa = records
+        .iter_mut()
+        .find(|rec| rec.qtype() == QueryType::SOA)
+        .ok_or(ResultCode::SERVFAIL)?;
+    if let RecordData::SOA { ref mut serial, .. } = soa.data {
+        if !serial_lt(zone.serial(), *serial) {
+            *serial = zone.serial().wrapping_add(1);
+        }
+    }
+
+    Zone::new(zone.origin().clone(), records)
+        .map(Some)
+        .map_err(|err| {
+            println!("Refused update of {}: {}", zone.origin(), err);
+            ResultCode::REFUSED
+        })
+}
+
+/// The prerequisite section (RFC 2136 section 3.2)
+fn check_prerequisites(
+    prerequisites: &[DnsRecord],
+    zone: &Zone,
+    records: &[DnsRecord],
+) -> Result<(), ResultCode> {
+    // RRsets that must exist with these exact records
+    let mut expected: Vec<&DnsRecord> = Vec::new();
+    for rr in prerequisites {
+        if rr.ttl() != 0 {
+            return Err(ResultCode::FORMERR);
+        }
+        if !rr.domain.is_subdomain_of(zone.origin()) {
+            return Err(ResultCode::NOTZONE);
+        }
+
+        let in_use = records.iter().any(|rec| rec.domain == rr.domain);
+        let rrset_exists = records.iter().any(|rec| is_of_rrset(rec, rr));
+        match rr.class {
+            DnsClass::ANY | DnsClass::NONE if !has_no_data(rr) => return Err(ResultCode::FORMERR),
+            DnsClass::ANY if rr.qtype() == QueryType::ANY && !in_use => {
+                return Err(ResultCode::NXDOMAIN)
+            }
+            DnsClass::ANY if rr.qtype() != QueryType::ANY && !rrset_exists => {
+                return Err(ResultCode::NXRRSET)
+            }
+            DnsClass::NONE if rr.qtype() == QueryType::ANY && in_use => {
+                return Err(ResultCode::YXDOMAIN)
+            }
+            DnsClass::NONE if rr.qtype() != QueryType::ANY && rrset_exists => {
+                return Err(ResultCode::YXRRSET)
+            }
+            DnsClass::ANY | DnsClass::NONE => {}
+            class if class == zone.class() => expected.push(rr),
+            _ => return Err(ResultCode::FORMERR),
+        }
+    }
+
+    // value dependent prerequisites, the RRset must hold the
+    // given records and no other
+    for rr in &expected {
+        let rrset = records.iter().filter(|rec| is_of_rrset(rec, rr));
+        let wanted = expected.iter().filter(|other| is_of_rrset(other, rr));
+        let same = rrset
+            .clone()
+            .all(|rec| wanted.clone().any(|w| w.data == rec.data))
+            && wanted
+                .clone()
+                .all(|w| rrset.clone().any(|rec| w.data == rec.data));
+        if !same {
+            return Err(ResultCode::NXRRSET);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the update section before any change is made (RFC
+/// 2136 section 3.4.1)
+fn prescan(updates: &[DnsRecord], zone: &Zone) -> Result<(), ResultCode> {
+    for rr in updates {
+        if !rr.domain.is_subdomain_of(zone.origin()) {
+            return Err(ResultCode::NOTZONE);
+        }
+        let is_meta = matches!(
+            rr.qtype(),
+            QueryType::ANY | QueryType::AXFR | QueryType::IXFR | QueryType::OPT
+        );
+        let valid = match rr.class {
+            class if class == zone.class() => !is_meta && !has_no_data(rr),
+            DnsClass::ANY => {
+                rr.ttl() == 0
+                    && has_no_data(rr)
+                    && !matches!(rr.qtype(), QueryType::AXFR | QueryType::IXFR)
+            }
+            DnsClass::NONE => rr.ttl() == 0 && !is_meta,
+            _ => false,
+        };
+        if !valid {
+            return Err(ResultCode::FORMERR);
+        }
+    }
+    Ok(())
+}
+
+/// Applies a single update (RFC 2136 section 3.4.2)
+///
+/// returns: `bool`, whether `records` changed
+fn update_record(update: &DnsRecord, zone: &Zone, records: &mut Vec<DnsRecord>) -> bool {
+    let at_apex = update.domain == *zone.origin();
+    let qtype = update.qtype();
+    let before = records.len();
+
+    match update.class {
+        DnsClass::ANY if qtype == QueryType::ANY => records.retain(|rec| {
+            rec.domain != update.domain
+                || (at_apex && matches!(rec.qtype(), QueryType::SOA | QueryType::NS))
+        }),
+        DnsClass::ANY => {
+            if at_apex && matches!(qtype, QueryType::SOA | QueryType::NS) {
+                return false;
+            }
+            records.retain(|rec| !is_of_rrset(rec, update));
+        }
+        DnsClass::NONE => {
+            if qtype == QueryType::SOA {
+                return false;
+            }
+            // the last NS of the apex stays
+            let rrset_len = records
+                .iter()
+                .filter(|rec| is_of_rrset(rec, update))
+                .count();
+            if at_apex && qtype == QueryType::NS && rrset_len == 1 {
+                return false;
+            }
+            records.retain(|rec| !(is_of_rrset(rec, update) && rec.data == update.data));
+        }
+        _ => return add_record(update, at_apex, records),
+    }
+    records.len() != before
+}
+
+/// Adds `update` to the zone: a CNAME and other data do not mix,
+/// the SOA is replaced by a newer one only, and a record already
+/// there gets the TTL of `update`
+fn add_record(update: &DnsRecord, at_apex: bool, records: &mut Vec<DnsRecord>) -> bool {
+    let at_name = || records.iter().filter(|rec| rec.domain == update.domain);
+    let has_cname = at_name().any(|rec| rec.qtype() == QueryType::CNAME);
+    let has_other = at_name().any(|rec| rec.qtype() != QueryType::CNAME);
+
+    match update.qtype() {
+        QueryType::SOA => {
+            if !at_apex {
+                return false;
+            }
+            let Some(soa) = records.iter_mut().find(|rec| rec.qtype() == QueryType::SOA) else {
+                return false;
+            };
+            let newer = match (&soa.data, &update.data) {
+                (RecordData::SOA { serial: old, .. }, RecordData::SOA { serial: new, .. }) => {
+                    serial_lt(*old, *new)
+                }
+                _ => false,
+            };
+            if newer {
+                *soa = update.clone();
+            }
+            newer
+        }
+        QueryType::CNAME if has_other => false,
+        QueryType::CNAME => {
+            records.retain(|rec| !is_of_rrset(rec, update));
+            records.push(update.clone());
+            true
+        }
+        _ if has_cname => false,
+        _ => {
+            let existing = records
+                .iter_mut()
+                .find(|rec| is_of_rrset(rec, update) && rec.data == update.data);
+            match existing {
+                Some(rec) if *rec == *update => false,
+                Some(rec) => {
+                    *rec = update.clone();
+                    true
+                }
+                None => {
+                    records.push(update.clone());
+                    true
+                }
+            }
+        }
+    }
+}
+
+/// Whether `record` belongs to the RRset of the name and the type
+/// of `rr`
+fn is_of_rrset(record: &DnsRecord, rr: &DnsRecord) -> bool {
+    record.domain == rr.domain && record.qtype() == rr.qtype()
+}
+
+/// Whether `rr` stands for a whole RRset or name, with no data
+fn has_no_data(rr: &DnsRecord) -> bool {
+    matches!(rr.data, RecordData::UNKNOWN { ref data, .. } if data.is_empty())
+}
+
+/// The name of the zone an UPDATE is for, from its zone section
+/// (RFC 2136 section 2.3)
+///
+/// returns: `Result<&DnsName, ResultCode>`, FORMERR unless the
+/// section holds a single SOA question
+pub fn zone_name(request: &DnsMessage) -> Result<&DnsName, ResultCode> {
+    match request.questions.as_slice() {
+        [zone] if zone.qtype == QueryType::SOA => Ok(&zone.qname),
+        _ => Err(ResultCode::FORMERR),
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::{DnsClass, QueryType};
+use crate::errors::{invalid_zone, Result};
+
+use super::journal::Journal;
+use super::zone_file;
+
+/// An authoritative zone: the records at and below `origin`,
+/// checked for consistency when the zone is built. Names are
+/// kept in canonical order.
+#[derive(Clone, Debug)]
+pub struct Zone {
+    origin: DnsName,
+    class: DnsClass,
+    names: BTreeMap<DnsName, Vec<DnsRecord>>,
+    /// the changes that led to this version, for IXFR
+    journal: Journal,
+}
+
+/// The outcome of looking a name up in a zone (RFC 1034
+/// section 4.3.2)
+#[derive(Clone, Debug)]
+pub enum Lookup {
+    /// the records of the requested type
+    Answer(Vec<DnsRecord>),
+    /// the name is an alias, holds its CNAME record
+    Alias(DnsRecord),
+    /// the name is at or below a zone cut, holds the NS records
+    /// of the cut
+    Referral(Vec<DnsRecord>),
+    /// the name exists but has no record of the requested type
+    NoData,
+    NxDomain,
+}
+
+impl Zone {
+    /// Builds the zone out of `records`, rejecting it when:
+    ///
+    /// - a record lies outside of the zone or is of another class
+    /// - the apex does not hold exactly one SOA and some NS records
+    /// - a CNAME shares its name with other records
+    /// - a zone cut holds other records than NS, DS and their
+    ///   DNSSEC records
+    /// - a name below a zone cut holds other records than glue
+    ///
+    /// takes: `(DnsName, Vec<DnsRecord>)` = (origin, records)
+    ///
+    /// returns: `Result<Zone>`
+    pub fn new(origin: DnsName, records: Vec<DnsRecord>) -> Result<Self> {
+        let invalid = |reason: String| invalid_zone(&origin.to_string(), &reason);
+
+        let mut names: BTreeMap<DnsName, Vec<DnsRecord>> = BTreeMap::new();
+        for record in records {
+            if !record.domain.is_subdomain_of(&origin) {
+                return Err(invalid(format!("`{}` is out of zone", record.domain)));
+            }
+            names.entry(record.domain.clone()).or_default().push(record);
+        }
+
+        let apex = names.get(&origin).map(Vec::as_slice).unwrap_or_default();
+        let soa: Vec<&DnsRecord> = apex
+            .iter()
+            .filter(|rec| rec.qtype() == QueryType::SOA)
+            .collect();
+        let class = match soa.as_slice() {
+            [soa] => soa.class,
+            _ => return Err(invalid("the apex must hold exactly one SOA".into())),
+        };
+        if !apex.iter().any(|rec| rec.qtype() == QueryType::NS) {
+            return Err(invalid("the apex holds no NS record".into()));
+        }
+
+        let mut cuts: Vec<&DnsName> = Vec::new();
+        for (name, records) in &names {
+            if let Some(record) = records.iter().find(|rec| rec.class != class) {
+                return Err(invalid(format!("`{}` is not of class {}", record, class)));
+            }
+
+            let cnames = records
+                .iter()
+                .filter(|rec| rec.qtype() == QueryType::CNAME)
+                .count();
+            if cnames > 0 && (records.len() > 1 || *name == origin) {
+                return Err(invalid(format!("the CNAME at `{}` has other data", name)));
+            }
+
+            // names are in canonical order, so the names below a
+            // cut come right after it
+            if let Some(cut) = cuts.last().filter(|cut| name.is_subdomain_of(cut)) {
+                if let Some(record) = records.iter().find(|rec| !is_glue(rec)) {
+                    return Err(invalid(format!(
+                        "`{}` is below the zone cut {}",
+                        record, cut
+                    )));
+                }
+                continue;
+            }
+            if *name != origin && records.iter().any(|rec| rec.qtype() == QueryType::NS) {
+                if let Some(record) = records.iter().find(|rec| !is_at_cut(rec)) {
+                    return Err(invalid(format!("`{}` is at the zone cut {}", record, name)));
+                }
+                cuts.push(name);
+            }
+        }
+
+        Ok(Self {
+            origin,
+            class,
+            names,
+            journal: Journal::new(),
+        })
+    }
+
+    /// Loads the zone from the master file at `path`
+    ///
+    /// takes: `(DnsName, &Path)` = (origin, path)
+    ///
+    /// returns: `Result<Zone>`
+    pub fn load(origin: DnsName, path: &Path) -> Result<Self> {
+        let records = zone_file::read(path, &origin)?;
+        Self::new(origin, records)
+    }
+
+    pub fn origin(&self) -> &DnsName {
+        &self.origin
+    }
+
+    pub fn class(&self) -> DnsClass {
+        self.class
+    }
+
+    /// The SOA record of the apex
+    pub fn soa(&self) -> &DnsRecord {
+        self.names[&self.origin]
+            .iter()
+            .find(|rec| rec.qtype() == QueryType::SOA)
+            .expect("a zone always has a SOA")
+    }
+
+    pub fn serial(&self) -> u32 {
+        match self.soa().data {
+            RecordData::SOA { serial, .. } => serial,
+            _ => 0,
+        }
+    }
+
+    /// The changes of the previous versions of the zone
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// The SOA record to send along negative answers, its TTL
+    /// is the negative caching TTL of RFC 2308 section 5
+    pub fn negative_soa(&self) -> DnsRecord {
+        let mut soa = self.soa().clone();
+        if let RecordData::SOA { minimum, .. } = soa.data {
+            let ttl = soa.ttl().min(minimum);
+            soa = DnsRecord::with_data(soa.domain, ttl, soa.data);
+            soa.class = self.class;
+        }
+        soa
+    }
+
+    /// All the records of the zone, in canonical order of their names
+    pub fn records(&self) -> impl Iterator<Item = &DnsRecord> {
+        self.names.values().flatten()
+    }
+
+    /// The records owned by `name`, wildcards are not expanded
+    pub fn records_at(&self, name: &DnsName) -> &[DnsRecord] {
+        self.names.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The `A` and `AAAA` records of `name`, used for glue and
+    /// additional section processing
+    pub fn addresses(&self, name: &DnsName) -> Vec<DnsRecord> {
+        self.names
+            .get(name)
+            .map(|records| records.iter().filter(|rec| is_glue(rec)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Looks `qname` up, which must lie in the zone. A name that
+    /// does not exist is answered by the matching wildcard, with
+    /// the owner of the records rewritten to `qname`. The DS
+    /// RRset of a cut is the parent's, it is answered rather than
+    /// referred (RFC 4035 section 3.1.4.1).
+    ///
+    /// takes: `(&self, &DnsName, QueryType)` = (zone, qname, qtype)
+    ///
+    /// returns: `Lookup`
+    pub fn lookup(&self, qname: &DnsName, qtype: QueryType) -> Lookup {
+        let last = match qtype {
+            QueryType::DS => qname.label_count().saturating_sub(1),
+            _ => qname.label_count(),
+        };
+        // zone cuts, from the one closest to the apex down to qname
+        for count in self.origin.label_count() + 1..=last {
+            let ns = self.records_of(&qname.trim_to(count), QueryType::NS);
+            if !ns.is_empty() {
+                return Lookup::Referral(ns);
+            }
+        }
+
+        if !self.exists(qname) {
+            return self.synthesize(qname, qtype);
+        }
+        self.lookup_at(qname, qname, qtype)
+    }
+
+    /// Looks `qtype` up at `owner`, answering for `qname`: the two
+    /// differ when `owner` is the wildcard `qname` matched
+    fn lookup_at(&self, qname: &DnsName, owner: &DnsName, qtype: QueryType) -> Lookup {
+        let Some(records) = self.names.get(owner) else {
+            // an empty non-terminal
+            return Lookup::NoData;
+        };
+
+        let rename = |record: &DnsRecord| {
+            let mut record = record.clone();
+            record.domain = qname.clone();
+            record
+        };
+        let matching: Vec<DnsRecord> = records
+            .iter()
+            .filter(|rec| rec.qtype() == qtype)
+            .map(rename)
+            .collect();
+        if !matching.is_empty() {
+            return Lookup::Answer(matching);
+        }
+        match records.iter().find(|rec| rec.qtype() == QueryType::CNAME) {
+            Some(cname) => Lookup::Alias(rename(cname)),
+            None => Lookup::NoData,
+        }
+    }
+
+    /// Wildcard synthesis (RFC 4592 section 3.3.1) for a name
+    /// that does not exist: the wildcard directly below the
+    /// closest encloser answers for it, if there is one.
+    fn synthesize(&self, qname: &DnsName, qtype: QueryType) -> Lookup {
+        let wildcard = self
+            .closest_encloser(qname)
+            .prepend(b"*")
+            .ok()
+            .filter(|wildcard| self.names.contains_key(wildcard));
+        match wildcard {
+            Some(wildcard) => self.lookup_at(qname, &wildcard, qtype),
+            None => Lookup::NxDomain,
+        }
+    }
+
+    /// The longest existing ancestor of `qname` (RFC 4592 section
+    /// 3.3.1), empty non-terminals exist, the apex always does.
+    pub fn closest_encloser(&self, qname: &DnsName) -> DnsName {
+        qname
+            .ancestors()
+            .take_while(|name| name.is_subdomain_of(&self.origin))
+            .find(|name| self.exists(name))
+            .unwrap_or_else(|| self.origin.clone())
+    }
+
+    /// The names owning records, in canonical order
+    pub fn names(&self) -> impl Iterator<Item = &DnsName> {
+        self.names.keys()
+    }
+
+    /// Whether `name` is a zone cut, a name holding NS records
+    /// other than the apex
+    pub fn is_cut(&self, name: &DnsName) -> bool {
+        *name != self.origin
+            && self
+                .records_at(name)
+                .iter()
+                .any(|rec| rec.qtype() == QueryType::NS)
+    }
+
+    /// Whether `name` lies below a zone cut: its records, if any,
+    /// are glue rather than data of the zone
+    pub fn is_occluded(&self, name: &DnsName) -> bool {
+        name.ancestors()
+            .skip(1)
+            .take_while(|ancestor| *ancestor != self.origin)
+            .any(|ancestor| self.is_cut(&ancestor))
+    }
+
+    /// Whether `name` owns records or is an empty non-terminal
+    pub fn exists(&self, name: &DnsName) -> bool {
+        self.names.contains_key(name) || self.has_descendants(name)
+    }
+
+    fn records_of(&self, name: &DnsName, qtype: QueryType) -> Vec<DnsRecord> {
+        self.names
+            .get(name)
+            .map(|records| {
+                records
+                    .iter()
+                    .filter(|rec| rec.qtype() == qtype)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether some name of the zone lies strictly below `name`
+    fn has_descendants(&self, name: &DnsName) -> bool {
+        self.names
+            .range(name..)
+            .find(|(other, _)| *other != name)
+            .is_some_and(|(other, _)| other.is_subdomain_of(name))
+    }
+}
+
+/// Whether serial `a` comes before serial `b` in the sequence
+/// space arithmetic of RFC 1982, serials wrap around.
+pub fn serial_lt(a: u32, b: u32) -> bool {
+    a != b && b.wrapping_sub(a) < 1 << 31
+}
+
+fn is_glue(record: &DnsRecord) -> bool {
+    matches!(record.qtype(), QueryType::A | QueryType::AAAA)
+}
+
+/// Whether `record` may sit at a zone cut: the NS RRset of the
+/// child, and the DS RRset of the parent along its NSEC and
+/// RRSIG records (RFC 4035 section 2.4)
+fn is_at_cut(record: &DnsRecord) -> bool {
+    matches!(
+        record.qtype(),
+        QueryType::NS | QueryType::DS | QueryType::NSEC | QueryType::RRSIG
+    )
+}
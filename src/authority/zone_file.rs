@@ -0,0 +1,284 @@
+//! Parser for master files (RFC 1035 section 5.1).
+//!
+//! Supported are the `$ORIGIN`, `$TTL` (RFC 2308) and `$INCLUDE`
+//! directives, relative names and `@`, blank owners repeating
+//! the previous owner, entries spanning lines in parentheses
+//! and `;` comments. TTLs may use the units of BIND (`1h`).
+
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::presentation::{parse_ttl, tokenize};
+use crate::dns_message::{DnsClass, QueryType};
+use crate::errors::{invalid_presentation, zone_file_error, Result};
+
+use super::zone::Zone;
+
+/// Deepest nesting of `$INCLUDE` directives
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Reads the records of the master file at `path`
+///
+/// takes: `(&Path, &DnsName)` = (path, origin), `$INCLUDE` paths
+/// are relative to the directory of `path`
+///
+/// returns: `Result<Vec<DnsRecord>>`
+pub fn read(path: &Path, origin: &DnsName) -> Result<Vec<DnsRecord>> {
+    let mut parser = Parser::new(origin);
+    parser.read(path, 0)?;
+    Ok(parser.records)
+}
+
+/// Parses the records of the master file `text`
+///
+/// takes: `(&str, &DnsName)` = (text, origin), `$INCLUDE` paths
+/// are relative to the current directory
+///
+/// returns: `Result<Vec<DnsRecord>>`
+pub fn parse(text: &str, origin: &DnsName) -> Result<Vec<DnsRecord>> {
+    let mut parser = Parser::new(origin);
+    parser.parse(text, "<zone>", Path::new("."), 0)?;
+    Ok(parser.records)
+}
+
+/// Writes `zone` to the master file at `path`, the SOA first
+/// and one record per line with absolute names. The file is
+/// replaced at once, a reader never sees half of it.
+///
+/// takes: `(&Path, &Zone)`
+///
+/// returns: `Result<()>`
+pub fn write(path: &Path, zone: &Zone) -> Result<()> {
+    let mut text = format!("; {} serial {}\n", zone.origin(), zone.serial());
+    let records =
+        iter::once(zone.soa()).chain(zone.records().filter(|rec| rec.qtype() != QueryType::SOA));
+    for record in records {
+        text.push_str(&format!("{}\n", record));
+    }
+
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, text)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// An entry of a master file, parentheses and comments removed
+struct Entry {
+    /// line the entry starts on
+    line: usize,
+    /// whether the entry starts with a blank, in which case the
+    /// owner is the one of the previous record
+    blank_owner: bool,
+    tokens: Vec<String>,
+}
+
+struct Parser {
+    origin: DnsName,
+    /// set by `$TTL`
+    default_ttl: Option<u32>,
+    last_ttl: Option<u32>,
+    last_owner: Option<DnsName>,
+    last_class: Option<DnsClass>,
+    records: Vec<DnsRecord>,
+}
+
+impl Parser {
+    fn new(origin: &DnsName) -> Self {
+        Self {
+            origin: origin.clone(),
+            default_ttl: None,
+            last_ttl: None,
+            last_owner: None,
+            last_class: None,
+            records: Vec::new(),
+        }
+    }
+
+    fn read(&mut self, path: &Path, depth: usize) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| zone_file_error(&path.display().to_string(), 0, &err.to_string()))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        self.parse(&text, &path.display().to_string(), dir, depth)
+    }
+
+    /// Parses `text`, `name` is used for error reporting and
+    /// `dir` is where `$INCLUDE` paths are looked up.
+    fn parse(&mut self, text: &str, name: &str, dir: &Path, depth: usize) -> Result<()> {
+        for entry in entries(text, name)? {
+            self.entry(&entry, dir, depth)
+                .map_err(|err| zone_file_error(name, entry.line, &err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, entry: &Entry, dir: &Path, depth: usize) -> Result<()> {
+        let text = entry.tokens.join(" ");
+        let argument = |index: usize| {
+            entry
+                .tokens
+                .get(index)
+                .ok_or_else(|| invalid_presentation(&text))
+        };
+
+        match entry.tokens[0].to_uppercase().as_str() {
+            "$ORIGIN" => {
+                self.origin = DnsName::parse_relative(argument(1)?, &self.origin)?;
+            }
+            "$TTL" => self.default_ttl = Some(parse_ttl(argument(1)?)?),
+            "$INCLUDE" => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(invalid_presentation(&text));
+                }
+                let path: PathBuf = dir.join(argument(1)?);
+                // the origin is restored once the included file is read
+                let origin = self.origin.clone();
+                if let Some(included) = entry.tokens.get(2) {
+                    self.origin = DnsName::parse_relative(included, &origin)?;
+                }
+                let read = self.read(&path, depth + 1);
+                self.origin = origin;
+                read?;
+            }
+            directive if directive.starts_with('$') => return Err(invalid_presentation(&text)),
+            _ => self.record(entry, &text)?,
+        }
+        Ok(())
+    }
+
+    /// Parses `[<owner>] [<ttl>] [<class>] <type> <rdata>`, the TTL
+    /// and the class may come in any order.
+    fn record(&mut self, entry: &Entry, text: &str) -> Result<()> {
+        let mut iter = entry.tokens.iter();
+        let owner = match entry.blank_owner {
+            true => self.last_owner.clone(),
+            false => Some(DnsName::parse_relative(
+                iter.next().ok_or_else(|| invalid_presentation(text))?,
+                &self.origin,
+            )?),
+        }
+        .ok_or_else(|| invalid_presentation(text))?;
+
+        let mut ttl = None;
+        let mut class = None;
+        let qtype = loop {
+            let token = iter.next().ok_or_else(|| invalid_presentation(text))?;
+            if let (None, Ok(value)) = (ttl, parse_ttl(token)) {
+                ttl = Some(value);
+            } else if let (None, Ok(value)) = (class, token.parse::<DnsClass>()) {
+                class = Some(value);
+            } else {
+                break token.parse::<QueryType>()?;
+            }
+        };
+
+        let data = RecordData::from_tokens(qtype, iter.as_slice(), Some(&self.origin), text)?;
+        // without any TTL in sight, the SOA falls back on its minimum
+        let ttl = match (ttl.or(self.default_ttl).or(self.last_ttl), &data) {
+            (Some(ttl), _) => ttl,
+            (None, RecordData::SOA { minimum, .. }) => *minimum,
+            (None, _) => return Err(invalid_presentation(text)),
+        };
+        let class = class.or(self.last_class).unwrap_or(DnsClass::IN);
+
+        let mut record = DnsRecord::with_data(owner.clone(), ttl, data);
+        record.class = class;
+        self.records.push(record);
+
+        self.last_owner = Some(owner);
+        self.last_ttl = Some(ttl);
+        self.last_class = Some(class);
+        Ok(())
+    }
+}
+
+/// Splits a master file into its entries: comments are dropped
+/// and the lines between parentheses are joined.
+fn entries(text: &str, name: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut start = 1;
+    let mut line = 1;
+    let mut depth = 0usize;
+    let mut quoted = false;
+
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let end_of_line = match c {
+            '\\' => {
+                current.push(c);
+                current.extend(chars.next());
+                false
+            }
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+                false
+            }
+            '\n' if quoted => return Err(zone_file_error(name, line, "unterminated string")),
+            _ if quoted => {
+                current.push(c);
+                false
+            }
+            ';' => {
+                chars.by_ref().find(|c| *c == '\n');
+                true
+            }
+            '(' => {
+                depth += 1;
+                current.push(' ');
+                false
+            }
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| zone_file_error(name, line, "unbalanced parentheses"))?;
+                current.push(' ');
+                false
+            }
+            '\n' => true,
+            c => {
+                current.push(c);
+                false
+            }
+        };
+
+        if !end_of_line {
+            continue;
+        }
+        line += 1;
+        if depth > 0 {
+            current.push(' ');
+            continue;
+        }
+        push_entry(&mut entries, &mut current, start, name)?;
+        start = line;
+    }
+
+    if depth > 0 || quoted {
+        return Err(zone_file_error(name, start, "unterminated entry"));
+    }
+    push_entry(&mut entries, &mut current, start, name)?;
+    Ok(entries)
+}
+
+fn push_entry(
+    entries: &mut Vec<Entry>,
+    current: &mut String,
+    line: usize,
+    name: &str,
+) -> Result<()> {
+    let text = std::mem::take(current);
+    if text.trim().is_empty() {
+        return Ok(());
+    }
+    let tokens = tokenize(&text).map_err(|err| zone_file_error(name, line, &err.to_string()))?;
+    entries.push(Entry {
+        line,
+        blank_owner: text.starts_with([' ', '\t']),
+        tokens,
+    });
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use super::dns_header::{Opcode, ResultCode};
+use super::dns_name::DnsName;
+use super::dns_question::DnsQuestion;
+use super::dns_record::DnsRecord;
+use super::edns::Edns;
+use super::{DnsMessage, QueryType};
+
+/// Fluent construction of a `DnsMessage`, started with
+/// `DnsMessage::query` or `DnsMessage::response_to`.
+pub struct MessageBuilder {
+    message: DnsMessage,
+}
+
+impl DnsMessage {
+    /// Starts a standard query holding a single question
+    ///
+    /// takes: `(&DnsName, QueryType)` = (qname, qtype)
+    ///
+    /// returns: `MessageBuilder`
+    pub fn query(qname: &DnsName, qtype: QueryType) -> MessageBuilder {
+        let mut message = DnsMessage::new();
+        message
+            .questions
+            .push(DnsQuestion::new(qname.clone(), qtype));
+        MessageBuilder { message }
+    }
+
+    /// Starts the response to `request`: the id, the opcode, the
+    /// RD and CD flags and the question are copied over, and
+    /// EDNS is answered with EDNS.
+    ///
+    /// takes: `&DnsMessage`
+    ///
+    /// returns: `MessageBuilder`
+    pub fn response_to(request: &DnsMessage) -> MessageBuilder {
+        let mut message = DnsMessage::new();
+        message.header.id = request.header.id;
+        message.header.opcode = request.header.opcode;
+        message.header.response = true;
+        message.header.recursion_desired = request.header.recursion_desired;
+        message.header.checking_disabled = request.header.checking_disabled;
+        message.questions.extend(request.questions.first().cloned());
+
+        let builder = MessageBuilder { message };
+        match request.edns() {
+            Some(edns) => builder.edns(Edns {
+                dnssec_ok: edns.dnssec_ok,
+                ..Edns::new()
+            }),
+            None => builder,
+        }
+    }
+}
+
+impl MessageBuilder {
+    pub fn id(mut self, id: u16) -> Self {
+        self.message.header.id = id;
+        self
+    }
+
+    pub fn opcode(mut self, opcode: Opcode) -> Self {
+        self.message.header.opcode = opcode;
+        self
+    }
+
+    pub fn rescode(mut self, rescode: ResultCode) -> Self {
+        self.message.header.rescode = rescode;
+        self
+    }
+
+    pub fn recursion_desired(mut self, value: bool) -> Self {
+        self.message.header.recursion_desired = value;
+        self
+    }
+
+    pub fn recursion_available(mut self, value: bool) -> Self {
+        self.message.header.recursion_available = value;
+        self
+    }
+
+    pub fn authoritative(mut self, value: bool) -> Self {
+        self.message.header.authoritative_answer = value;
+        self
+    }
+
+    pub fn checking_disabled(mut self, value: bool) -> Self {
+        self.message.header.checking_disabled = value;
+        self
+    }
+
+    /// Attaches an OPT record carrying `edns`, replacing any previous one
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.message.set_edns(edns);
+        self
+    }
+
+    pub fn answer(mut self, record: DnsRecord) -> Self {
+        self.message.answers.push(record);
+        self
+    }
+
+    pub fn authority(mut self, record: DnsRecord) -> Self {
+        self.message.authorities.push(record);
+        self
+    }
+
+    pub fn additional(mut self, record: DnsRecord) -> Self {
+        self.message.resources.push(record);
+        self
+    }
+
+    /// Finishes the message, the header counts are set from
+    /// the sections.
+    pub fn build(self) -> DnsMessage {
+        let mut message = self.message;
+        message.header.questions = message.questions.len() as u16;
+        message.header.answers = message.answers.len() as u16;
+        message.header.authoritative_entries = message.authorities.len() as u16;
+        message.header.resource_entries = message.resources.len() as u16;
+        message
+    }
+}
@@ -1,25 +1,134 @@
 use crate::dns_message::packet_buffer::PacketBuffer;
 use crate::errors::Result;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// The response code of a message (IANA "DNS RCODEs").
+///
+/// Codes above 15 are extended codes (RFC 6891 section 6.1.3):
+/// their upper 8 bits travel in the OPT record, `DnsMessage`
+/// splits and assembles them when writing and reading.
+/// Unassigned values are kept as they are. `BADVERS` is also
+/// `BADSIG` in the error field of TSIG records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ResultCode {
-    NOERROR = 0,
-    FORMERR = 1,
-    SERVFAIL = 2,
-    NXDOMAIN = 3,
-    NOTIMP = 4,
-    REFUSED = 5,
+    UNKNOWN(u16),
+    NOERROR,   // 0
+    FORMERR,   // 1
+    SERVFAIL,  // 2
+    NXDOMAIN,  // 3
+    NOTIMP,    // 4
+    REFUSED,   // 5
+    YXDOMAIN,  // 6
+    YXRRSET,   // 7
+    NXRRSET,   // 8
+    NOTAUTH,   // 9
+    NOTZONE,   // 10
+    DSOTYPENI, // 11
+    BADVERS,   // 16
+    BADKEY,    // 17
+    BADTIME,   // 18
+    BADMODE,   // 19
+    BADNAME,   // 20
+    BADALG,    // 21
+    BADTRUNC,  // 22
+    BADCOOKIE, // 23
 }
 
-impl From<u8> for ResultCode {
-    fn from(value: u8) -> Self {
+impl From<u16> for ResultCode {
+    fn from(value: u16) -> Self {
         match value {
+            0 => ResultCode::NOERROR,
             1 => ResultCode::FORMERR,
             2 => ResultCode::SERVFAIL,
             3 => ResultCode::NXDOMAIN,
             4 => ResultCode::NOTIMP,
             5 => ResultCode::REFUSED,
-            0 | _ => ResultCode::NOERROR,
+            6 => ResultCode::YXDOMAIN,
+            7 => ResultCode::YXRRSET,
+            8 => ResultCode::NXRRSET,
+            9 => ResultCode::NOTAUTH,
+            10 => ResultCode::NOTZONE,
+            11 => ResultCode::DSOTYPENI,
+            16 => ResultCode::BADVERS,
+            17 => ResultCode::BADKEY,
+            18 => ResultCode::BADTIME,
+            19 => ResultCode::BADMODE,
+            20 => ResultCode::BADNAME,
+            21 => ResultCode::BADALG,
+            22 => ResultCode::BADTRUNC,
+            23 => ResultCode::BADCOOKIE,
+            _ => ResultCode::UNKNOWN(value),
+        }
+    }
+}
+impl ResultCode {
+    /// The TSIG error (RFC 8945) sharing its value with `BADVERS`
+    pub const BADSIG: ResultCode = ResultCode::BADVERS;
+}
+
+impl From<ResultCode> for u16 {
+    fn from(value: ResultCode) -> Self {
+        match value {
+            ResultCode::NOERROR => 0,
+            ResultCode::FORMERR => 1,
+            ResultCode::SERVFAIL => 2,
+            ResultCode::NXDOMAIN => 3,
+            ResultCode::NOTIMP => 4,
+            ResultCode::REFUSED => 5,
+            ResultCode::YXDOMAIN => 6,
+            ResultCode::YXRRSET => 7,
+            ResultCode::NXRRSET => 8,
+            ResultCode::NOTAUTH => 9,
+            ResultCode::NOTZONE => 10,
+            ResultCode::DSOTYPENI => 11,
+            ResultCode::BADVERS => 16,
+            ResultCode::BADKEY => 17,
+            ResultCode::BADTIME => 18,
+            ResultCode::BADMODE => 19,
+            ResultCode::BADNAME => 20,
+            ResultCode::BADALG => 21,
+            ResultCode::BADTRUNC => 22,
+            ResultCode::BADCOOKIE => 23,
+            ResultCode::UNKNOWN(value) => value,
+        }
+    }
+}
+
+/// The kind of a message (RFC 1035 section 4.1.1, RFC 1996,
+/// RFC 2136, RFC 8490), unassigned values are kept as they are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    UNKNOWN(u8),
+    QUERY,  // 0
+    IQUERY, // 1
+    STATUS, // 2
+    NOTIFY, // 4
+    UPDATE, // 5
+    DSO,    // 6
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Opcode::QUERY,
+            1 => Opcode::IQUERY,
+            2 => Opcode::STATUS,
+            4 => Opcode::NOTIFY,
+            5 => Opcode::UPDATE,
+            6 => Opcode::DSO,
+            _ => Opcode::UNKNOWN(value),
+        }
+    }
+}
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        match value {
+            Opcode::QUERY => 0,
+            Opcode::IQUERY => 1,
+            Opcode::STATUS => 2,
+            Opcode::NOTIFY => 4,
+            Opcode::UPDATE => 5,
+            Opcode::DSO => 6,
+            Opcode::UNKNOWN(value) => value,
         }
     }
 }
@@ -31,9 +140,10 @@ pub struct DnsHeader {
     pub recursion_desired: bool,
     pub truncated_message: bool,
     pub authoritative_answer: bool,
-    pub opcode: u8,
+    pub opcode: Opcode,
     pub response: bool,
 
+    /// Only the lower 4 bits are in the header, see `ResultCode`
     pub rescode: ResultCode,
     pub checking_disabled: bool,
     pub authed_data: bool,
@@ -46,6 +156,12 @@ pub struct DnsHeader {
     pub resource_entries: u16,
 }
 
+impl Default for DnsHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DnsHeader {
     pub fn new() -> DnsHeader {
         DnsHeader {
@@ -54,7 +170,7 @@ impl DnsHeader {
             recursion_desired: false,
             truncated_message: false,
             authoritative_answer: false,
-            opcode: 0,
+            opcode: Opcode::QUERY,
             response: false,
 
             rescode: ResultCode::NOERROR,
@@ -83,10 +199,10 @@ impl DnsHeader {
         self.recursion_desired = (tmp_a & (1 << 0)) > 0;
         self.truncated_message = (tmp_a & (1 << 1)) > 0;
         self.authoritative_answer = (tmp_a & (1 << 2)) > 0;
-        self.opcode = (tmp_a >> 3) & 0x0F;
+        self.opcode = ((tmp_a >> 3) & 0x0F).into();
         self.response = (tmp_a & (1 << 7)) > 0;
 
-        self.rescode = (tmp_b & 0x0F).into();
+        self.rescode = ((tmp_b & 0x0F) as u16).into();
         self.checking_disabled = (tmp_b & (1 << 4)) > 0;
         self.authed_data = (tmp_b & (1 << 5)) > 0;
         self.z = (tmp_b & (1 << 6)) > 0;
@@ -103,19 +219,19 @@ impl DnsHeader {
     pub fn write(&self, buffer: &mut PacketBuffer) -> Result<()> {
         buffer.write_u16(self.id)?;
         buffer.write(
-            ((self.recursion_desired as u8) << 0)
+            (self.recursion_desired as u8)
                 | ((self.truncated_message as u8) << 1)
                 | ((self.authoritative_answer as u8) << 2)
-                | ((self.opcode) << 3)
-                | ((self.response as u8) << 7) as u8,
+                | ((u8::from(self.opcode) & 0x0F) << 3)
+                | ((self.response as u8) << 7),
         )?;
 
         buffer.write(
-            (self.rescode as u8)
+            ((u16::from(self.rescode) & 0x0F) as u8)
                 | ((self.checking_disabled as u8) << 4)
                 | ((self.authed_data as u8) << 5)
                 | ((self.z as u8) << 6)
-                | ((self.recursion_available as u8) << 7) as u8,
+                | ((self.recursion_available as u8) << 7),
         )?;
 
         buffer.write_u16(self.questions)?;
@@ -0,0 +1,335 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::{
+    empty_label, invalid_presentation, label_len_limit, name_len_limit, Error, Result,
+};
+
+/// Longest label allowed by RFC 1035 section 2.3.4
+pub const MAX_LABEL_LEN: usize = 63;
+/// Longest name allowed by RFC 1035 section 2.3.4, counted
+/// in wire format, i.e. with the length octets and the root label
+pub const MAX_NAME_LEN: usize = 255;
+
+/// A validated domain name.
+///
+/// Labels are kept as raw bytes in their original case, but
+/// equality, hashing and ordering ignore ASCII case as required
+/// by RFC 4343. Ordering is the canonical DNS ordering of
+/// RFC 4034 section 6.1.
+///
+/// The presentation format (`Display` / `FromStr`) is the
+/// absolute form with its trailing dot, bytes that can not be
+/// written as such are escaped as `\.` or `\DDD`.
+#[derive(Clone, Default)]
+pub struct DnsName {
+    labels: Vec<Vec<u8>>,
+}
+
+impl DnsName {
+    /// The root name `.`
+    pub fn root() -> Self {
+        Self { labels: Vec::new() }
+    }
+
+    /// Builds a name out of its labels, the leftmost label first
+    ///
+    /// takes: `IntoIterator<Item = impl Into<Vec<u8>>>`
+    ///
+    /// returns: `Result<DnsName>`
+    pub fn from_labels<I, L>(labels: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<Vec<u8>>,
+    {
+        let mut name = Self::root();
+        for label in labels {
+            name.push_label(label.into())?;
+        }
+        Ok(name)
+    }
+
+    /// Appends `label` to the right of the name, used when
+    /// reading a name label by label.
+    pub(crate) fn push_label(&mut self, label: Vec<u8>) -> Result<()> {
+        if label.is_empty() {
+            return Err(empty_label());
+        }
+        if label.len() > MAX_LABEL_LEN {
+            return Err(label_len_limit());
+        }
+        if self.wire_len() + label.len() + 1 > MAX_NAME_LEN {
+            return Err(name_len_limit());
+        }
+        self.labels.push(label);
+        Ok(())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Iterates over the labels, the leftmost label first
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        self.labels.iter().map(|label| label.as_slice())
+    }
+
+    pub fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Length of the uncompressed wire format of the name
+    pub fn wire_len(&self) -> usize {
+        self.labels
+            .iter()
+            .map(|label| label.len() + 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    /// Uncompressed wire format of the name
+    pub fn to_wire(&self) -> Vec<u8> {
+        let mut wire = Vec::with_capacity(self.wire_len());
+        for label in &self.labels {
+            wire.push(label.len() as u8);
+            wire.extend_from_slice(label);
+        }
+        wire.push(0);
+        wire
+    }
+
+    /// A copy of the name with all its labels in lowercase
+    pub fn to_lowercase(&self) -> Self {
+        Self {
+            labels: self
+                .labels
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// The name with its leftmost label removed, `None` for the root
+    pub fn parent(&self) -> Option<Self> {
+        self.labels.split_first().map(|(_, rest)| Self {
+            labels: rest.to_vec(),
+        })
+    }
+
+    /// The name prefixed by `label`
+    ///
+    /// takes: `(&self, &[u8])`
+    ///
+    /// returns: `Result<DnsName>`
+    pub fn prepend(&self, label: &[u8]) -> Result<Self> {
+        Self::from_labels(std::iter::once(label).chain(self.labels()))
+    }
+
+    /// Keeps the `count` rightmost labels of the name
+    pub fn trim_to(&self, count: usize) -> Self {
+        let skip = self.labels.len().saturating_sub(count);
+        Self {
+            labels: self.labels[skip..].to_vec(),
+        }
+    }
+
+    /// Iterates over the name and all its ancestors, from the
+    /// name itself up to the root
+    pub fn ancestors(&self) -> impl Iterator<Item = DnsName> + '_ {
+        (0..=self.labels.len())
+            .rev()
+            .map(move |count| self.trim_to(count))
+    }
+
+    /// Whether the name is `other` or lies below it, a name is
+    /// a subdomain of itself.
+    pub fn is_subdomain_of(&self, other: &DnsName) -> bool {
+        self.labels.len() >= other.labels.len()
+            && self
+                .labels()
+                .rev()
+                .zip(other.labels().rev())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// The deepest name both `self` and `other` are subdomains of
+    pub fn common_ancestor(&self, other: &DnsName) -> DnsName {
+        let count = self
+            .labels()
+            .rev()
+            .zip(other.labels().rev())
+            .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+            .count();
+        self.trim_to(count)
+    }
+
+    /// The name made of `self` followed by `origin`, used to
+    /// complete relative names.
+    pub fn append(&self, origin: &DnsName) -> Result<Self> {
+        Self::from_labels(self.labels().chain(origin.labels()))
+    }
+
+    /// Parses a name which may be relative to `origin`: names
+    /// ending with a dot are absolute, `@` is `origin` itself,
+    /// other names get `origin` appended.
+    ///
+    /// takes: `(&str, &DnsName)`
+    ///
+    /// returns: `Result<DnsName>`
+    pub fn parse_relative(text: &str, origin: &DnsName) -> Result<Self> {
+        if text == "@" {
+            return Ok(origin.clone());
+        }
+        let name = text.parse::<DnsName>()?;
+        match is_absolute(text) {
+            true => Ok(name),
+            false => name.append(origin),
+        }
+    }
+}
+
+/// Whether the presentation format `text` ends with an
+/// unescaped dot
+fn is_absolute(text: &str) -> bool {
+    match text.strip_suffix('.') {
+        Some(rest) => rest.bytes().rev().take_while(|byte| *byte == b'\\').count() % 2 == 0,
+        None => false,
+    }
+}
+
+impl FromStr for DnsName {
+    type Err = Error;
+
+    /// Parses the presentation format of a name, the trailing
+    /// dot is optional.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut name = Self::root();
+        if s == "." || s.is_empty() {
+            return Ok(name);
+        }
+
+        let mut label = Vec::new();
+        let mut bytes = s.bytes();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'.' => name.push_label(std::mem::take(&mut label))?,
+                b'\\' => match bytes.next() {
+                    Some(digit) if digit.is_ascii_digit() => {
+                        let value = [Some(digit), bytes.next(), bytes.next()]
+                            .iter()
+                            .try_fold(0u16, |acc, digit| match digit {
+                                Some(d) if d.is_ascii_digit() => Some(acc * 10 + (d - b'0') as u16),
+                                _ => None,
+                            })
+                            .filter(|value| *value <= 0xFF)
+                            .ok_or_else(|| invalid_presentation(s))?;
+                        label.push(value as u8);
+                    }
+                    Some(escaped) => label.push(escaped),
+                    None => return Err(invalid_presentation(s)),
+                },
+                byte => label.push(byte),
+            }
+        }
+        if !label.is_empty() {
+            name.push_label(label)?;
+        }
+        Ok(name)
+    }
+}
+
+impl fmt::Display for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        for label in &self.labels {
+            for &byte in label {
+                match byte {
+                    b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => {
+                        write!(f, "\\{}", byte as char)?
+                    }
+                    0x21..=0x7E => write!(f, "{}", byte as char)?,
+                    _ => write!(f, "\\{:03}", byte)?,
+                }
+            }
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+impl PartialEq for DnsName {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len() && self.is_subdomain_of(other)
+    }
+}
+
+impl Eq for DnsName {}
+
+impl PartialEq<str> for DnsName {
+    fn eq(&self, other: &str) -> bool {
+        other.parse::<DnsName>().is_ok_and(|other| *self == other)
+    }
+}
+
+impl PartialEq<&str> for DnsName {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl Hash for DnsName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.labels.len().hash(state);
+        for label in &self.labels {
+            label.to_ascii_lowercase().hash(state);
+        }
+    }
+}
+
+impl Ord for DnsName {
+    /// Canonical ordering (RFC 4034 section 6.1): labels are
+    /// compared from the rightmost one, as lowercase bytes, and
+    /// a name sorts before its subdomains.
+    fn cmp(&self, other: &Self) -> Ordering {
+        for (a, b) in self.labels().rev().zip(other.labels().rev()) {
+            let ordering = a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase());
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        self.labels.len().cmp(&other.labels.len())
+    }
+}
+
+impl PartialOrd for DnsName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Serialize for DnsName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse()
+            .map_err(|err: Error| D::Error::custom(err.to_string()))
+    }
+}
@@ -1,34 +1,35 @@
 use crate::dns_message::packet_buffer::PacketBuffer;
 use crate::errors::Result;
 
-use super::QueryType;
+use super::dns_name::DnsName;
+use super::{DnsClass, QueryType};
 
 #[derive(Clone, Debug)]
 pub struct DnsQuestion {
-    pub qname: String,
+    pub qname: DnsName,
     pub qtype: QueryType,
-    qclass: u16,
+    pub qclass: DnsClass,
 }
 
 impl DnsQuestion {
-    pub fn new(qname: String, qtype: QueryType) -> Self {
+    pub fn new(qname: DnsName, qtype: QueryType) -> Self {
         Self {
             qname,
             qtype,
-            qclass: 1,
+            qclass: DnsClass::IN,
         }
     }
 
     pub fn read(&mut self, buffer: &mut PacketBuffer) -> Result<()> {
         buffer.read_qname(&mut self.qname)?;
         self.qtype = buffer.read_u16()?.into();
-        self.qclass = buffer.read_u16()?;
+        self.qclass = buffer.read_u16()?.into();
         Ok(())
     }
 
     pub fn write(&self, buffer: &mut PacketBuffer) -> Result<()> {
         buffer.write_qname(&self.qname)?;
         buffer.write_u16(self.qtype.into())?;
-        buffer.write_u16(self.qclass)
+        buffer.write_u16(self.qclass.into())
     }
 }
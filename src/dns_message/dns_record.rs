@@ -1,23 +1,172 @@
 use crate::dns_message::packet_buffer::PacketBuffer;
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-use crate::errors::Result;
+use crate::errors::{index_out_of_bound, rdata_len_mismatch, Result};
 
-use super::QueryType;
+use super::dns_name::DnsName;
+use super::edns::EdnsOption;
+use super::{DnsClass, QueryType};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RecordData {
-    UNKNOWN,
-    A { addr: Ipv4Addr },
-    NS { host: String },
-    CNAME { host: String },
-    MX { priority: u16, host: String },
-    AAAA { addr: Ipv6Addr },
+    UNKNOWN {
+        qtype: u16,
+        data: Vec<u8>,
+    },
+    A {
+        addr: Ipv4Addr,
+    },
+    NS {
+        host: DnsName,
+    },
+    CNAME {
+        host: DnsName,
+    },
+    SOA {
+        mname: DnsName,
+        rname: DnsName,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    PTR {
+        host: DnsName,
+    },
+    MX {
+        priority: u16,
+        host: DnsName,
+    },
+    /// the strings are kept as the bytes they are on the wire,
+    /// and escaped in presentation format
+    TXT {
+        data: Vec<Vec<u8>>,
+    },
+    AAAA {
+        addr: Ipv6Addr,
+    },
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: DnsName,
+    },
+    NAPTR {
+        order: u16,
+        preference: u16,
+        flags: Vec<u8>,
+        services: Vec<u8>,
+        regexp: Vec<u8>,
+        replacement: DnsName,
+    },
+    OPT {
+        options: Vec<EdnsOption>,
+    },
+    DS {
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        digest: Vec<u8>,
+    },
+    SSHFP {
+        algorithm: u8,
+        fp_type: u8,
+        fingerprint: Vec<u8>,
+    },
+    RRSIG {
+        type_covered: QueryType,
+        algorithm: u8,
+        labels: u8,
+        original_ttl: u32,
+        expiration: u32,
+        inception: u32,
+        key_tag: u16,
+        signer: DnsName,
+        signature: Vec<u8>,
+    },
+    NSEC {
+        next: DnsName,
+        types: Vec<QueryType>,
+    },
+    DNSKEY {
+        flags: u16,
+        protocol: u8,
+        algorithm: u8,
+        public_key: Vec<u8>,
+    },
+    NSEC3 {
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+        next_hashed: Vec<u8>,
+        types: Vec<QueryType>,
+    },
+    NSEC3PARAM {
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+    },
+    TLSA {
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        data: Vec<u8>,
+    },
+    CAA {
+        flags: u8,
+        tag: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// RFC 8945 section 4.2, `time_signed` is 48 bits wide
+    TSIG {
+        algorithm: DnsName,
+        time_signed: u64,
+        fudge: u16,
+        mac: Vec<u8>,
+        original_id: u16,
+        error: u16,
+        other: Vec<u8>,
+    },
 }
 impl RecordData {
     fn new() -> Self {
-        Self::UNKNOWN
+        Self::UNKNOWN {
+            qtype: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// The `QueryType` this data is stored under
+    pub fn qtype(&self) -> QueryType {
+        match self {
+            Self::UNKNOWN { qtype, .. } => QueryType::from(*qtype),
+            Self::A { .. } => QueryType::A,
+            Self::NS { .. } => QueryType::NS,
+            Self::CNAME { .. } => QueryType::CNAME,
+            Self::SOA { .. } => QueryType::SOA,
+            Self::PTR { .. } => QueryType::PTR,
+            Self::MX { .. } => QueryType::MX,
+            Self::AAAA { .. } => QueryType::AAAA,
+            Self::SRV { .. } => QueryType::SRV,
+            Self::TXT { .. } => QueryType::TXT,
+            Self::NAPTR { .. } => QueryType::NAPTR,
+            Self::OPT { .. } => QueryType::OPT,
+            Self::DS { .. } => QueryType::DS,
+            Self::SSHFP { .. } => QueryType::SSHFP,
+            Self::RRSIG { .. } => QueryType::RRSIG,
+            Self::NSEC { .. } => QueryType::NSEC,
+            Self::DNSKEY { .. } => QueryType::DNSKEY,
+            Self::NSEC3 { .. } => QueryType::NSEC3,
+            Self::NSEC3PARAM { .. } => QueryType::NSEC3PARAM,
+            Self::TLSA { .. } => QueryType::TLSA,
+            Self::CAA { .. } => QueryType::CAA,
+            Self::TSIG { .. } => QueryType::TSIG,
+        }
     }
+
     fn new_a(addr: &str) -> Self {
         Self::A {
             addr: addr.parse::<Ipv4Addr>().unwrap(),
@@ -34,44 +183,84 @@ impl RecordData {
             addr: Ipv6Addr::from(raw_addr),
         }
     }
-    fn read_ns(host: String) -> RecordData {
+    fn read_ns(host: DnsName) -> RecordData {
         RecordData::NS { host }
     }
-    fn read_cname(host: String) -> RecordData {
+    fn read_cname(host: DnsName) -> RecordData {
         RecordData::CNAME { host }
     }
 
-    fn read_mx(priority: u16, host: String) -> RecordData {
+    fn read_mx(priority: u16, host: DnsName) -> RecordData {
         RecordData::MX { priority, host }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct DnsRecord {
-    pub domain: String,
-    qtype: QueryType,
-    class: u16,
-    ttl: u32,
+    pub domain: DnsName,
+    pub(super) qtype: QueryType,
+    pub class: DnsClass,
+    pub(super) ttl: u32,
     data_len: u16,
     pub data: RecordData,
 }
+/// Records are equal when their owner, type, class, TTL and
+/// data are, the data length read off the wire is left out
+impl PartialEq for DnsRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.domain == other.domain
+            && self.qtype == other.qtype
+            && self.class == other.class
+            && self.ttl == other.ttl
+            && self.data == other.data
+    }
+}
+
+impl Eq for DnsRecord {}
+
+impl Default for DnsRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DnsRecord {
     pub fn new() -> Self {
         Self {
-            domain: String::new(),
+            domain: DnsName::root(),
             qtype: QueryType::UNKNOWN(0),
-            class: 1,
+            class: DnsClass::IN,
             ttl: 0,
             data_len: 0,
             data: RecordData::new(),
         }
     }
 
-    pub fn new_a(addr: &str, hostname: &str) -> Self {
+    /// Builds a record of class `IN` whose type is taken from `data`
+    pub fn with_data(domain: DnsName, ttl: u32, data: RecordData) -> Self {
         Self {
-            domain: hostname.into(),
+            domain,
+            qtype: data.qtype(),
+            class: DnsClass::IN,
+            ttl,
+            data_len: 0,
+            data,
+        }
+    }
+
+    pub fn qtype(&self) -> QueryType {
+        self.qtype
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    pub fn new_a(addr: &str, hostname: &DnsName) -> Self {
+        Self {
+            domain: hostname.clone(),
             qtype: QueryType::A,
-            class: 1,
+            class: DnsClass::IN,
             ttl: 100,
             data_len: 4,
             data: RecordData::new_a(addr),
@@ -94,24 +283,54 @@ impl DnsRecord {
             }
 
             QueryType::NS => {
-                let mut host = String::new();
+                let mut host = DnsName::root();
                 buffer.read_qname(&mut host)?;
 
                 record.data = RecordData::read_ns(host)
             }
             QueryType::CNAME => {
-                let mut host = String::new();
+                let mut host = DnsName::root();
                 buffer.read_qname(&mut host)?;
 
                 record.data = RecordData::read_cname(host)
             }
+            QueryType::SOA => {
+                let mut mname = DnsName::root();
+                buffer.read_qname(&mut mname)?;
+                let mut rname = DnsName::root();
+                buffer.read_qname(&mut rname)?;
+
+                record.data = RecordData::SOA {
+                    mname,
+                    rname,
+                    serial: buffer.read_u32()?,
+                    refresh: buffer.read_u32()?,
+                    retry: buffer.read_u32()?,
+                    expire: buffer.read_u32()?,
+                    minimum: buffer.read_u32()?,
+                }
+            }
+            QueryType::PTR => {
+                let mut host = DnsName::root();
+                buffer.read_qname(&mut host)?;
+
+                record.data = RecordData::PTR { host }
+            }
             QueryType::MX => {
                 let priority = buffer.read_u16()?;
-                let mut host = String::new();
+                let mut host = DnsName::root();
                 buffer.read_qname(&mut host)?;
 
                 record.data = RecordData::read_mx(priority, host)
             }
+            QueryType::TXT => {
+                let end = buffer.pos() + record.data_len as usize;
+                let mut data = Vec::new();
+                while buffer.pos() < end {
+                    data.push(buffer.read_character_string()?);
+                }
+                record.data = RecordData::TXT { data }
+            }
             QueryType::AAAA => {
                 let mut raw_addr: [u16; 8] = [0; 8];
                 for hextet in raw_addr.iter_mut() {
@@ -119,26 +338,279 @@ impl DnsRecord {
                 }
                 record.data = RecordData::read_aaaa(raw_addr)
             }
-            QueryType::UNKNOWN(_) => {
-                buffer.advance(record.data_len as usize)?;
+            QueryType::SRV => {
+                let priority = buffer.read_u16()?;
+                let weight = buffer.read_u16()?;
+                let port = buffer.read_u16()?;
+                let mut target = DnsName::root();
+                buffer.read_qname(&mut target)?;
+
+                record.data = RecordData::SRV {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                }
+            }
+            QueryType::NAPTR => {
+                let order = buffer.read_u16()?;
+                let preference = buffer.read_u16()?;
+                let flags = buffer.read_character_string()?;
+                let services = buffer.read_character_string()?;
+                let regexp = buffer.read_character_string()?;
+                let mut replacement = DnsName::root();
+                buffer.read_qname(&mut replacement)?;
+
+                record.data = RecordData::NAPTR {
+                    order,
+                    preference,
+                    flags,
+                    services,
+                    regexp,
+                    replacement,
+                }
+            }
+            QueryType::OPT => {
+                let end = buffer.pos() + record.data_len as usize;
+                let mut options = Vec::new();
+                while buffer.pos() < end {
+                    let code = buffer.read_u16()?;
+                    let len = buffer.read_u16()?;
+                    let data = buffer.read_bytes(len as usize)?;
+                    options.push(EdnsOption { code, data });
+                }
+
+                record.data = RecordData::OPT { options }
+            }
+            QueryType::DS => {
+                let key_tag = buffer.read_u16()?;
+                let algorithm = buffer.read()?;
+                let digest_type = buffer.read()?;
+                let digest = buffer.read_bytes(Self::rdata_rest(record, 4)?)?;
+
+                record.data = RecordData::DS {
+                    key_tag,
+                    algorithm,
+                    digest_type,
+                    digest,
+                }
+            }
+            QueryType::RRSIG => {
+                let start = buffer.pos();
+                let type_covered = buffer.read_u16()?.into();
+                let algorithm = buffer.read()?;
+                let labels = buffer.read()?;
+                let original_ttl = buffer.read_u32()?;
+                let expiration = buffer.read_u32()?;
+                let inception = buffer.read_u32()?;
+                let key_tag = buffer.read_u16()?;
+                let mut signer = DnsName::root();
+                buffer.read_qname(&mut signer)?;
+                let prefix_len = buffer.pos() - start;
+                let signature = buffer.read_bytes(Self::rdata_rest(record, prefix_len)?)?;
+
+                record.data = RecordData::RRSIG {
+                    type_covered,
+                    algorithm,
+                    labels,
+                    original_ttl,
+                    expiration,
+                    inception,
+                    key_tag,
+                    signer,
+                    signature,
+                }
+            }
+            QueryType::NSEC => {
+                let start = buffer.pos();
+                let mut next = DnsName::root();
+                buffer.read_qname(&mut next)?;
+                let prefix_len = buffer.pos() - start;
+                let types = read_type_bitmap(buffer, Self::rdata_rest(record, prefix_len)?)?;
+
+                record.data = RecordData::NSEC { next, types }
+            }
+            QueryType::DNSKEY => {
+                let flags = buffer.read_u16()?;
+                let protocol = buffer.read()?;
+                let algorithm = buffer.read()?;
+                let public_key = buffer.read_bytes(Self::rdata_rest(record, 4)?)?;
+
+                record.data = RecordData::DNSKEY {
+                    flags,
+                    protocol,
+                    algorithm,
+                    public_key,
+                }
+            }
+            QueryType::NSEC3 => {
+                let hash_algorithm = buffer.read()?;
+                let flags = buffer.read()?;
+                let iterations = buffer.read_u16()?;
+                let salt_len = buffer.read()?;
+                let salt = buffer.read_bytes(salt_len as usize)?;
+                let hash_len = buffer.read()?;
+                let next_hashed = buffer.read_bytes(hash_len as usize)?;
+                let prefix_len = 6 + salt_len as usize + hash_len as usize;
+                let types = read_type_bitmap(buffer, Self::rdata_rest(record, prefix_len)?)?;
+
+                record.data = RecordData::NSEC3 {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed,
+                    types,
+                }
+            }
+            QueryType::NSEC3PARAM => {
+                let hash_algorithm = buffer.read()?;
+                let flags = buffer.read()?;
+                let iterations = buffer.read_u16()?;
+                let salt_len = buffer.read()?;
+                let salt = buffer.read_bytes(salt_len as usize)?;
+
+                record.data = RecordData::NSEC3PARAM {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                }
+            }
+            QueryType::SSHFP => {
+                let algorithm = buffer.read()?;
+                let fp_type = buffer.read()?;
+                let fingerprint = buffer.read_bytes(Self::rdata_rest(record, 2)?)?;
+
+                record.data = RecordData::SSHFP {
+                    algorithm,
+                    fp_type,
+                    fingerprint,
+                }
+            }
+            QueryType::TLSA => {
+                let usage = buffer.read()?;
+                let selector = buffer.read()?;
+                let matching_type = buffer.read()?;
+                let data = buffer.read_bytes(Self::rdata_rest(record, 3)?)?;
+
+                record.data = RecordData::TLSA {
+                    usage,
+                    selector,
+                    matching_type,
+                    data,
+                }
+            }
+            QueryType::CAA => {
+                let flags = buffer.read()?;
+                let tag_len = buffer.read()?;
+                let tag = buffer.read_bytes(tag_len as usize)?;
+                let value = buffer.read_bytes(Self::rdata_rest(record, 2 + tag_len as usize)?)?;
+
+                record.data = RecordData::CAA { flags, tag, value }
+            }
+            QueryType::TSIG => {
+                let mut algorithm = DnsName::root();
+                buffer.read_qname(&mut algorithm)?;
+                let time_signed =
+                    (u64::from(buffer.read_u16()?) << 32) | u64::from(buffer.read_u32()?);
+                let fudge = buffer.read_u16()?;
+                let mac_len = buffer.read_u16()?;
+                let mac = buffer.read_bytes(mac_len as usize)?;
+                let original_id = buffer.read_u16()?;
+                let error = buffer.read_u16()?;
+                let other_len = buffer.read_u16()?;
+                let other = buffer.read_bytes(other_len as usize)?;
+
+                record.data = RecordData::TSIG {
+                    algorithm,
+                    time_signed,
+                    fudge,
+                    mac,
+                    original_id,
+                    error,
+                    other,
+                }
+            }
+            // the query types only appear in questions, and in
+            // updates without data
+            QueryType::UNKNOWN(_) | QueryType::IXFR | QueryType::AXFR | QueryType::ANY => {
+                let data = buffer.read_bytes(record.data_len as usize)?;
+                record.data = RecordData::UNKNOWN {
+                    qtype: record.qtype.into(),
+                    data,
+                };
             }
         }
         Ok(())
     }
 
+    /// Number of record data bytes left after a fixed size prefix
+    /// of `prefix_len` bytes.
+    ///
+    /// returns `Result<usize>`
+    fn rdata_rest(record: &Self, prefix_len: usize) -> Result<usize> {
+        (record.data_len as usize)
+            .checked_sub(prefix_len)
+            .ok_or_else(|| rdata_len_mismatch(&record.qtype.to_string(), record.data_len))
+    }
+
     /// Parse data from payload `PacketBuffer`
     ///
-    /// takes `&mut PacketBuffer`
+    /// takes `&mut PacketBuffer`, in strict mode the record
+    /// data must span exactly `data_len` bytes, otherwise the
+    /// buffer is moved to the end of the record data whatever
+    /// was parsed.
     ///
     /// returns `Result<DnsRecord>`
     pub fn read(buffer: &mut PacketBuffer) -> Result<Self> {
+        Self::read_record(buffer, false)
+    }
+
+    /// Parses a prerequisite or an update of an UPDATE message,
+    /// the ones of class ANY or NONE may have no data
+    ///
+    /// returns `Result<DnsRecord>`
+    pub fn read_update(buffer: &mut PacketBuffer) -> Result<Self> {
+        Self::read_record(buffer, true)
+    }
+
+    fn read_record(buffer: &mut PacketBuffer, is_update: bool) -> Result<Self> {
         let mut result = Self::new();
         buffer.read_qname(&mut result.domain)?;
         result.qtype = buffer.read_u16()?.into();
-        result.class = buffer.read_u16()?;
+        result.class = buffer.read_u16()?.into();
         result.ttl = buffer.read_u32()?;
         result.data_len = buffer.read_u16()?;
+
+        let end = buffer.pos() + result.data_len as usize;
+        if end > buffer.len() {
+            return Err(index_out_of_bound());
+        }
+        // RFC 2136 section 2.4 and 2.5, the prerequisites and
+        // updates standing for a whole RRset or name have no data
+        let is_rrset = is_update && matches!(result.class, DnsClass::ANY | DnsClass::NONE);
+        if result.data_len == 0 && result.qtype != QueryType::OPT {
+            let has_data = !matches!(result.qtype, QueryType::UNKNOWN(_));
+            if has_data && !is_rrset && buffer.is_strict() {
+                return Err(rdata_len_mismatch(&result.qtype.to_string(), 0));
+            }
+            result.data = RecordData::UNKNOWN {
+                qtype: result.qtype.into(),
+                data: Vec::new(),
+            };
+            return Ok(result);
+        }
         Self::read_data(&mut result, buffer)?;
+        if buffer.pos() != end {
+            if buffer.is_strict() {
+                return Err(rdata_len_mismatch(
+                    &result.qtype.to_string(),
+                    result.data_len,
+                ));
+            }
+            buffer.seek(end)?;
+        }
         Ok(result)
     }
 
@@ -157,16 +629,200 @@ impl DnsRecord {
             RecordData::A { addr } => buffer.write_u32(addr.into())?,
             RecordData::NS { ref host } => buffer.write_qname(host)?,
             RecordData::CNAME { ref host } => buffer.write_qname(host)?,
+            RecordData::SOA {
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                buffer.write_qname(mname)?;
+                buffer.write_qname(rname)?;
+                buffer.write_u32(serial)?;
+                buffer.write_u32(refresh)?;
+                buffer.write_u32(retry)?;
+                buffer.write_u32(expire)?;
+                buffer.write_u32(minimum)?;
+            }
+            RecordData::PTR { ref host } => buffer.write_qname(host)?,
             RecordData::MX { priority, ref host } => {
                 buffer.write_u16(priority)?;
                 buffer.write_qname(host)?;
             }
+            RecordData::TXT { ref data } => {
+                for text in data {
+                    buffer.write_character_string(text)?;
+                }
+            }
             RecordData::AAAA { ref addr } => {
                 for hextet in addr.segments() {
                     buffer.write_u16(hextet)?;
                 }
             }
-            RecordData::UNKNOWN => {}
+            RecordData::SRV {
+                priority,
+                weight,
+                port,
+                ref target,
+            } => {
+                buffer.write_u16(priority)?;
+                buffer.write_u16(weight)?;
+                buffer.write_u16(port)?;
+                buffer.write_qname(target)?;
+            }
+            RecordData::NAPTR {
+                order,
+                preference,
+                ref flags,
+                ref services,
+                ref regexp,
+                ref replacement,
+            } => {
+                buffer.write_u16(order)?;
+                buffer.write_u16(preference)?;
+                buffer.write_character_string(flags)?;
+                buffer.write_character_string(services)?;
+                buffer.write_character_string(regexp)?;
+                buffer.write_qname(replacement)?;
+            }
+            RecordData::OPT { ref options } => {
+                for option in options {
+                    buffer.write_u16(option.code)?;
+                    buffer.write_u16(option.data.len() as u16)?;
+                    buffer.write_bytes(&option.data)?;
+                }
+            }
+            RecordData::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                ref digest,
+            } => {
+                buffer.write_u16(key_tag)?;
+                buffer.write(algorithm)?;
+                buffer.write(digest_type)?;
+                buffer.write_bytes(digest)?;
+            }
+            RecordData::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                ref signer,
+                ref signature,
+            } => {
+                buffer.write_u16(type_covered.into())?;
+                buffer.write(algorithm)?;
+                buffer.write(labels)?;
+                buffer.write_u32(original_ttl)?;
+                buffer.write_u32(expiration)?;
+                buffer.write_u32(inception)?;
+                buffer.write_u16(key_tag)?;
+                buffer.write_qname(signer)?;
+                buffer.write_bytes(signature)?;
+            }
+            RecordData::NSEC {
+                ref next,
+                ref types,
+            } => {
+                buffer.write_qname(next)?;
+                buffer.write_bytes(&type_bitmap(types))?;
+            }
+            RecordData::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                ref public_key,
+            } => {
+                buffer.write_u16(flags)?;
+                buffer.write(protocol)?;
+                buffer.write(algorithm)?;
+                buffer.write_bytes(public_key)?;
+            }
+            RecordData::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ref next_hashed,
+                ref types,
+            } => {
+                buffer.write(hash_algorithm)?;
+                buffer.write(flags)?;
+                buffer.write_u16(iterations)?;
+                buffer.write(salt.len() as u8)?;
+                buffer.write_bytes(salt)?;
+                buffer.write(next_hashed.len() as u8)?;
+                buffer.write_bytes(next_hashed)?;
+                buffer.write_bytes(&type_bitmap(types))?;
+            }
+            RecordData::NSEC3PARAM {
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+            } => {
+                buffer.write(hash_algorithm)?;
+                buffer.write(flags)?;
+                buffer.write_u16(iterations)?;
+                buffer.write(salt.len() as u8)?;
+                buffer.write_bytes(salt)?;
+            }
+            RecordData::SSHFP {
+                algorithm,
+                fp_type,
+                ref fingerprint,
+            } => {
+                buffer.write(algorithm)?;
+                buffer.write(fp_type)?;
+                buffer.write_bytes(fingerprint)?;
+            }
+            RecordData::TLSA {
+                usage,
+                selector,
+                matching_type,
+                ref data,
+            } => {
+                buffer.write(usage)?;
+                buffer.write(selector)?;
+                buffer.write(matching_type)?;
+                buffer.write_bytes(data)?;
+            }
+            RecordData::CAA {
+                flags,
+                ref tag,
+                ref value,
+            } => {
+                buffer.write(flags)?;
+                buffer.write_character_string(tag)?;
+                buffer.write_bytes(value)?;
+            }
+            RecordData::TSIG {
+                ref algorithm,
+                time_signed,
+                fudge,
+                ref mac,
+                original_id,
+                error,
+                ref other,
+            } => {
+                buffer.write_qname(algorithm)?;
+                buffer.write_u16((time_signed >> 32) as u16)?;
+                buffer.write_u32(time_signed as u32)?;
+                buffer.write_u16(fudge)?;
+                buffer.write_u16(mac.len() as u16)?;
+                buffer.write_bytes(mac)?;
+                buffer.write_u16(original_id)?;
+                buffer.write_u16(error)?;
+                buffer.write_u16(other.len() as u16)?;
+                buffer.write_bytes(other)?;
+            }
+            RecordData::UNKNOWN { ref data, .. } => buffer.write_bytes(data)?,
         }
         self.data_len = (buffer.pos() - (start_pos + 2)) as u16;
         buffer.set_u16(start_pos, self.data_len)?;
@@ -185,9 +841,59 @@ impl DnsRecord {
         let start_pos = buffer.pos();
         buffer.write_qname(&self.domain)?;
         buffer.write_u16(self.qtype.into())?;
-        buffer.write_u16(self.class)?;
+        buffer.write_u16(self.class.into())?;
         buffer.write_u32(self.ttl)?;
         self.write_data(buffer)?;
         Ok(buffer.pos() - start_pos)
     }
 }
+
+/// Reads the type bitmap of NSEC and NSEC3 records (RFC 4034
+/// section 4.1.2), spanning `len` bytes
+fn read_type_bitmap(buffer: &mut PacketBuffer, len: usize) -> Result<Vec<QueryType>> {
+    let data = buffer.read_bytes(len)?;
+    let mut types = Vec::new();
+    let mut rest = data.as_slice();
+    while let [window, bitmap_len, tail @ ..] = rest {
+        let bitmap = tail
+            .get(..*bitmap_len as usize)
+            .ok_or_else(|| rdata_len_mismatch("type bitmap", len as u16))?;
+        for (i, byte) in bitmap.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    let value = (*window as u16) << 8 | (i * 8 + bit) as u16;
+                    types.push(value.into());
+                }
+            }
+        }
+        rest = &tail[*bitmap_len as usize..];
+    }
+    if !rest.is_empty() {
+        return Err(rdata_len_mismatch("type bitmap", len as u16));
+    }
+    Ok(types)
+}
+
+/// The type bitmap of `types`, in windows of 256 types
+fn type_bitmap(types: &[QueryType]) -> Vec<u8> {
+    let mut values: Vec<u16> = types.iter().map(|qtype| u16::from(*qtype)).collect();
+    values.sort_unstable();
+    values.dedup();
+
+    let mut data = Vec::new();
+    for window in 0..=u8::MAX {
+        let mut bitmap = [0u8; 32];
+        let mut len = 0;
+        for value in values.iter().filter(|value| (*value >> 8) as u8 == window) {
+            let low = (value & 0xFF) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+            len = low / 8 + 1;
+        }
+        if len > 0 {
+            data.push(window);
+            data.push(len as u8);
+            data.extend_from_slice(&bitmap[..len]);
+        }
+    }
+    data
+}
@@ -0,0 +1,75 @@
+use super::dns_name::DnsName;
+use super::dns_record::{DnsRecord, RecordData};
+
+/// Payload size advertised by default, the value recommended by
+/// the DNS flag day 2020 to avoid IP fragmentation.
+pub const DEFAULT_PAYLOAD_SIZE: u16 = 1232;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+/// EDNS(0) parameters (RFC 6891), carried on the wire by an
+/// OPT pseudo record in the additional section:
+///
+/// - the record class holds the UDP payload size
+/// - the record TTL holds the extended rcode, the version
+///   and the flags
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Edns {
+    pub fn new() -> Self {
+        Self {
+            udp_payload_size: DEFAULT_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    /// Reads the EDNS parameters out of an OPT record
+    ///
+    /// takes: `&DnsRecord`
+    ///
+    /// returns: `Option<Edns>`, `None` if the record is not an OPT record
+    pub fn from_record(record: &DnsRecord) -> Option<Self> {
+        let options = match record.data {
+            RecordData::OPT { ref options } => options.clone(),
+            _ => return None,
+        };
+        let ttl = record.ttl();
+        Some(Self {
+            udp_payload_size: record.class.into(),
+            extended_rcode: (ttl >> 24) as u8,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: (ttl & 0x8000) > 0,
+            options,
+        })
+    }
+
+    pub fn to_record(&self) -> DnsRecord {
+        let options = self.options.clone();
+        let mut record = DnsRecord::with_data(DnsName::root(), 0, RecordData::OPT { options });
+        record.class = self.udp_payload_size.into();
+        record.ttl = ((self.extended_rcode as u32) << 24)
+            | ((self.version as u32) << 16)
+            | ((self.dnssec_ok as u32) << 15);
+        record
+    }
+}
@@ -0,0 +1,301 @@
+//! JSON representation of DNS messages following the
+//! conventions of RFC 8427.
+//!
+//! Record data of known types is stored in its presentation
+//! format under `rdata<TYPE>` (e.g. `rdataMX`), the data of
+//! unknown types is stored as hex under `RDATAHEX`. The strings
+//! of TXT, NAPTR and CAA records that are not UTF-8 are escaped
+//! as `\DDD`, and the data is given as hex under `RDATAHEX` too,
+//! which is read first.
+
+use std::collections::BTreeMap;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::errors::{missing_json_rdata, Error, Result};
+
+use super::dns_header::DnsHeader;
+use super::dns_name::DnsName;
+use super::dns_question::DnsQuestion;
+use super::dns_record::{DnsRecord, RecordData};
+use super::packet_buffer::PacketBuffer;
+use super::presentation::to_hex;
+use super::{DnsMessage, QueryType};
+
+#[derive(Serialize, Deserialize)]
+struct MessageRepr {
+    #[serde(rename = "ID")]
+    id: u16,
+    #[serde(rename = "QR")]
+    qr: u8,
+    #[serde(rename = "Opcode")]
+    opcode: u8,
+    #[serde(rename = "AA")]
+    aa: u8,
+    #[serde(rename = "TC")]
+    tc: u8,
+    #[serde(rename = "RD")]
+    rd: u8,
+    #[serde(rename = "RA")]
+    ra: u8,
+    #[serde(rename = "Z", default)]
+    z: u8,
+    #[serde(rename = "AD", default)]
+    ad: u8,
+    #[serde(rename = "CD", default)]
+    cd: u8,
+    #[serde(rename = "RCODE")]
+    rcode: u16,
+    #[serde(rename = "QDCOUNT", default)]
+    qdcount: Option<u16>,
+    #[serde(rename = "ANCOUNT", default)]
+    ancount: Option<u16>,
+    #[serde(rename = "NSCOUNT", default)]
+    nscount: Option<u16>,
+    #[serde(rename = "ARCOUNT", default)]
+    arcount: Option<u16>,
+    #[serde(rename = "questionRRs", default)]
+    questions: Vec<QuestionRepr>,
+    #[serde(rename = "answerRRs", default)]
+    answers: Vec<RecordRepr>,
+    #[serde(rename = "authorityRRs", default)]
+    authorities: Vec<RecordRepr>,
+    #[serde(rename = "additionalRRs", default)]
+    resources: Vec<RecordRepr>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuestionRepr {
+    #[serde(rename = "NAME")]
+    name: DnsName,
+    #[serde(rename = "TYPE")]
+    qtype: u16,
+    #[serde(rename = "TYPEname", default, skip_serializing_if = "Option::is_none")]
+    type_name: Option<String>,
+    #[serde(rename = "CLASS")]
+    class: u16,
+    #[serde(rename = "CLASSname", default, skip_serializing_if = "Option::is_none")]
+    class_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordRepr {
+    #[serde(rename = "NAME")]
+    name: DnsName,
+    #[serde(rename = "TYPE")]
+    qtype: u16,
+    #[serde(rename = "TYPEname", default, skip_serializing_if = "Option::is_none")]
+    type_name: Option<String>,
+    #[serde(rename = "CLASS")]
+    class: u16,
+    #[serde(rename = "CLASSname", default, skip_serializing_if = "Option::is_none")]
+    class_name: Option<String>,
+    #[serde(rename = "TTL")]
+    ttl: u32,
+    /// `rdata<TYPE>` or `RDATAHEX`, along with any other member
+    #[serde(flatten)]
+    rdata: BTreeMap<String, Value>,
+}
+
+impl From<&DnsQuestion> for QuestionRepr {
+    fn from(question: &DnsQuestion) -> Self {
+        Self {
+            name: question.qname.clone(),
+            qtype: question.qtype.into(),
+            type_name: Some(question.qtype.to_string()),
+            class: question.qclass.into(),
+            class_name: Some(question.qclass.to_string()),
+        }
+    }
+}
+
+impl From<QuestionRepr> for DnsQuestion {
+    fn from(repr: QuestionRepr) -> Self {
+        let mut question = DnsQuestion::new(repr.name, repr.qtype.into());
+        question.qclass = repr.class.into();
+        question
+    }
+}
+
+impl From<&DnsRecord> for RecordRepr {
+    fn from(record: &DnsRecord) -> Self {
+        let mut rdata = BTreeMap::new();
+        match record.data {
+            RecordData::UNKNOWN { ref data, .. } => {
+                rdata.insert("RDATAHEX".into(), to_hex(data).into())
+            }
+            ref data => rdata.insert(format!("rdata{}", record.qtype), data.to_string().into()),
+        };
+        if is_binary(&record.data) {
+            if let Some(hex) = rdata_hex(record) {
+                rdata.insert("RDATAHEX".into(), hex.into());
+            }
+        }
+
+        Self {
+            name: record.domain.clone(),
+            qtype: record.qtype.into(),
+            type_name: Some(record.qtype.to_string()),
+            class: record.class.into(),
+            class_name: Some(record.class.to_string()),
+            ttl: record.ttl,
+            rdata,
+        }
+    }
+}
+
+/// Whether `data` has strings that are not UTF-8
+fn is_binary(data: &RecordData) -> bool {
+    let strings: Vec<&[u8]> = match data {
+        RecordData::TXT { data } => data.iter().map(Vec::as_slice).collect(),
+        RecordData::NAPTR {
+            flags,
+            services,
+            regexp,
+            ..
+        } => vec![flags, services, regexp],
+        RecordData::CAA { tag, value, .. } => vec![tag, value],
+        _ => return false,
+    };
+    strings
+        .iter()
+        .any(|bytes| std::str::from_utf8(bytes).is_err())
+}
+
+/// The record data on the wire, in hex
+fn rdata_hex(record: &DnsRecord) -> Option<String> {
+    let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+    record.clone().write(&mut buffer).ok()?;
+    let start = record.domain.wire_len() + 10;
+    Some(to_hex(buffer.get_range(start, buffer.pos() - start).ok()?))
+}
+
+impl TryFrom<RecordRepr> for DnsRecord {
+    type Error = Error;
+
+    fn try_from(repr: RecordRepr) -> Result<Self> {
+        let qtype = QueryType::from(repr.qtype);
+        let data = match (
+            repr.rdata.get("RDATAHEX").and_then(Value::as_str),
+            repr.rdata
+                .get(&format!("rdata{}", qtype))
+                .and_then(Value::as_str),
+        ) {
+            (Some(hex), _) => {
+                RecordData::from_presentation(qtype, &format!("\\# {} {}", hex.len() / 2, hex))?
+            }
+            (None, Some(text)) => RecordData::from_presentation(qtype, text)?,
+            (None, None) => return Err(missing_json_rdata(&repr.name.to_string())),
+        };
+
+        let mut record = DnsRecord::with_data(repr.name, repr.ttl, data);
+        record.qtype = qtype;
+        record.class = repr.class.into();
+        Ok(record)
+    }
+}
+
+impl From<&DnsMessage> for MessageRepr {
+    fn from(message: &DnsMessage) -> Self {
+        let header = &message.header;
+        Self {
+            id: header.id,
+            qr: header.response as u8,
+            opcode: header.opcode.into(),
+            aa: header.authoritative_answer as u8,
+            tc: header.truncated_message as u8,
+            rd: header.recursion_desired as u8,
+            ra: header.recursion_available as u8,
+            z: header.z as u8,
+            ad: header.authed_data as u8,
+            cd: header.checking_disabled as u8,
+            rcode: header.rescode.into(),
+            qdcount: Some(message.questions.len() as u16),
+            ancount: Some(message.answers.len() as u16),
+            nscount: Some(message.authorities.len() as u16),
+            arcount: Some(message.resources.len() as u16),
+            questions: message.questions.iter().map(QuestionRepr::from).collect(),
+            answers: message.answers.iter().map(RecordRepr::from).collect(),
+            authorities: message.authorities.iter().map(RecordRepr::from).collect(),
+            resources: message.resources.iter().map(RecordRepr::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<MessageRepr> for DnsMessage {
+    type Error = Error;
+
+    fn try_from(repr: MessageRepr) -> Result<Self> {
+        let records = |reprs: Vec<RecordRepr>| -> Result<Vec<DnsRecord>> {
+            reprs.into_iter().map(DnsRecord::try_from).collect()
+        };
+
+        let mut header = DnsHeader::new();
+        header.id = repr.id;
+        header.response = repr.qr != 0;
+        header.opcode = (repr.opcode & 0x0F).into();
+        header.authoritative_answer = repr.aa != 0;
+        header.truncated_message = repr.tc != 0;
+        header.recursion_desired = repr.rd != 0;
+        header.recursion_available = repr.ra != 0;
+        header.z = repr.z != 0;
+        header.authed_data = repr.ad != 0;
+        header.checking_disabled = repr.cd != 0;
+        header.rescode = repr.rcode.into();
+
+        let mut message = DnsMessage {
+            header,
+            questions: repr.questions.into_iter().map(DnsQuestion::from).collect(),
+            answers: records(repr.answers)?,
+            authorities: records(repr.authorities)?,
+            resources: records(repr.resources)?,
+            tsig_data: None,
+        };
+        message.header.questions = repr.qdcount.unwrap_or(message.questions.len() as u16);
+        message.header.answers = repr.ancount.unwrap_or(message.answers.len() as u16);
+        message.header.authoritative_entries =
+            repr.nscount.unwrap_or(message.authorities.len() as u16);
+        message.header.resource_entries = repr.arcount.unwrap_or(message.resources.len() as u16);
+        Ok(message)
+    }
+}
+
+impl Serialize for DnsMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        MessageRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let repr = MessageRepr::deserialize(deserializer)?;
+        DnsMessage::try_from(repr).map_err(|err| D::Error::custom(err.to_string()))
+    }
+}
+
+impl Serialize for DnsQuestion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        QuestionRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsQuestion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        QuestionRepr::deserialize(deserializer).map(DnsQuestion::from)
+    }
+}
+
+impl Serialize for DnsRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        RecordRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsRecord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let repr = RecordRepr::deserialize(deserializer)?;
+        DnsRecord::try_from(repr).map_err(|err| D::Error::custom(err.to_string()))
+    }
+}
@@ -0,0 +1,365 @@
+use crate::errors::{bad_compression_pointer, index_out_of_bound, name_len_limit, Result};
+
+use super::dns_header::DnsHeader;
+use super::dns_name::{DnsName, MAX_NAME_LEN};
+use super::dns_question::DnsQuestion;
+use super::dns_record::DnsRecord;
+use super::packet_buffer::{PacketBuffer, HEADER_SIZE};
+use super::{DnsClass, DnsMessage, QueryType};
+
+/// A borrowed view over a DNS message in wire format.
+///
+/// Only the header is parsed up front, out of a copy of its 12
+/// bytes. The sections are walked lazily by the iterators and
+/// names are decoded on demand, so looking at the first question
+/// costs no further allocation. Names are read with the rules of
+/// the strict parsing mode of `PacketBuffer`.
+#[derive(Clone, Debug)]
+pub struct DnsMessageRef<'a> {
+    bytes: &'a [u8],
+    header: DnsHeader,
+}
+
+/// A name inside a message, decoded when iterated over
+#[derive(Clone, Copy, Debug)]
+pub struct NameRef<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QuestionRef<'a> {
+    pub qname: NameRef<'a>,
+    pub qtype: QueryType,
+    pub qclass: DnsClass,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RecordRef<'a> {
+    pub domain: NameRef<'a>,
+    pub qtype: QueryType,
+    pub class: DnsClass,
+    pub ttl: u32,
+    /// The raw record data, names in it may be compressed
+    pub rdata: &'a [u8],
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DnsMessageRef<'a> {
+    /// Reads the header of the message in `bytes`
+    ///
+    /// takes: `&'a [u8]`
+    ///
+    /// returns: `Result<DnsMessageRef<'a>>`
+    pub fn new(bytes: &'a [u8]) -> Result<Self> {
+        let mut header = DnsHeader::new();
+        let mut buffer = PacketBuffer::from_bytes(bytes.get(..HEADER_SIZE).unwrap_or(bytes));
+        header.read(&mut buffer)?;
+        Ok(Self { bytes, header })
+    }
+
+    pub fn header(&self) -> &DnsHeader {
+        &self.header
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn questions(&self) -> Questions<'a> {
+        Questions {
+            bytes: self.bytes,
+            pos: HEADER_SIZE,
+            remaining: self.header.questions,
+        }
+    }
+
+    /// The first question, `None` if there is none
+    pub fn first_question(&self) -> Option<Result<QuestionRef<'a>>> {
+        self.questions().next()
+    }
+
+    pub fn answers(&self) -> Records<'a> {
+        self.records(0, self.header.answers)
+    }
+
+    pub fn authorities(&self) -> Records<'a> {
+        self.records(
+            self.header.answers as u32,
+            self.header.authoritative_entries,
+        )
+    }
+
+    pub fn resources(&self) -> Records<'a> {
+        // the counts come off the wire, their sum may not fit a u16
+        self.records(
+            self.header.answers as u32 + self.header.authoritative_entries as u32,
+            self.header.resource_entries,
+        )
+    }
+
+    fn records(&self, skip: u32, count: u16) -> Records<'a> {
+        Records {
+            bytes: self.bytes,
+            pos: None,
+            skip_questions: self.header.questions,
+            skip_records: skip,
+            remaining: count,
+        }
+    }
+
+    /// Parses the whole message into an owned `DnsMessage`, in
+    /// strict mode.
+    pub fn to_owned(&self) -> Result<DnsMessage> {
+        let mut buffer = PacketBuffer::from_bytes(self.bytes);
+        buffer.set_strict(true);
+        DnsMessage::from_buf(&mut buffer)
+    }
+}
+
+impl<'a> NameRef<'a> {
+    /// Iterates over the labels of the name, following the
+    /// compression pointers.
+    pub fn labels(&self) -> Labels<'a> {
+        Labels {
+            bytes: self.bytes,
+            pos: self.offset,
+            limit: self.offset,
+            len: 1,
+            done: false,
+        }
+    }
+
+    pub fn to_name(&self) -> Result<DnsName> {
+        let mut name = DnsName::root();
+        for label in self.labels() {
+            name.push_label(label?.to_vec())?;
+        }
+        Ok(name)
+    }
+
+    /// Compares the name to `name` without decoding it, ignoring
+    /// ASCII case. A malformed name is equal to no name.
+    pub fn eq_name(&self, name: &DnsName) -> bool {
+        let mut labels = self.labels();
+        for label in name.labels() {
+            match labels.next() {
+                Some(Ok(other)) if other.eq_ignore_ascii_case(label) => {}
+                _ => return false,
+            }
+        }
+        labels.next().is_none()
+    }
+}
+
+impl<'a> QuestionRef<'a> {
+    pub fn to_owned(&self) -> Result<DnsQuestion> {
+        let mut question = DnsQuestion::new(self.qname.to_name()?, self.qtype);
+        question.qclass = self.qclass;
+        Ok(question)
+    }
+}
+
+impl<'a> RecordRef<'a> {
+    /// Parses the record into an owned `DnsRecord`, in strict mode.
+    pub fn to_owned(&self) -> Result<DnsRecord> {
+        let mut buffer = PacketBuffer::from_bytes(self.bytes);
+        buffer.set_strict(true);
+        buffer.seek(self.offset)?;
+        DnsRecord::read(&mut buffer)
+    }
+}
+
+/// Position right after the name starting at `pos`, the name
+/// is not decoded and its pointers are not followed.
+fn skip_name(bytes: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *bytes.get(pos).ok_or_else(index_out_of_bound)?;
+        match len & 0xC0 {
+            0xC0 if pos + 1 < bytes.len() => return Ok(pos + 2),
+            0x00 if len == 0 => return Ok(pos + 1),
+            0x00 => pos += len as usize + 1,
+            _ => return Err(index_out_of_bound()),
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16> {
+    match bytes.get(pos..pos + 2) {
+        Some(raw) => Ok(u16::from_be_bytes([raw[0], raw[1]])),
+        None => Err(index_out_of_bound()),
+    }
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+    match bytes.get(pos..pos + 4) {
+        Some(raw) => Ok(u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]])),
+        None => Err(index_out_of_bound()),
+    }
+}
+
+fn read_question(bytes: &[u8], pos: usize) -> Result<(QuestionRef<'_>, usize)> {
+    let end = skip_name(bytes, pos)?;
+    let question = QuestionRef {
+        qname: NameRef { bytes, offset: pos },
+        qtype: read_u16(bytes, end)?.into(),
+        qclass: read_u16(bytes, end + 2)?.into(),
+    };
+    Ok((question, end + 4))
+}
+
+fn read_record(bytes: &[u8], pos: usize) -> Result<(RecordRef<'_>, usize)> {
+    let end = skip_name(bytes, pos)?;
+    let data_len = read_u16(bytes, end + 8)? as usize;
+    let rdata = bytes
+        .get(end + 10..end + 10 + data_len)
+        .ok_or_else(index_out_of_bound)?;
+    let record = RecordRef {
+        domain: NameRef { bytes, offset: pos },
+        qtype: read_u16(bytes, end)?.into(),
+        class: read_u16(bytes, end + 2)?.into(),
+        ttl: read_u32(bytes, end + 4)?,
+        rdata,
+        bytes,
+        offset: pos,
+    };
+    Ok((record, end + 10 + data_len))
+}
+
+/// Labels of a `NameRef`, an error ends the iteration
+pub struct Labels<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// previous jump target, pointers must point before it
+    limit: usize,
+    /// wire length of the labels read so far
+    len: usize,
+    done: bool,
+}
+
+impl<'a> Labels<'a> {
+    fn next_label(&mut self) -> Result<Option<&'a [u8]>> {
+        loop {
+            let len = *self.bytes.get(self.pos).ok_or_else(index_out_of_bound)?;
+            match len & 0xC0 {
+                0xC0 => {
+                    let offset = (read_u16(self.bytes, self.pos)? & 0x3FFF) as usize;
+                    if offset >= self.limit || offset < HEADER_SIZE {
+                        return Err(bad_compression_pointer(offset));
+                    }
+                    self.pos = offset;
+                    self.limit = offset;
+                }
+                0x00 if len == 0 => return Ok(None),
+                0x00 => {
+                    let start = self.pos + 1;
+                    let label = self
+                        .bytes
+                        .get(start..start + len as usize)
+                        .ok_or_else(index_out_of_bound)?;
+                    self.len += label.len() + 1;
+                    if self.len > MAX_NAME_LEN {
+                        return Err(name_len_limit());
+                    }
+                    self.pos = start + label.len();
+                    return Ok(Some(label));
+                }
+                _ => return Err(index_out_of_bound()),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let label = self.next_label();
+        self.done = !matches!(label, Ok(Some(_)));
+        label.transpose()
+    }
+}
+
+/// Questions of a `DnsMessageRef`, an error ends the iteration
+pub struct Questions<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    remaining: u16,
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = Result<QuestionRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match read_question(self.bytes, self.pos) {
+            Ok((question, end)) => {
+                self.pos = end;
+                Some(Ok(question))
+            }
+            Err(err) => {
+                self.remaining = 0;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Records of a section of a `DnsMessageRef`, the preceding
+/// entries are skipped on the first call to `next`. An error
+/// ends the iteration.
+pub struct Records<'a> {
+    bytes: &'a [u8],
+    pos: Option<usize>,
+    skip_questions: u16,
+    skip_records: u32,
+    remaining: u16,
+}
+
+impl<'a> Records<'a> {
+    fn start(&self) -> Result<usize> {
+        let mut pos = HEADER_SIZE;
+        for _ in 0..self.skip_questions {
+            pos = skip_name(self.bytes, pos)? + 4;
+        }
+        for _ in 0..self.skip_records {
+            pos = read_record(self.bytes, pos)?.1;
+        }
+        Ok(pos)
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<RecordRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let record = match self.pos {
+            Some(pos) => Ok(pos),
+            None => self.start(),
+        }
+        .and_then(|pos| read_record(self.bytes, pos));
+
+        match record {
+            Ok((record, end)) => {
+                self.pos = Some(end);
+                Some(Ok(record))
+            }
+            Err(err) => {
+                self.remaining = 0;
+                Some(Err(err))
+            }
+        }
+    }
+}
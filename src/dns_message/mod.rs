@@ -1,16 +1,42 @@
+pub mod builder;
 pub mod dns_header;
+pub mod dns_name;
 pub mod dns_question;
 pub mod dns_record;
+pub mod edns;
+pub mod json;
+pub mod message_ref;
 pub mod packet_buffer;
+pub mod presentation;
+pub mod tsig;
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash, Copy)]
 pub enum QueryType {
     UNKNOWN(u16),
-    A,     // 1
-    NS,    // 2
-    CNAME, // 5
-    MX,    // 15
-    AAAA,  // 28
+    A,          // 1
+    NS,         // 2
+    CNAME,      // 5
+    SOA,        // 6
+    PTR,        // 12
+    MX,         // 15
+    TXT,        // 16
+    AAAA,       // 28
+    SRV,        // 33
+    NAPTR,      // 35
+    OPT,        // 41
+    DS,         // 43
+    SSHFP,      // 44
+    RRSIG,      // 46
+    NSEC,       // 47
+    DNSKEY,     // 48
+    NSEC3,      // 50
+    NSEC3PARAM, // 51
+    TLSA,       // 52
+    TSIG,       // 250
+    IXFR,       // 251
+    AXFR,       // 252
+    ANY,        // 255
+    CAA,        // 257
 }
 
 impl From<u16> for QueryType {
@@ -19,33 +45,110 @@ impl From<u16> for QueryType {
             1 => Self::A,
             2 => Self::NS,
             5 => Self::CNAME,
+            6 => Self::SOA,
+            12 => Self::PTR,
             15 => Self::MX,
+            16 => Self::TXT,
             28 => Self::AAAA,
+            33 => Self::SRV,
+            35 => Self::NAPTR,
+            41 => Self::OPT,
+            43 => Self::DS,
+            44 => Self::SSHFP,
+            46 => Self::RRSIG,
+            47 => Self::NSEC,
+            48 => Self::DNSKEY,
+            50 => Self::NSEC3,
+            51 => Self::NSEC3PARAM,
+            52 => Self::TLSA,
+            250 => Self::TSIG,
+            251 => Self::IXFR,
+            252 => Self::AXFR,
+            255 => Self::ANY,
+            257 => Self::CAA,
             _ => Self::UNKNOWN(value),
         }
     }
 }
-impl Into<u16> for QueryType {
-    fn into(self) -> u16 {
-        match self {
-            Self::A => 1,
-            Self::NS => 2,
-            Self::CNAME => 5,
-            Self::MX => 15,
-            Self::AAAA => 28,
-            Self::UNKNOWN(value) => value,
+impl From<QueryType> for u16 {
+    fn from(value: QueryType) -> Self {
+        match value {
+            QueryType::A => 1,
+            QueryType::NS => 2,
+            QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
+            QueryType::MX => 15,
+            QueryType::TXT => 16,
+            QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::NAPTR => 35,
+            QueryType::OPT => 41,
+            QueryType::DS => 43,
+            QueryType::SSHFP => 44,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::NSEC3PARAM => 51,
+            QueryType::TLSA => 52,
+            QueryType::TSIG => 250,
+            QueryType::IXFR => 251,
+            QueryType::AXFR => 252,
+            QueryType::ANY => 255,
+            QueryType::CAA => 257,
+            QueryType::UNKNOWN(value) => value,
+        }
+    }
+}
+
+/// The class of a question or a record (RFC 1035 section 3.2.4,
+/// RFC 2136 section 1)
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy)]
+pub enum DnsClass {
+    UNKNOWN(u16),
+    IN,   // 1
+    CH,   // 3
+    HS,   // 4
+    NONE, // 254
+    ANY,  // 255
+}
+
+impl From<u16> for DnsClass {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Self::IN,
+            3 => Self::CH,
+            4 => Self::HS,
+            254 => Self::NONE,
+            255 => Self::ANY,
+            _ => Self::UNKNOWN(value),
+        }
+    }
+}
+impl From<DnsClass> for u16 {
+    fn from(value: DnsClass) -> Self {
+        match value {
+            DnsClass::IN => 1,
+            DnsClass::CH => 3,
+            DnsClass::HS => 4,
+            DnsClass::NONE => 254,
+            DnsClass::ANY => 255,
+            DnsClass::UNKNOWN(value) => value,
         }
     }
 }
 
 use std::net::Ipv4Addr;
 
-use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::packet_buffer::{PacketBuffer, BUF_SIZE};
 use crate::errors::Result;
 
-use self::dns_header::DnsHeader;
+use self::dns_header::{DnsHeader, Opcode, ResultCode};
+use self::dns_name::DnsName;
 use self::dns_question::DnsQuestion;
 use self::dns_record::{DnsRecord, RecordData};
+use self::edns::Edns;
 
 #[derive(Clone, Debug)]
 pub struct DnsMessage {
@@ -54,6 +157,15 @@ pub struct DnsMessage {
     pub answers: Vec<DnsRecord>,
     pub authorities: Vec<DnsRecord>,
     pub resources: Vec<DnsRecord>,
+    /// The message as received up to its TSIG record, the data
+    /// the MAC of the record covers
+    tsig_data: Option<Vec<u8>>,
+}
+
+impl Default for DnsMessage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DnsMessage {
@@ -64,6 +176,7 @@ impl DnsMessage {
             answers: Vec::new(),
             authorities: Vec::new(),
             resources: Vec::new(),
+            tsig_data: None,
         }
     }
 
@@ -71,38 +184,86 @@ impl DnsMessage {
         let mut result = DnsMessage::new();
         result.header.read(buffer)?;
         let header = &result.header;
+        // the prerequisites and updates of an UPDATE message
+        let read = match header.opcode {
+            Opcode::UPDATE => DnsRecord::read_update,
+            _ => DnsRecord::read,
+        };
 
         for _ in 0..header.questions {
-            let mut question = DnsQuestion::new("".into(), QueryType::UNKNOWN(0));
+            let mut question = DnsQuestion::new(DnsName::root(), QueryType::UNKNOWN(0));
             question.read(buffer)?;
             result.questions.push(question);
         }
 
         for _ in 0..header.answers {
-            let rec = DnsRecord::read(buffer)?;
+            let rec = read(buffer)?;
             result.answers.push(rec);
         }
 
         for _ in 0..header.authoritative_entries {
-            let rec = DnsRecord::read(buffer)?;
+            let rec = read(buffer)?;
             result.authorities.push(rec);
         }
 
         for _ in 0..header.resource_entries {
+            let start = buffer.pos();
             let rec = DnsRecord::read(buffer)?;
+            result.tsig_data = match rec.qtype {
+                QueryType::TSIG => Some(buffer.get_range(0, start)?.to_vec()),
+                _ => None,
+            };
             result.resources.push(rec);
         }
 
+        if let Some(edns) = result.edns() {
+            let rescode = u16::from(result.header.rescode) | ((edns.extended_rcode as u16) << 4);
+            result.header.rescode = rescode.into();
+        }
+
         Ok(result)
     }
 
+    /// Builds the FORMERR response to a request that failed to
+    /// parse out of `buffer`, copying what can be read of its
+    /// header.
+    ///
+    /// returns: `Option<DnsMessage>`, `None` when not even the
+    /// header can be read, or when the packet is a response.
+    pub fn format_error(buffer: &mut PacketBuffer) -> Option<DnsMessage> {
+        let mut header = DnsHeader::new();
+        buffer.seek(0).ok()?;
+        header.read(buffer).ok()?;
+        if header.response {
+            return None;
+        }
+
+        let mut message = DnsMessage::new();
+        message.header.id = header.id;
+        message.header.opcode = header.opcode;
+        message.header.recursion_desired = header.recursion_desired;
+        message.header.response = true;
+        message.header.rescode = ResultCode::FORMERR;
+        Some(message)
+    }
+
     pub fn into_buf(&mut self) -> Result<PacketBuffer> {
+        self.into_buf_with_size(BUF_SIZE)
+    }
+
+    /// Writes the message into a buffer of `size` bytes, e.g.
+    /// `u16::MAX` for TCP
+    ///
+    /// returns: `Result<PacketBuffer>`, an error if the message
+    /// does not fit
+    pub fn into_buf_with_size(&mut self, size: usize) -> Result<PacketBuffer> {
         self.header.questions = self.questions.len() as u16;
         self.header.answers = self.answers.len() as u16;
         self.header.authoritative_entries = self.authorities.len() as u16;
         self.header.resource_entries = self.resources.len() as u16;
+        self.write_extended_rcode();
 
-        let mut buffer = PacketBuffer::new();
+        let mut buffer = PacketBuffer::with_size(size);
         self.header.write(&mut buffer)?;
 
         for qst in &self.questions {
@@ -124,6 +285,35 @@ impl DnsMessage {
         Ok(buffer)
     }
 
+    /// The EDNS parameters carried by the OPT record of the
+    /// additional section, if any
+    pub fn edns(&self) -> Option<Edns> {
+        self.resources.iter().find_map(Edns::from_record)
+    }
+
+    /// Stores the upper 8 bits of the response code in the OPT
+    /// record. A code above 15 can not be sent without one, it is
+    /// replaced by `SERVFAIL` rather than cut to its lower 4 bits.
+    fn write_extended_rcode(&mut self) {
+        let extended_rcode = (u16::from(self.header.rescode) >> 4) as u32;
+        let mut has_opt = false;
+        for rec in &mut self.resources {
+            if rec.qtype == QueryType::OPT {
+                rec.ttl = (rec.ttl & 0x00FF_FFFF) | (extended_rcode << 24);
+                has_opt = true;
+            }
+        }
+        if extended_rcode != 0 && !has_opt {
+            self.header.rescode = ResultCode::SERVFAIL;
+        }
+    }
+
+    /// Replaces the OPT record of the additional section
+    pub fn set_edns(&mut self, edns: Edns) {
+        self.resources.retain(|rec| rec.qtype() != QueryType::OPT);
+        self.resources.push(edns.to_record());
+    }
+
     /// Picks a random `DnsRecord` of type `QueryType::A`
     ///
     /// takes: `&self`
@@ -139,42 +329,44 @@ impl DnsMessage {
     /// Picks and `Iterator` over all name servers in the
     /// authorities section
     ///
-    /// takes: `(&'a self, &'a str)` = (DnsMessage, qname)
+    /// takes: `(&'a self, &'a DnsName)` = (DnsMessage, qname)
     ///
-    /// returns: `impl Iterator<Item = (&'a str, &'a str)>`
+    /// returns: `impl Iterator<Item = (&'a DnsName, &'a DnsName)>`
     /// which is an iterator over a tuple (domain, hostname)
-    fn iter_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+    fn iter_ns<'a>(
+        &'a self,
+        qname: &'a DnsName,
+    ) -> impl Iterator<Item = (&'a DnsName, &'a DnsName)> {
         self.authorities
             .iter()
             .filter_map(|rec| match rec.data {
-                RecordData::NS { ref host } => Some((rec.domain.as_str(), host.as_str())),
+                RecordData::NS { ref host } => Some((&rec.domain, host)),
                 _ => None,
             })
-            .filter(move |(domain, _)| qname.ends_with(*domain))
+            .filter(move |(domain, _)| qname.is_subdomain_of(domain))
     }
 
     /// Picks the `Ipv4Addr`  of a resolved nameservers `RecordData::NS`
     ///
-    /// takes: `(&self, &str)` = (DnsMessage, qname)
+    /// takes: `(&self, &DnsName)` = (DnsMessage, qname)
     ///
     /// returns: `Option<Ipv4Addr>`
-    pub fn get_resolved_ns(&self, qname: &str) -> Option<Ipv4Addr> {
+    pub fn get_resolved_ns(&self, qname: &DnsName) -> Option<Ipv4Addr> {
         self.iter_ns(qname)
             .flat_map(|(_, host)| {
                 self.resources.iter().filter_map(move |rec| match rec.data {
-                    RecordData::A { addr } if rec.domain == host => Some(addr),
+                    RecordData::A { addr } if rec.domain == *host => Some(addr),
                     _ => None,
                 })
             })
-            .map(|addr| addr)
             .next()
     }
 
     /// Picks the unresolved nameserver `RecordData::NS`
     ///
-    /// takes: `(&'a self, &'a str)` = (DnsMessage, qname)
-    /// returns: `Option<&'a str>`
-    pub fn get_unresolved_ns<'a>(&'a self, qname: &'a str) -> Option<&'a str> {
-        self.iter_ns(qname).find_map(|(_, host)| Some(host))
+    /// takes: `(&'a self, &'a DnsName)` = (DnsMessage, qname)
+    /// returns: `Option<&'a DnsName>`
+    pub fn get_unresolved_ns<'a>(&'a self, qname: &'a DnsName) -> Option<&'a DnsName> {
+        self.iter_ns(qname).map(|(_, host)| host).next()
     }
 }
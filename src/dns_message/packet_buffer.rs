@@ -1,36 +1,95 @@
-use crate::errors::{index_out_of_bound, jumps_limit, label_len_limit, Result};
+use crate::dns_message::dns_name::DnsName;
+use crate::errors::{
+    bad_compression_pointer, character_string_len_limit, index_out_of_bound, jumps_limit, Result,
+};
 
-const BUF_SIZE: usize = 512;
+/// Size of a UDP message without EDNS (RFC 1035 section 4.2.1)
+pub const BUF_SIZE: usize = 512;
+/// Size of the header, compression pointers may not point into it
+pub const HEADER_SIZE: usize = 12;
 
+/// A DNS message in wire format. Every access is bounds checked
+/// against the length of `buf`, which is the length of the
+/// received packet after `truncate`.
+///
+/// In strict mode, meant for untrusted input, the parser also
+/// enforces the record data lengths and only follows compression
+/// pointers that point backwards, out of the header.
 pub struct PacketBuffer {
-    pub buf: [u8; BUF_SIZE],
+    pub buf: Vec<u8>,
     pub pos: usize,
+    strict: bool,
+}
+
+impl Default for PacketBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PacketBuffer {
     pub fn new() -> Self {
+        Self::with_size(BUF_SIZE)
+    }
+
+    /// A zeroed buffer of `size` bytes, e.g. `u16::MAX` for TCP
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            buf: vec![0; size],
+            pos: 0,
+            strict: false,
+        }
+    }
+
+    /// A buffer holding a copy of `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Self {
         Self {
-            buf: [0; BUF_SIZE],
+            buf: bytes.to_vec(),
             pos: 0,
+            strict: false,
         }
     }
 
+    /// Enables or disables the strict parsing mode
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Shortens the buffer to the `len` bytes actually received
+    pub fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
     pub fn pos(&self) -> usize {
         self.pos
     }
 
     pub fn advance(&mut self, steps: usize) -> Result<()> {
-        self.pos += steps;
-        Ok(())
+        self.seek(self.pos + steps)
     }
 
     pub fn seek(&mut self, pos: usize) -> Result<()> {
+        if pos > self.buf.len() {
+            return Err(index_out_of_bound());
+        }
         self.pos = pos;
         Ok(())
     }
 
     pub fn read(&mut self) -> Result<u8> {
-        if self.pos >= BUF_SIZE {
+        if self.pos >= self.buf.len() {
             return Err(index_out_of_bound());
         }
         let res = self.buf[self.pos];
@@ -40,14 +99,14 @@ impl PacketBuffer {
     }
 
     pub fn get(&self, pos: usize) -> Result<u8> {
-        if pos >= BUF_SIZE {
+        if pos >= self.buf.len() {
             return Err(index_out_of_bound());
         }
         Ok(self.buf[pos])
     }
 
     pub fn get_range(&self, start: usize, len: usize) -> Result<&[u8]> {
-        if (start + len) >= BUF_SIZE {
+        if (start + len) > self.buf.len() {
             return Err(index_out_of_bound());
         }
         Ok(&self.buf[start..start + len])
@@ -66,38 +125,56 @@ impl PacketBuffer {
         Ok(res)
     }
 
-    fn qname_jump(&mut self, pos: &mut usize, len: u8, num_jumps: &mut u8) -> Result<bool> {
-        let byte = self.get(*pos + 1)? as u16;
-        let offset = (((len as u16) ^ 0xC0) << 8) | byte;
-        *pos = offset as usize;
-        *num_jumps += 1;
-        Ok(true)
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let bytes = self.get_range(self.pos, len)?.to_vec();
+        self.pos += len;
+        Ok(bytes)
     }
 
-    /// This function pushes the label (eg. www, google, com)
-    /// and the delimiter '.' into the output string named "outstr"
-    fn qname_push(
+    /// Reads a length prefixed `<character-string>` (RFC 1035 section 3.3)
+    pub fn read_character_string(&mut self) -> Result<Vec<u8>> {
+        let len = self.read()? as usize;
+        self.read_bytes(len)
+    }
+
+    /// Follows the compression pointer at `pos`. In strict mode
+    /// the pointer must point before `limit`, the previous jump
+    /// target, which rules out forward and self pointers as well
+    /// as loops.
+    fn qname_jump(
         &mut self,
-        outstr: &mut String,
-        delim: &str,
         pos: &mut usize,
         len: u8,
-    ) -> Result<()> {
-        outstr.push_str(delim);
+        num_jumps: &mut u8,
+        limit: &mut usize,
+    ) -> Result<bool> {
+        let byte = self.get(*pos + 1)? as u16;
+        let offset = ((((len as u16) ^ 0xC0) << 8) | byte) as usize;
+        if self.strict && (offset >= *limit || offset < HEADER_SIZE) {
+            return Err(bad_compression_pointer(offset));
+        }
+        *pos = offset;
+        *limit = offset;
+        *num_jumps += 1;
+        Ok(true)
+    }
 
-        let str_buf = self.get_range(*pos, len as usize)?;
-        outstr.push_str(&String::from_utf8_lossy(str_buf).to_lowercase());
+    /// This function pushes the label (eg. www, google, com)
+    /// into the output name named "outname"
+    fn qname_push(&mut self, outname: &mut DnsName, pos: &mut usize, len: u8) -> Result<()> {
+        let label = self.get_range(*pos, len as usize)?.to_vec();
+        outname.push_label(label)?;
 
         *pos += len as usize;
         Ok(())
     }
 
-    pub fn read_qname(&mut self, outstr: &mut String) -> Result<()> {
+    pub fn read_qname(&mut self, outname: &mut DnsName) -> Result<()> {
         let mut pos = self.pos();
         let mut jumped = false;
         let mut num_jumps: u8 = 0;
         let max_jumps: u8 = 5;
-        let mut delim = "";
+        let mut limit = pos;
 
         loop {
             if num_jumps > max_jumps {
@@ -108,7 +185,7 @@ impl PacketBuffer {
                 if !jumped {
                     self.seek(pos + 2)?;
                 }
-                jumped = self.qname_jump(&mut pos, len, &mut num_jumps)?;
+                jumped = self.qname_jump(&mut pos, len, &mut num_jumps, &mut limit)?;
                 continue;
             }
 
@@ -117,8 +194,7 @@ impl PacketBuffer {
                 break;
             }
 
-            self.qname_push(outstr, delim, &mut pos, len)?;
-            delim = ".";
+            self.qname_push(outname, &mut pos, len)?;
         }
 
         if !jumped {
@@ -129,7 +205,7 @@ impl PacketBuffer {
     }
 
     pub fn write(&mut self, val: u8) -> Result<()> {
-        if self.pos >= BUF_SIZE {
+        if self.pos >= self.buf.len() {
             return Err(index_out_of_bound());
         }
         self.buf[self.pos] = val;
@@ -139,39 +215,50 @@ impl PacketBuffer {
 
     pub fn write_u16(&mut self, val: u16) -> Result<()> {
         self.write(((val >> 8) & 0xFF) as u8)?;
-        self.write(((val >> 0) & 0xFF) as u8)
+        self.write((val & 0xFF) as u8)
     }
 
     pub fn write_u32(&mut self, val: u32) -> Result<()> {
         self.write(((val >> 24) & 0xFF) as u8)?;
         self.write(((val >> 16) & 0xFF) as u8)?;
         self.write(((val >> 8) & 0xFF) as u8)?;
-        self.write(((val >> 0) & 0xFF) as u8)
+        self.write((val & 0xFF) as u8)
     }
 
-    pub fn write_qname(&mut self, qname: &str) -> Result<()> {
-        for label in qname.split('.') {
-            let len = label.len();
-            if len > 0x3f {
-                return Err(label_len_limit());
-            }
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        for byte in bytes {
+            self.write(*byte)?;
+        }
+        Ok(())
+    }
 
-            self.write(len as u8)?;
-            for byte in label.as_bytes() {
-                self.write(*byte)?;
-            }
+    pub fn write_character_string(&mut self, value: &[u8]) -> Result<()> {
+        let len = value.len();
+        if len > 0xFF {
+            return Err(character_string_len_limit());
+        }
+        self.write(len as u8)?;
+        self.write_bytes(value)
+    }
+
+    pub fn write_qname(&mut self, qname: &DnsName) -> Result<()> {
+        for label in qname.labels() {
+            self.write(label.len() as u8)?;
+            self.write_bytes(label)?;
         }
         self.write(0)
     }
 
     pub fn set(&mut self, pos: usize, val: u8) -> Result<()> {
-        self.buf[pos] = val;
+        match self.buf.get_mut(pos) {
+            Some(byte) => *byte = val,
+            None => return Err(index_out_of_bound()),
+        }
         Ok(())
     }
 
     pub fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
-        self.buf[pos] = ((val >> 8) & 0xFF) as u8;
-        self.buf[pos + 1] = ((val >> 0) & 0xFF) as u8;
-        Ok(())
+        self.set(pos, ((val >> 8) & 0xFF) as u8)?;
+        self.set(pos + 1, (val & 0xFF) as u8)
     }
 }
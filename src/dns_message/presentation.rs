@@ -0,0 +1,1196 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::errors::{invalid_presentation, unknown_qtype, Error, Result};
+
+use super::dns_header::{Opcode, ResultCode};
+use super::dns_name::DnsName;
+use super::dns_question::DnsQuestion;
+use super::dns_record::{DnsRecord, RecordData};
+use super::{DnsClass, DnsMessage, QueryType};
+
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A => write!(f, "A"),
+            Self::NS => write!(f, "NS"),
+            Self::CNAME => write!(f, "CNAME"),
+            Self::SOA => write!(f, "SOA"),
+            Self::PTR => write!(f, "PTR"),
+            Self::MX => write!(f, "MX"),
+            Self::TXT => write!(f, "TXT"),
+            Self::AAAA => write!(f, "AAAA"),
+            Self::SRV => write!(f, "SRV"),
+            Self::NAPTR => write!(f, "NAPTR"),
+            Self::OPT => write!(f, "OPT"),
+            Self::DS => write!(f, "DS"),
+            Self::SSHFP => write!(f, "SSHFP"),
+            Self::RRSIG => write!(f, "RRSIG"),
+            Self::NSEC => write!(f, "NSEC"),
+            Self::DNSKEY => write!(f, "DNSKEY"),
+            Self::NSEC3 => write!(f, "NSEC3"),
+            Self::NSEC3PARAM => write!(f, "NSEC3PARAM"),
+            Self::TLSA => write!(f, "TLSA"),
+            Self::TSIG => write!(f, "TSIG"),
+            Self::IXFR => write!(f, "IXFR"),
+            Self::AXFR => write!(f, "AXFR"),
+            Self::ANY => write!(f, "ANY"),
+            Self::CAA => write!(f, "CAA"),
+            Self::UNKNOWN(value) => write!(f, "TYPE{}", value),
+        }
+    }
+}
+
+impl FromStr for QueryType {
+    type Err = Error;
+
+    /// Parses a type mnemonic (`MX`) or the generic
+    /// `TYPEnnn` notation of RFC 3597.
+    fn from_str(s: &str) -> Result<Self> {
+        let qtype = match s.to_uppercase().as_str() {
+            "A" => Self::A,
+            "NS" => Self::NS,
+            "CNAME" => Self::CNAME,
+            "SOA" => Self::SOA,
+            "PTR" => Self::PTR,
+            "MX" => Self::MX,
+            "TXT" => Self::TXT,
+            "AAAA" => Self::AAAA,
+            "SRV" => Self::SRV,
+            "NAPTR" => Self::NAPTR,
+            "OPT" => Self::OPT,
+            "DS" => Self::DS,
+            "SSHFP" => Self::SSHFP,
+            "RRSIG" => Self::RRSIG,
+            "NSEC" => Self::NSEC,
+            "DNSKEY" => Self::DNSKEY,
+            "NSEC3" => Self::NSEC3,
+            "NSEC3PARAM" => Self::NSEC3PARAM,
+            "TLSA" => Self::TLSA,
+            "TSIG" => Self::TSIG,
+            "IXFR" => Self::IXFR,
+            "AXFR" => Self::AXFR,
+            "ANY" => Self::ANY,
+            "CAA" => Self::CAA,
+            upper => upper
+                .strip_prefix("TYPE")
+                .and_then(|value| value.parse::<u16>().ok())
+                .ok_or_else(|| unknown_qtype(s))?
+                .into(),
+        };
+        Ok(qtype)
+    }
+}
+
+impl fmt::Display for DnsClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IN => write!(f, "IN"),
+            Self::CH => write!(f, "CH"),
+            Self::HS => write!(f, "HS"),
+            Self::NONE => write!(f, "NONE"),
+            Self::ANY => write!(f, "ANY"),
+            Self::UNKNOWN(value) => write!(f, "CLASS{}", value),
+        }
+    }
+}
+
+impl FromStr for DnsClass {
+    type Err = Error;
+
+    /// Parses a class mnemonic (`CH`) or the generic
+    /// `CLASSnnn` notation of RFC 3597.
+    fn from_str(s: &str) -> Result<Self> {
+        let class = match s.to_uppercase().as_str() {
+            "IN" => Self::IN,
+            "CH" => Self::CH,
+            "HS" => Self::HS,
+            "NONE" => Self::NONE,
+            "ANY" => Self::ANY,
+            upper => upper
+                .strip_prefix("CLASS")
+                .and_then(|value| value.parse::<u16>().ok())
+                .ok_or_else(|| invalid_presentation(s))?
+                .into(),
+        };
+        Ok(class)
+    }
+}
+
+impl fmt::Display for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A { addr } => write!(f, "{}", addr),
+            Self::AAAA { addr } => write!(f, "{}", addr),
+            Self::NS { host } | Self::CNAME { host } | Self::PTR { host } => {
+                write!(f, "{}", host)
+            }
+            Self::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            Self::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            Self::MX { priority, host } => write!(f, "{} {}", priority, host),
+            Self::TXT { data } => {
+                let strings: Vec<String> =
+                    data.iter().map(|text| fmt_character_string(text)).collect();
+                write!(f, "{}", strings.join(" "))
+            }
+            Self::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => write!(
+                f,
+                "{} {} {} {} {} {}",
+                order,
+                preference,
+                fmt_character_string(flags),
+                fmt_character_string(services),
+                fmt_character_string(regexp),
+                replacement
+            ),
+            Self::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => write!(
+                f,
+                "{} {} {} {}",
+                key_tag,
+                algorithm,
+                digest_type,
+                to_hex(digest)
+            ),
+            Self::SSHFP {
+                algorithm,
+                fp_type,
+                fingerprint,
+            } => write!(f, "{} {} {}", algorithm, fp_type, to_hex(fingerprint)),
+            Self::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {} {} {}",
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                fmt_time(*expiration),
+                fmt_time(*inception),
+                key_tag,
+                signer,
+                BASE64.encode(signature)
+            ),
+            Self::NSEC { next, types } => write!(f, "{}{}", next, fmt_types(types)),
+            Self::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => write!(
+                f,
+                "{} {} {} {}",
+                flags,
+                protocol,
+                algorithm,
+                BASE64.encode(public_key)
+            ),
+            Self::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } => write!(
+                f,
+                "{} {} {} {} {}{}",
+                hash_algorithm,
+                flags,
+                iterations,
+                fmt_salt(salt),
+                to_base32hex(next_hashed),
+                fmt_types(types)
+            ),
+            Self::NSEC3PARAM {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+            } => write!(
+                f,
+                "{} {} {} {}",
+                hash_algorithm,
+                flags,
+                iterations,
+                fmt_salt(salt)
+            ),
+            Self::TLSA {
+                usage,
+                selector,
+                matching_type,
+                data,
+            } => write!(
+                f,
+                "{} {} {} {}",
+                usage,
+                selector,
+                matching_type,
+                to_hex(data)
+            ),
+            Self::CAA { flags, tag, value } => write!(
+                f,
+                "{} {} {}",
+                flags,
+                escape(tag),
+                fmt_character_string(value)
+            ),
+            Self::OPT { options } => {
+                let mut data = Vec::new();
+                for option in options {
+                    data.extend_from_slice(&option.code.to_be_bytes());
+                    data.extend_from_slice(&(option.data.len() as u16).to_be_bytes());
+                    data.extend_from_slice(&option.data);
+                }
+                fmt_generic(f, &data)
+            }
+            Self::TSIG {
+                algorithm,
+                time_signed,
+                fudge,
+                mac,
+                original_id,
+                error,
+                other,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                algorithm,
+                time_signed,
+                fudge,
+                fmt_sized_base64(mac),
+                original_id,
+                error,
+                fmt_sized_base64(other)
+            ),
+            Self::UNKNOWN { data, .. } => fmt_generic(f, data),
+        }
+    }
+}
+
+impl RecordData {
+    /// Parses the presentation format of the record data of
+    /// type `qtype`, e.g. `10 mail.example.com.` for `MX`.
+    ///
+    /// Any type also accepts the generic `\# <len> <hex>`
+    /// notation of RFC 3597.
+    ///
+    /// takes: `(QueryType, &str)`
+    ///
+    /// returns: `Result<RecordData>`
+    pub fn from_presentation(qtype: QueryType, text: &str) -> Result<Self> {
+        Self::from_tokens(qtype, &tokenize(text)?, None, text)
+    }
+
+    /// Same as `from_presentation` over already tokenized text.
+    /// Names are completed with `origin` when given, as in zone
+    /// files, and are absolute otherwise. `text` is only used for
+    /// error reporting.
+    pub(crate) fn from_tokens(
+        qtype: QueryType,
+        tokens: &[String],
+        origin: Option<&DnsName>,
+        text: &str,
+    ) -> Result<Self> {
+        let mut tokens = Tokens {
+            iter: tokens.iter(),
+            origin,
+            text,
+        };
+
+        if tokens.iter.as_slice().first().map(String::as_str) == Some("\\#") {
+            tokens.next()?;
+            let len: usize = tokens.parse()?;
+            let data = from_hex(&tokens.rest())?;
+            if data.len() != len {
+                return Err(invalid_presentation(text));
+            }
+            return match qtype {
+                QueryType::UNKNOWN(_) | QueryType::IXFR | QueryType::AXFR | QueryType::ANY => {
+                    Ok(Self::UNKNOWN {
+                        qtype: qtype.into(),
+                        data,
+                    })
+                }
+                _ => Self::from_wire(qtype, &data),
+            };
+        }
+
+        let data = match qtype {
+            QueryType::A => Self::A {
+                addr: tokens.parse::<Ipv4Addr>()?,
+            },
+            QueryType::AAAA => Self::AAAA {
+                addr: tokens.parse::<Ipv6Addr>()?,
+            },
+            QueryType::NS => Self::NS {
+                host: tokens.name()?,
+            },
+            QueryType::CNAME => Self::CNAME {
+                host: tokens.name()?,
+            },
+            QueryType::SOA => Self::SOA {
+                mname: tokens.name()?,
+                rname: tokens.name()?,
+                serial: tokens.parse()?,
+                refresh: tokens.ttl()?,
+                retry: tokens.ttl()?,
+                expire: tokens.ttl()?,
+                minimum: tokens.ttl()?,
+            },
+            QueryType::PTR => Self::PTR {
+                host: tokens.name()?,
+            },
+            QueryType::MX => Self::MX {
+                priority: tokens.parse()?,
+                host: tokens.name()?,
+            },
+            QueryType::SRV => Self::SRV {
+                priority: tokens.parse()?,
+                weight: tokens.parse()?,
+                port: tokens.parse()?,
+                target: tokens.name()?,
+            },
+            QueryType::TXT => {
+                let mut data = vec![parse_character_string(tokens.next()?)?];
+                while let Ok(token) = tokens.next() {
+                    data.push(parse_character_string(token)?);
+                }
+                Self::TXT { data }
+            }
+            QueryType::NAPTR => Self::NAPTR {
+                order: tokens.parse()?,
+                preference: tokens.parse()?,
+                flags: parse_character_string(tokens.next()?)?,
+                services: parse_character_string(tokens.next()?)?,
+                regexp: parse_character_string(tokens.next()?)?,
+                replacement: tokens.name()?,
+            },
+            QueryType::DS => Self::DS {
+                key_tag: tokens.parse()?,
+                algorithm: tokens.parse()?,
+                digest_type: tokens.parse()?,
+                digest: from_hex(&tokens.rest())?,
+            },
+            QueryType::RRSIG => Self::RRSIG {
+                type_covered: tokens.parse()?,
+                algorithm: tokens.parse()?,
+                labels: tokens.parse()?,
+                original_ttl: tokens.ttl()?,
+                expiration: parse_time(tokens.next()?)?,
+                inception: parse_time(tokens.next()?)?,
+                key_tag: tokens.parse()?,
+                signer: tokens.name()?,
+                signature: tokens.base64()?,
+            },
+            QueryType::NSEC => Self::NSEC {
+                next: tokens.name()?,
+                types: tokens.types()?,
+            },
+            QueryType::DNSKEY => Self::DNSKEY {
+                flags: tokens.parse()?,
+                protocol: tokens.parse()?,
+                algorithm: tokens.parse()?,
+                public_key: tokens.base64()?,
+            },
+            QueryType::NSEC3 => Self::NSEC3 {
+                hash_algorithm: tokens.parse()?,
+                flags: tokens.parse()?,
+                iterations: tokens.parse()?,
+                salt: parse_salt(tokens.next()?)?,
+                next_hashed: from_base32hex(tokens.next()?)?,
+                types: tokens.types()?,
+            },
+            QueryType::NSEC3PARAM => Self::NSEC3PARAM {
+                hash_algorithm: tokens.parse()?,
+                flags: tokens.parse()?,
+                iterations: tokens.parse()?,
+                salt: parse_salt(tokens.next()?)?,
+            },
+            QueryType::SSHFP => Self::SSHFP {
+                algorithm: tokens.parse()?,
+                fp_type: tokens.parse()?,
+                fingerprint: from_hex(&tokens.rest())?,
+            },
+            QueryType::TLSA => Self::TLSA {
+                usage: tokens.parse()?,
+                selector: tokens.parse()?,
+                matching_type: tokens.parse()?,
+                data: from_hex(&tokens.rest())?,
+            },
+            QueryType::CAA => Self::CAA {
+                flags: tokens.parse()?,
+                tag: parse_character_string(tokens.next()?)?,
+                value: parse_character_string(tokens.next()?)?,
+            },
+            QueryType::TSIG => Self::TSIG {
+                algorithm: tokens.name()?,
+                time_signed: tokens.parse()?,
+                fudge: tokens.parse()?,
+                mac: tokens.sized_base64()?,
+                original_id: tokens.parse()?,
+                error: tokens.parse()?,
+                other: tokens.sized_base64()?,
+            },
+            QueryType::OPT
+            | QueryType::IXFR
+            | QueryType::AXFR
+            | QueryType::ANY
+            | QueryType::UNKNOWN(_) => return Err(invalid_presentation(text)),
+        };
+        tokens.finish()?;
+        Ok(data)
+    }
+
+    /// Decodes uncompressed wire format record data, used for the
+    /// generic notation of known types. The data must span the
+    /// whole record data of the type.
+    fn from_wire(qtype: QueryType, data: &[u8]) -> Result<Self> {
+        use super::dns_record::DnsRecord;
+        use super::packet_buffer::PacketBuffer;
+
+        let len = u16::try_from(data.len()).map_err(|_| invalid_presentation(&to_hex(data)))?;
+        let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+        buffer.write_qname(&DnsName::root())?;
+        buffer.write_u16(qtype.into())?;
+        buffer.write_u16(1)?;
+        buffer.write_u32(0)?;
+        buffer.write_u16(len)?;
+        buffer.write_bytes(data)?;
+        buffer.truncate(buffer.pos());
+        buffer.seek(0)?;
+        buffer.set_strict(true);
+        Ok(DnsRecord::read(&mut buffer)?.data)
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", fmt_opcode((*self).into()))
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = Error;
+
+    /// Parses an opcode mnemonic or its numeric value
+    fn from_str(s: &str) -> Result<Self> {
+        parse_opcode(s).map(Self::from)
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NOERROR => write!(f, "NOERROR"),
+            Self::FORMERR => write!(f, "FORMERR"),
+            Self::SERVFAIL => write!(f, "SERVFAIL"),
+            Self::NXDOMAIN => write!(f, "NXDOMAIN"),
+            Self::NOTIMP => write!(f, "NOTIMP"),
+            Self::REFUSED => write!(f, "REFUSED"),
+            Self::YXDOMAIN => write!(f, "YXDOMAIN"),
+            Self::YXRRSET => write!(f, "YXRRSET"),
+            Self::NXRRSET => write!(f, "NXRRSET"),
+            Self::NOTAUTH => write!(f, "NOTAUTH"),
+            Self::NOTZONE => write!(f, "NOTZONE"),
+            Self::DSOTYPENI => write!(f, "DSOTYPENI"),
+            Self::BADVERS => write!(f, "BADVERS"),
+            Self::BADKEY => write!(f, "BADKEY"),
+            Self::BADTIME => write!(f, "BADTIME"),
+            Self::BADMODE => write!(f, "BADMODE"),
+            Self::BADNAME => write!(f, "BADNAME"),
+            Self::BADALG => write!(f, "BADALG"),
+            Self::BADTRUNC => write!(f, "BADTRUNC"),
+            Self::BADCOOKIE => write!(f, "BADCOOKIE"),
+            Self::UNKNOWN(value) => write!(f, "RCODE{}", value),
+        }
+    }
+}
+
+impl FromStr for ResultCode {
+    type Err = Error;
+
+    /// Parses a response code mnemonic, `BADSIG` being `BADVERS`,
+    /// or the `RCODEnnn` notation of unassigned codes.
+    fn from_str(s: &str) -> Result<Self> {
+        let rescode = match s.to_uppercase().as_str() {
+            "NOERROR" => Self::NOERROR,
+            "FORMERR" => Self::FORMERR,
+            "SERVFAIL" => Self::SERVFAIL,
+            "NXDOMAIN" => Self::NXDOMAIN,
+            "NOTIMP" => Self::NOTIMP,
+            "REFUSED" => Self::REFUSED,
+            "YXDOMAIN" => Self::YXDOMAIN,
+            "YXRRSET" => Self::YXRRSET,
+            "NXRRSET" => Self::NXRRSET,
+            "NOTAUTH" => Self::NOTAUTH,
+            "NOTZONE" => Self::NOTZONE,
+            "DSOTYPENI" => Self::DSOTYPENI,
+            "BADVERS" => Self::BADVERS,
+            "BADKEY" => Self::BADKEY,
+            "BADTIME" => Self::BADTIME,
+            "BADMODE" => Self::BADMODE,
+            "BADNAME" => Self::BADNAME,
+            "BADALG" => Self::BADALG,
+            "BADTRUNC" => Self::BADTRUNC,
+            "BADCOOKIE" => Self::BADCOOKIE,
+            "BADSIG" => Self::BADVERS,
+            upper => upper
+                .strip_prefix("RCODE")
+                .and_then(|value| value.parse::<u16>().ok())
+                .filter(|value| *value <= 0x0FFF)
+                .ok_or_else(|| invalid_presentation(s))?
+                .into(),
+        };
+        Ok(rescode)
+    }
+}
+
+impl fmt::Display for DnsQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t\t{}\t{}", self.qname, self.qclass, self.qtype)
+    }
+}
+
+impl FromStr for DnsQuestion {
+    type Err = Error;
+
+    /// Parses `<name> [<class>] <type>`, the leading `;`
+    /// of dig's question section is accepted.
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s.trim_start().trim_start_matches(';'))?;
+        let (qname, qclass, qtype) = match tokens.as_slice() {
+            [qname, qtype] => (qname, DnsClass::IN, qtype),
+            [qname, qclass, qtype] => (qname, qclass.parse()?, qtype),
+            _ => return Err(invalid_presentation(s)),
+        };
+
+        let mut question = DnsQuestion::new(qname.parse()?, qtype.parse()?);
+        question.qclass = qclass;
+        Ok(question)
+    }
+}
+
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.domain, self.ttl, self.class, self.qtype, self.data
+        )
+    }
+}
+
+impl FromStr for DnsRecord {
+    type Err = Error;
+
+    /// Parses `<name> [<ttl>] [<class>] <type> <rdata>`, the TTL
+    /// and the class may come in any order and default to `0`
+    /// and `IN`.
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut iter = tokens.iter();
+        let domain: DnsName = iter
+            .next()
+            .ok_or_else(|| invalid_presentation(s))?
+            .parse()?;
+
+        let mut ttl = None;
+        let mut class = None;
+        let qtype = loop {
+            let token = iter.next().ok_or_else(|| invalid_presentation(s))?;
+            if let (None, Ok(value)) = (ttl, token.parse::<u32>()) {
+                ttl = Some(value);
+            } else if let (None, Ok(value)) = (class, token.parse::<DnsClass>()) {
+                class = Some(value);
+            } else {
+                break token.parse::<QueryType>()?;
+            }
+        };
+
+        let data = RecordData::from_tokens(qtype, iter.as_slice(), None, s)?;
+        let mut record = DnsRecord::with_data(domain, ttl.unwrap_or(0), data);
+        record.qtype = qtype;
+        record.class = class.unwrap_or(DnsClass::IN);
+        Ok(record)
+    }
+}
+
+impl fmt::Display for DnsMessage {
+    /// Formats the message the way `dig` prints it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            header.opcode, header.rescode, header.id
+        )?;
+
+        let flags = [
+            (header.response, "qr"),
+            (header.authoritative_answer, "aa"),
+            (header.truncated_message, "tc"),
+            (header.recursion_desired, "rd"),
+            (header.recursion_available, "ra"),
+            (header.authed_data, "ad"),
+            (header.checking_disabled, "cd"),
+        ];
+        let flags: Vec<&str> = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| *name)
+            .collect();
+        write!(
+            f,
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            flags.join(" "),
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.resources.len()
+        )?;
+
+        if !self.questions.is_empty() {
+            write!(f, "\n\n;; QUESTION SECTION:")?;
+            for question in &self.questions {
+                write!(f, "\n;{}", question)?;
+            }
+        }
+
+        let sections = [
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.resources),
+        ];
+        for (name, records) in sections {
+            if records.is_empty() {
+                continue;
+            }
+            write!(f, "\n\n;; {} SECTION:", name)?;
+            for record in records {
+                write!(f, "\n{}", record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DnsMessage {
+    type Err = Error;
+
+    /// Parses the output of `DnsMessage`'s `Display`, or of `dig`.
+    /// Comment lines other than the header, the flags and the
+    /// section names are ignored.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut message = DnsMessage::new();
+        let mut section = "";
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(header) = line.strip_prefix(";; ->>HEADER<<-") {
+                for field in header.split(',') {
+                    let (key, value) = field
+                        .split_once(':')
+                        .ok_or_else(|| invalid_presentation(line))?;
+                    let value = value.trim();
+                    match key.trim() {
+                        "opcode" => message.header.opcode = value.parse()?,
+                        "status" => message.header.rescode = value.parse()?,
+                        "id" => {
+                            message.header.id =
+                                value.parse().map_err(|_| invalid_presentation(line))?
+                        }
+                        _ => {}
+                    }
+                }
+            } else if let Some(flags) = line.strip_prefix(";; flags:") {
+                let header = &mut message.header;
+                for flag in flags.split(';').next().unwrap_or("").split_whitespace() {
+                    match flag {
+                        "qr" => header.response = true,
+                        "aa" => header.authoritative_answer = true,
+                        "tc" => header.truncated_message = true,
+                        "rd" => header.recursion_desired = true,
+                        "ra" => header.recursion_available = true,
+                        "ad" => header.authed_data = true,
+                        "cd" => header.checking_disabled = true,
+                        _ => return Err(invalid_presentation(line)),
+                    }
+                }
+            } else if let Some(name) = line
+                .strip_prefix(";; ")
+                .and_then(|line| line.strip_suffix(" SECTION:"))
+            {
+                section = match name {
+                    "QUESTION" | "ANSWER" | "AUTHORITY" | "ADDITIONAL" => name,
+                    _ => "",
+                };
+            } else if line.starts_with(";;") || (line.starts_with(';') && section != "QUESTION") {
+                continue;
+            } else {
+                match section {
+                    "QUESTION" => message.questions.push(line.parse()?),
+                    "ANSWER" => message.answers.push(line.parse()?),
+                    "AUTHORITY" => message.authorities.push(line.parse()?),
+                    "ADDITIONAL" => message.resources.push(line.parse()?),
+                    _ => {}
+                }
+            }
+        }
+
+        message.header.questions = message.questions.len() as u16;
+        message.header.answers = message.answers.len() as u16;
+        message.header.authoritative_entries = message.authorities.len() as u16;
+        message.header.resource_entries = message.resources.len() as u16;
+        Ok(message)
+    }
+}
+
+/// Formats record data in the generic `\# <len> <hex>` notation
+fn fmt_generic(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    match data.is_empty() {
+        true => write!(f, "\\# 0"),
+        false => write!(f, "\\# {} {}", data.len(), to_hex(data)),
+    }
+}
+
+/// Formats a length followed by the base64 of `data`, as the MAC
+/// and other data of TSIG, an empty field is the length alone
+fn fmt_sized_base64(data: &[u8]) -> String {
+    match data.is_empty() {
+        true => "0".to_string(),
+        false => format!("{} {}", data.len(), BASE64.encode(data)),
+    }
+}
+
+/// Consumes the tokens of a presentation format string
+struct Tokens<'a> {
+    iter: std::slice::Iter<'a, String>,
+    /// origin of the relative names, `None` if names are absolute
+    origin: Option<&'a DnsName>,
+    text: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Result<&'a str> {
+        self.iter
+            .next()
+            .map(|token| token.as_str())
+            .ok_or_else(|| invalid_presentation(self.text))
+    }
+
+    fn parse<T: FromStr>(&mut self) -> Result<T> {
+        self.next()?
+            .parse::<T>()
+            .map_err(|_| invalid_presentation(self.text))
+    }
+
+    fn name(&mut self) -> Result<DnsName> {
+        let token = self.next()?;
+        match self.origin {
+            Some(origin) => DnsName::parse_relative(token, origin),
+            None => token.parse(),
+        }
+    }
+
+    fn ttl(&mut self) -> Result<u32> {
+        parse_ttl(self.next()?)
+    }
+
+    /// Concatenates all the remaining tokens, used for hex
+    /// fields which may be split by whitespace
+    fn rest(&mut self) -> String {
+        self.iter.by_ref().map(|token| token.as_str()).collect()
+    }
+
+    /// Decodes all the remaining tokens as base64, which may be
+    /// split by whitespace
+    fn base64(&mut self) -> Result<Vec<u8>> {
+        BASE64
+            .decode(self.rest())
+            .map_err(|_| invalid_presentation(self.text))
+    }
+
+    /// The type list of NSEC and NSEC3 records, all the
+    /// remaining tokens
+    fn types(&mut self) -> Result<Vec<QueryType>> {
+        self.iter.by_ref().map(|token| token.parse()).collect()
+    }
+
+    /// A length and, unless it is zero, the base64 of that many
+    /// bytes
+    fn sized_base64(&mut self) -> Result<Vec<u8>> {
+        let len: usize = self.parse()?;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        BASE64
+            .decode(self.next()?)
+            .ok()
+            .filter(|data| data.len() == len)
+            .ok_or_else(|| invalid_presentation(self.text))
+    }
+
+    fn finish(mut self) -> Result<()> {
+        match self.iter.next() {
+            Some(_) => Err(invalid_presentation(self.text)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits a presentation format string on whitespace,
+/// quoted strings count as a single token. The surrounding
+/// quotes are removed, escape sequences are kept as they are.
+///
+/// takes: `&str`
+///
+/// returns: `Result<Vec<String>>`
+pub fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let quoted = c == '"';
+        if quoted {
+            chars.next();
+        }
+
+        let mut token = String::new();
+        let mut closed = !quoted;
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    token.push(c);
+                    token.push(chars.next().ok_or_else(|| invalid_presentation(text))?);
+                }
+                '"' if quoted => {
+                    closed = true;
+                    break;
+                }
+                c if c.is_whitespace() && !quoted => break,
+                c => token.push(c),
+            }
+        }
+        if !closed {
+            return Err(invalid_presentation(text));
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Decodes the `\X` and `\DDD` escapes of a `<character-string>`
+pub fn parse_character_string(token: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(token.len());
+    let mut iter = token.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match iter.next() {
+            Some(digit) if digit.is_ascii_digit() => {
+                let digits = [Some(digit), iter.next(), iter.next()];
+                let value = digits
+                    .iter()
+                    .try_fold(0u16, |acc, digit| match digit {
+                        Some(d) if d.is_ascii_digit() => Some(acc * 10 + (d - b'0') as u16),
+                        _ => None,
+                    })
+                    .filter(|value| *value <= 0xFF)
+                    .ok_or_else(|| invalid_presentation(token))?;
+                bytes.push(value as u8);
+            }
+            Some(escaped) => bytes.push(escaped),
+            None => return Err(invalid_presentation(token)),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Quotes a `<character-string>`, escaping quotes, backslashes
+/// and non printable bytes.
+pub fn fmt_character_string(bytes: &[u8]) -> String {
+    format!("\"{}\"", escape(bytes))
+}
+
+/// `bytes` as text, quotes, backslashes and non printable bytes
+/// escaped
+pub fn escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            0x20..=0x7E => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+    out
+}
+
+/// Formats a header opcode as its mnemonic
+pub fn fmt_opcode(opcode: u8) -> String {
+    match opcode {
+        0 => "QUERY".into(),
+        1 => "IQUERY".into(),
+        2 => "STATUS".into(),
+        4 => "NOTIFY".into(),
+        5 => "UPDATE".into(),
+        6 => "DSO".into(),
+        _ => opcode.to_string(),
+    }
+}
+
+pub fn parse_opcode(token: &str) -> Result<u8> {
+    let opcode = match token.to_uppercase().as_str() {
+        "QUERY" => 0,
+        "IQUERY" => 1,
+        "STATUS" => 2,
+        "NOTIFY" => 4,
+        "UPDATE" => 5,
+        "DSO" => 6,
+        _ => token
+            .parse::<u8>()
+            .ok()
+            .filter(|opcode| *opcode <= 0x0F)
+            .ok_or_else(|| invalid_presentation(token))?,
+    };
+    Ok(opcode)
+}
+
+/// Parses a TTL given in seconds (`3600`) or with the units of
+/// BIND (`1h`, `1w2d`), as found in zone files
+pub fn parse_ttl(token: &str) -> Result<u32> {
+    if let Ok(ttl) = token.parse::<u32>() {
+        return Ok(ttl);
+    }
+
+    let mut ttl: u32 = 0;
+    let mut value: Option<u32> = None;
+    for c in token.chars() {
+        let unit = match c.to_ascii_lowercase() {
+            digit if digit.is_ascii_digit() => {
+                let digit = digit.to_digit(10).unwrap_or(0);
+                value = value
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(digit));
+                if value.is_none() {
+                    return Err(invalid_presentation(token));
+                }
+                continue;
+            }
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return Err(invalid_presentation(token)),
+        };
+        ttl = value
+            .take()
+            .and_then(|value| value.checked_mul(unit))
+            .and_then(|value| ttl.checked_add(value))
+            .ok_or_else(|| invalid_presentation(token))?;
+    }
+    match value {
+        None if !token.is_empty() => Ok(ttl),
+        _ => Err(invalid_presentation(token)),
+    }
+}
+
+/// Formats the type list of NSEC and NSEC3 records, each type
+/// preceded by a space
+fn fmt_types(types: &[QueryType]) -> String {
+    types.iter().map(|qtype| format!(" {}", qtype)).collect()
+}
+
+/// The NSEC3 salt in hex, `-` when empty
+fn fmt_salt(salt: &[u8]) -> String {
+    match salt.is_empty() {
+        true => "-".to_string(),
+        false => to_hex(salt),
+    }
+}
+
+fn parse_salt(token: &str) -> Result<Vec<u8>> {
+    match token {
+        "-" => Ok(Vec::new()),
+        _ => from_hex(token),
+    }
+}
+
+/// Formats a timestamp of RRSIG records as `YYYYMMDDHHmmSS` in
+/// UTC (RFC 4034 section 3.2)
+pub fn fmt_time(time: u32) -> String {
+    let days = (time / 86400) as i64;
+    let seconds = time % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parses a timestamp of RRSIG records, `YYYYMMDDHHmmSS` or a
+/// number of seconds since the epoch
+pub fn parse_time(token: &str) -> Result<u32> {
+    let invalid = || invalid_presentation(token);
+    if token.len() != 14 {
+        return token.parse().map_err(|_| invalid());
+    }
+    let field = |range: std::ops::Range<usize>| -> Result<i64> {
+        token
+            .get(range)
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(invalid)
+    };
+    let (month, day) = (field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return Err(invalid());
+    }
+    let days = days_from_civil(field(0..4)?, month, day);
+    u32::try_from(days * 86400 + hour * 3600 + minute * 60 + second).map_err(|_| invalid())
+}
+
+/// The date of a day since the epoch, H. Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The day since the epoch of a date, H. Hinnant's algorithm
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+const BASE32HEX: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+/// Encodes `bytes` in base32 with the extended hex alphabet and
+/// no padding (RFC 4648 section 7), as NSEC3 hashes
+pub fn to_base32hex(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut block = [0u8; 5];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let bits = block
+            .iter()
+            .fold(0u64, |bits, byte| bits << 8 | *byte as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            let index = (bits >> (35 - i * 5)) & 0x1F;
+            text.push(BASE32HEX[index as usize] as char);
+        }
+    }
+    text
+}
+
+pub fn from_base32hex(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut bits, mut len) = (0u32, 0);
+    for c in text.bytes() {
+        let value = BASE32HEX
+            .iter()
+            .position(|digit| *digit == c.to_ascii_lowercase())
+            .ok_or_else(|| invalid_presentation(text))?;
+        bits = (bits << 5) | value as u32;
+        len += 5;
+        if len >= 8 {
+            len -= 8;
+            bytes.push((bits >> len) as u8);
+            bits &= (1 << len) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+pub fn from_hex(text: &str) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid_presentation(text));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| invalid_presentation(text))
+        })
+        .collect()
+}
@@ -0,0 +1,432 @@
+//! Transaction signatures (RFC 8945): an HMAC of the message,
+//! keyed with a secret shared by the two ends, carried by a TSIG
+//! record at the end of the additional section.
+//!
+//! A `Signer` follows a whole transaction: the MAC of a request
+//! is covered by the MAC of its response, and each message of a
+//! multi-message response by the MAC of the next one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use sha2::{Sha256, Sha384, Sha512};
+
+use crate::errors::{unknown_tsig_algorithm, Error, Result};
+
+use super::dns_header::ResultCode;
+use super::dns_name::DnsName;
+use super::dns_record::{DnsRecord, RecordData};
+use super::{DnsClass, DnsMessage, QueryType};
+
+/// Seconds of clock skew allowed between the two ends
+pub const FUDGE: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+/// A shared secret, named as the TSIG records signed with it
+#[derive(Clone, Debug, Deserialize)]
+pub struct TsigKey {
+    pub name: DnsName,
+    pub algorithm: Algorithm,
+    /// base64 in the settings
+    #[serde(deserialize_with = "base64_secret")]
+    pub secret: Vec<u8>,
+}
+
+/// The keys known to the server, by name
+#[derive(Clone, Debug, Default)]
+pub struct Keyring {
+    keys: HashMap<DnsName, TsigKey>,
+}
+
+/// Signs and verifies the messages of a transaction with `key`
+#[derive(Clone, Debug)]
+pub struct Signer {
+    key: TsigKey,
+    /// MAC of the previous message, covered by the next one
+    mac: Option<Vec<u8>>,
+    /// responses signed or verified so far, all but the first
+    /// cover the timers alone (RFC 8945 section 5.3.1)
+    responses: usize,
+}
+
+/// The TSIG fields covered by the MAC
+struct Variables<'a> {
+    algorithm: &'a DnsName,
+    time_signed: u64,
+    fudge: u16,
+    error: u16,
+    other: &'a [u8],
+}
+
+impl Algorithm {
+    pub fn name(&self) -> DnsName {
+        let name = match self {
+            Self::HmacSha256 => "hmac-sha256.",
+            Self::HmacSha384 => "hmac-sha384.",
+            Self::HmacSha512 => "hmac-sha512.",
+        };
+        name.parse().expect("Algorithm names are valid")
+    }
+
+    fn mac(&self, secret: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::HmacSha256 => compute::<Hmac<Sha256>>(secret, data),
+            Self::HmacSha384 => compute::<Hmac<Sha384>>(secret, data),
+            Self::HmacSha512 => compute::<Hmac<Sha512>>(secret, data),
+        }
+    }
+
+    fn verify(&self, secret: &[u8], data: &[u8], mac: &[u8]) -> bool {
+        match self {
+            Self::HmacSha256 => check::<Hmac<Sha256>>(secret, data, mac),
+            Self::HmacSha384 => check::<Hmac<Sha384>>(secret, data, mac),
+            Self::HmacSha512 => check::<Hmac<Sha512>>(secret, data, mac),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    /// Parses an algorithm name, `hmac-sha256` or `hmac-sha256.`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim_end_matches('.').to_lowercase().as_str() {
+            "hmac-sha256" => Ok(Self::HmacSha256),
+            "hmac-sha384" => Ok(Self::HmacSha384),
+            "hmac-sha512" => Ok(Self::HmacSha512),
+            _ => Err(unknown_tsig_algorithm(s)),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Algorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse()
+            .map_err(|err: Error| D::Error::custom(err.to_string()))
+    }
+}
+
+fn base64_secret<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    BASE64
+        .decode(text.trim())
+        .map_err(|err| D::Error::custom(format!("Invalid TSIG secret, {}", err)))
+}
+
+fn compute<M: Mac + KeyInit>(secret: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn check<M: Mac + KeyInit>(secret: &[u8], data: &[u8], mac: &[u8]) -> bool {
+    let mut hmac = <M as KeyInit>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    hmac.update(data);
+    hmac.verify_slice(mac).is_ok()
+}
+
+impl Keyring {
+    pub fn new(keys: Vec<TsigKey>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| (key.name.clone(), key))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, name: &DnsName) -> Option<&TsigKey> {
+        self.keys.get(name)
+    }
+
+    /// Verifies the TSIG record of `request`, if any
+    ///
+    /// takes: `&DnsMessage`
+    ///
+    /// returns: `Result<Option<Signer>, ResultCode>`, the signer
+    /// of the responses of a signed request, `None` when it is
+    /// not signed, or FORMERR, BADKEY, BADSIG or BADTIME
+    pub fn verify(&self, request: &DnsMessage) -> std::result::Result<Option<Signer>, ResultCode> {
+        let Some((tsig, _)) = signature(request)? else {
+            return Ok(None);
+        };
+        let key = self.get(&tsig.domain).ok_or(ResultCode::BADKEY)?;
+        let mut signer = Signer::new(key.clone());
+        signer.verify(request)?;
+        Ok(Some(signer))
+    }
+
+    /// The response to a request whose TSIG record failed with
+    /// `rescode` (RFC 8945 section 5.2): NOTAUTH carrying the
+    /// error in an unsigned TSIG record, but for BADTIME which is
+    /// signed and tells the time of the server.
+    ///
+    /// takes: `(&self, &DnsMessage, ResultCode)`
+    ///
+    /// returns: `DnsMessage`
+    pub fn error_response(&self, request: &DnsMessage, rescode: ResultCode) -> DnsMessage {
+        let tsig = signature(request).ok().flatten().map(|(tsig, _)| tsig);
+        let Some(tsig) = tsig.filter(|_| rescode != ResultCode::FORMERR) else {
+            return DnsMessage::response_to(request)
+                .rescode(ResultCode::FORMERR)
+                .build();
+        };
+        let RecordData::TSIG {
+            ref algorithm,
+            time_signed,
+            fudge,
+            ref mac,
+            ..
+        } = tsig.data
+        else {
+            unreachable!("signature only returns TSIG records");
+        };
+        let mut response = DnsMessage::response_to(request)
+            .rescode(ResultCode::NOTAUTH)
+            .build();
+
+        let key = self.get(&tsig.domain);
+        if let (ResultCode::BADTIME, Some(key)) = (rescode, key) {
+            let mut signer = Signer::new(key.clone());
+            signer.mac = Some(mac.clone());
+            let variables = Variables {
+                algorithm: &key.algorithm.name(),
+                time_signed,
+                fudge,
+                error: rescode.into(),
+                other: &now().to_be_bytes()[2..],
+            };
+            if signer.sign_with(&mut response, &variables).is_ok() {
+                return response;
+            }
+        }
+        response.resources.push(tsig_record(
+            &tsig.domain,
+            RecordData::TSIG {
+                algorithm: algorithm.clone(),
+                time_signed,
+                fudge,
+                mac: Vec::new(),
+                original_id: request.header.id,
+                error: rescode.into(),
+                other: Vec::new(),
+            },
+        ));
+        response
+    }
+}
+
+impl Signer {
+    pub fn new(key: TsigKey) -> Self {
+        Self {
+            key,
+            mac: None,
+            responses: 0,
+        }
+    }
+
+    pub fn key(&self) -> &TsigKey {
+        &self.key
+    }
+
+    /// Signs `message`, the next one of the transaction, with
+    /// the current time
+    pub fn sign(&mut self, message: &mut DnsMessage) -> Result<()> {
+        self.sign_at(message, now())
+    }
+
+    /// Signs `message` as if it was `time_signed` seconds since
+    /// the epoch
+    pub fn sign_at(&mut self, message: &mut DnsMessage, time_signed: u64) -> Result<()> {
+        let algorithm = self.key.algorithm.name();
+        let variables = Variables {
+            algorithm: &algorithm,
+            time_signed,
+            fudge: FUDGE,
+            error: 0,
+            other: &[],
+        };
+        self.sign_with(message, &variables)
+    }
+
+    fn sign_with(&mut self, message: &mut DnsMessage, variables: &Variables) -> Result<()> {
+        let buffer = message.clone().into_buf_with_size(u16::MAX as usize)?;
+        let data = self.digest(
+            buffer.get_range(0, buffer.pos())?,
+            message.header.response,
+            variables,
+        );
+        let mac = self.key.algorithm.mac(&self.key.secret, &data);
+
+        message.resources.push(tsig_record(
+            &self.key.name,
+            RecordData::TSIG {
+                algorithm: variables.algorithm.clone(),
+                time_signed: variables.time_signed,
+                fudge: variables.fudge,
+                mac: mac.clone(),
+                original_id: message.header.id,
+                error: variables.error,
+                other: variables.other.to_vec(),
+            },
+        ));
+        self.advance(message, mac);
+        Ok(())
+    }
+
+    /// Verifies the TSIG record of `message`, the next one of the
+    /// transaction, which must be signed with the key of the
+    /// signer.
+    ///
+    /// returns: `Result<(), ResultCode>`, FORMERR, BADKEY, BADSIG
+    /// or BADTIME, or the error reported by the other end
+    pub fn verify(&mut self, message: &DnsMessage) -> std::result::Result<(), ResultCode> {
+        let (tsig, mut data) = signature(message)?.ok_or(ResultCode::FORMERR)?;
+        let RecordData::TSIG {
+            ref algorithm,
+            time_signed,
+            fudge,
+            ref mac,
+            original_id,
+            error,
+            ref other,
+        } = tsig.data
+        else {
+            unreachable!("signature only returns TSIG records");
+        };
+        if tsig.domain != self.key.name || *algorithm != self.key.algorithm.name() {
+            return Err(ResultCode::BADKEY);
+        }
+        if mac.is_empty() && error != 0 {
+            return Err(error.into());
+        }
+
+        // the message as it was signed: with its original id and
+        // without its TSIG record
+        data[..2].copy_from_slice(&original_id.to_be_bytes());
+        let arcount = u16::from_be_bytes([data[10], data[11]]) - 1;
+        data[10..12].copy_from_slice(&arcount.to_be_bytes());
+
+        let variables = Variables {
+            algorithm,
+            time_signed,
+            fudge,
+            error,
+            other,
+        };
+        let data = self.digest(&data, message.header.response, &variables);
+        if !self.key.algorithm.verify(&self.key.secret, &data, mac) {
+            return Err(ResultCode::BADSIG);
+        }
+        if now().abs_diff(time_signed) > fudge.into() {
+            return Err(ResultCode::BADTIME);
+        }
+        if error != 0 {
+            return Err(error.into());
+        }
+        self.advance(message, mac.clone());
+        Ok(())
+    }
+
+    /// The data covered by the MAC of a message (RFC 8945 section
+    /// 4.3): the previous MAC, the message and the TSIG variables,
+    /// or only their timers past the first response
+    fn digest(&self, message: &[u8], response: bool, variables: &Variables) -> Vec<u8> {
+        let mut data = Vec::new();
+        if let Some(mac) = &self.mac {
+            data.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+            data.extend_from_slice(mac);
+        }
+        data.extend_from_slice(message);
+        if !(response && self.responses > 0) {
+            data.extend(self.key.name.to_lowercase().to_wire());
+            data.extend_from_slice(&u16::from(DnsClass::ANY).to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend(variables.algorithm.to_lowercase().to_wire());
+        }
+        data.extend_from_slice(&variables.time_signed.to_be_bytes()[2..]);
+        data.extend_from_slice(&variables.fudge.to_be_bytes());
+        if !(response && self.responses > 0) {
+            data.extend_from_slice(&variables.error.to_be_bytes());
+            data.extend_from_slice(&(variables.other.len() as u16).to_be_bytes());
+            data.extend_from_slice(variables.other);
+        }
+        data
+    }
+
+    fn advance(&mut self, message: &DnsMessage, mac: Vec<u8>) {
+        self.mac = Some(mac);
+        if message.header.response {
+            self.responses += 1;
+        }
+    }
+}
+
+/// The TSIG record of `message` with the data its MAC covers, as
+/// received
+///
+/// returns: `Result<Option<(&DnsRecord, Vec<u8>)>, ResultCode>`,
+/// `None` when the message is not signed, FORMERR unless the
+/// record is the last of the additional section and the only one
+fn signature(
+    message: &DnsMessage,
+) -> std::result::Result<Option<(&DnsRecord, Vec<u8>)>, ResultCode> {
+    let count = message
+        .answers
+        .iter()
+        .chain(&message.authorities)
+        .chain(&message.resources)
+        .filter(|rec| rec.qtype() == QueryType::TSIG)
+        .count();
+    match (count, message.resources.last(), &message.tsig_data) {
+        (0, _, _) => Ok(None),
+        (1, Some(tsig), Some(data)) if matches!(tsig.data, RecordData::TSIG { .. }) => {
+            Ok(Some((tsig, data.clone())))
+        }
+        _ => Err(ResultCode::FORMERR),
+    }
+}
+
+/// The name of a TSIG error, `BADSIG` rather than `BADVERS`
+pub fn error_name(rescode: ResultCode) -> String {
+    match rescode {
+        ResultCode::BADSIG => "BADSIG".to_string(),
+        rescode => rescode.to_string(),
+    }
+}
+
+fn tsig_record(name: &DnsName, data: RecordData) -> DnsRecord {
+    let mut record = DnsRecord::with_data(name.clone(), 0, data);
+    record.class = DnsClass::ANY;
+    record
+}
+
+/// Seconds since the epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
@@ -0,0 +1,124 @@
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::edns::Edns;
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::dns_resolver::upstream::Upstream;
+use crate::errors::{no_upstream_answered, Result};
+use crate::settings::config::{Fallback, ForwardSettings};
+
+/// Forwards the queries to upstream resolvers, tried in order
+/// until one of them answers
+pub struct Forwarder {
+    upstreams: Vec<Upstream>,
+    fallback: Fallback,
+    random: SystemRandom,
+}
+
+impl Forwarder {
+    /// takes: `&ForwardSettings`
+    ///
+    /// returns: `Result<Forwarder>`, an error if an upstream is
+    /// invalid
+    pub fn new(settings: &ForwardSettings) -> Result<Self> {
+        Ok(Self {
+            upstreams: settings
+                .upstreams
+                .iter()
+                .map(Upstream::new)
+                .collect::<Result<_>>()?,
+            fallback: settings.fallback,
+            random: SystemRandom::new(),
+        })
+    }
+
+    pub fn upstreams(&self) -> &[Upstream] {
+        &self.upstreams
+    }
+
+    /// Sends `request` to the upstreams in order, as long as the
+    /// fallback policy allows. SERVFAIL and REFUSED count as
+    /// failures, the last of them is returned when no upstream
+    /// does better.
+    ///
+    /// takes: `(&self, &DnsMessage)`
+    ///
+    /// returns: `Result<DnsMessage>`, an error if no upstream
+    /// answered
+    pub async fn forward(&self, request: &DnsMessage) -> Result<DnsMessage> {
+        let mut failed_encrypted = false;
+        let mut last = None;
+        for upstream in &self.upstreams {
+            if failed_encrypted && !upstream.is_encrypted() {
+                continue;
+            }
+            match upstream.exchange(request).await {
+                Ok(response) => match response.header.rescode {
+                    ResultCode::SERVFAIL | ResultCode::REFUSED => {
+                        println!("Upstream {} answered {}", upstream, response.header.rescode);
+                        last = Some(response);
+                    }
+                    _ => return Ok(response),
+                },
+                Err(err) => println!("Failed to forward to {}: {}", upstream, err),
+            }
+
+            match self.fallback {
+                Fallback::Any => {}
+                Fallback::Encrypted => failed_encrypted |= upstream.is_encrypted(),
+                Fallback::None => break,
+            }
+        }
+        last.ok_or_else(no_upstream_answered)
+    }
+
+    /// Answers `request` by forwarding its first question, under
+    /// a fresh random id, with the DO bit of the client
+    ///
+    /// takes: `(&self, &DnsMessage)`
+    ///
+    /// returns: `DnsMessage`
+    pub async fn handle_query(&self, request: &DnsMessage) -> DnsMessage {
+        let mut message = DnsMessage::response_to(request)
+            .recursion_available(true)
+            .build();
+
+        let Some(question) = request.questions.first() else {
+            message.header.rescode = ResultCode::FORMERR;
+            return message;
+        };
+        let mut id = [0; 2];
+        if self.random.fill(&mut id).is_err() {
+            message.header.rescode = ResultCode::SERVFAIL;
+            return message;
+        }
+        let edns = Edns {
+            dnssec_ok: request.edns().is_some_and(|edns| edns.dnssec_ok),
+            ..Edns::new()
+        };
+        let query = DnsMessage::query(&question.qname, question.qtype)
+            .id(u16::from_be_bytes(id))
+            .recursion_desired(true)
+            .edns(edns)
+            .build();
+
+        match self.forward(&query).await {
+            Ok(result) => {
+                message.header.rescode = result.header.rescode;
+                message.answers.extend(result.answers);
+                message.authorities.extend(result.authorities);
+                message.resources.extend(
+                    result
+                        .resources
+                        .into_iter()
+                        .filter(|rec| rec.qtype() != QueryType::OPT),
+                );
+            }
+            Err(err) => {
+                println!("Failed to forward {}: {}", question, err);
+                message.header.rescode = ResultCode::SERVFAIL;
+            }
+        }
+        message
+    }
+}
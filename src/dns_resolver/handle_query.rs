@@ -1,39 +1,65 @@
-use tokio::net::UdpSocket;
-
 use crate::dns_message::dns_header::ResultCode;
-use crate::dns_message::packet_buffer::PacketBuffer;
-use crate::dns_message::DnsMessage;
+use crate::dns_message::dns_record::RecordData;
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::dns_resolver::forward::Forwarder;
+use crate::dns_resolver::hosts::Hosts;
 use crate::dns_resolver::lookup::recursive_lookup;
-use crate::errors::Result;
-
-/// Handle a single incoming packet
-pub async fn handle_query(socket: &UdpSocket) -> Result<()> {
-    let mut recv_buffer = PacketBuffer::new();
 
-    let (_, src) = socket.recv_from(&mut recv_buffer.buf).await?;
-
-    let mut request = DnsMessage::from_buf(&mut recv_buffer)?;
+/// Answers `request` from the static records of `hosts`, or else
+/// through `forwarder` or by resolving its first question
+/// recursively. A static CNAME record whose target is not static
+/// gets the answers for its target.
+///
+/// takes: `(&DnsMessage, &Hosts, Option<&Forwarder>)`
+///
+/// returns: `DnsMessage`
+pub async fn handle_query(
+    request: &DnsMessage,
+    hosts: &Hosts,
+    forwarder: Option<&Forwarder>,
+) -> DnsMessage {
+    let Some(mut message) = hosts.handle_query(request) else {
+        return resolve(request, forwarder).await;
+    };
+    let qtype = request.questions[0].qtype;
+    let target = match message.answers.last().map(|rec| &rec.data) {
+        Some(RecordData::CNAME { host })
+            if qtype != QueryType::CNAME && qtype != QueryType::ANY && !hosts.contains(host) =>
+        {
+            Some(host.clone())
+        }
+        _ => None,
+    };
+    if let Some(target) = target {
+        let mut chased = request.clone();
+        chased.questions[0].qname = target;
+        let response = resolve(&chased, forwarder).await;
+        message.header.authoritative_answer = false;
+        message.header.rescode = response.header.rescode;
+        message.answers.extend(response.answers);
+    }
+    message
+}
 
-    let mut message = DnsMessage::new();
-    message.header.id = request.header.id;
-    message.header.recursion_desired = true;
-    message.header.recursion_available = true;
-    message.header.response = true;
+async fn resolve(request: &DnsMessage, forwarder: Option<&Forwarder>) -> DnsMessage {
+    if let Some(forwarder) = forwarder {
+        return forwarder.handle_query(request).await;
+    }
+    let mut message = DnsMessage::response_to(request)
+        .recursion_available(true)
+        .build();
 
-    if let Some(question) = request.questions.pop() {
-        println!("Received query: {:#?}", question);
+    if let Some(question) = request.questions.first() {
         if let Ok(result) = recursive_lookup(&question.qname, question.qtype).await {
-            message.questions.push(question);
             message.header.rescode = result.header.rescode;
-            for rec in result.answers {
-                message.answers.push(rec);
-            }
-            for rec in result.authorities {
-                message.authorities.push(rec);
-            }
-            for rec in result.resources {
-                message.resources.push(rec);
-            }
+            message.answers.extend(result.answers);
+            message.authorities.extend(result.authorities);
+            message.resources.extend(
+                result
+                    .resources
+                    .into_iter()
+                    .filter(|rec| rec.qtype() != QueryType::OPT),
+            );
         } else {
             message.header.rescode = ResultCode::SERVFAIL;
         }
@@ -41,12 +67,6 @@ pub async fn handle_query(socket: &UdpSocket) -> Result<()> {
         message.header.rescode = ResultCode::FORMERR;
     }
 
-    let send_buffer = message.into_buf()?;
-    println!("message {:#?}", message);
-    let len = send_buffer.pos();
-    println!("The data length is {}", len);
-    let data = send_buffer.get_range(0, len)?;
-    socket.send_to(data, src).await?;
-
-    Ok(())
+    println!("{}", message);
+    message
 }
@@ -0,0 +1,208 @@
+//! Static records: names answered by the resolver itself, out of
+//! hosts files and the settings, before any recursion.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::errors::{invalid_hosts_file, invalid_static_record, Error, Result};
+use crate::settings::config::{HostsSettings, StaticRecordType};
+
+/// Longest chain of CNAME records followed in the table
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// The static records by name
+#[derive(Default)]
+pub struct Hosts {
+    records: HashMap<DnsName, Vec<DnsRecord>>,
+}
+
+impl Hosts {
+    /// Loads the hosts files and the records of `settings`
+    ///
+    /// takes: `&HostsSettings`
+    ///
+    /// returns: `Result<Hosts>`, an error if a file can not be
+    /// read or parsed, or if a name has a CNAME record along
+    /// with other records
+    pub fn new(settings: &HostsSettings) -> Result<Self> {
+        let mut hosts = Self::default();
+        let mut addresses = Vec::new();
+        for record in &settings.records {
+            let qtype = match record.qtype {
+                StaticRecordType::A => QueryType::A,
+                StaticRecordType::AAAA => QueryType::AAAA,
+                StaticRecordType::CNAME => QueryType::CNAME,
+                StaticRecordType::PTR => QueryType::PTR,
+            };
+            let data = RecordData::from_presentation(qtype, &record.data)?;
+            if let RecordData::A { addr } = data {
+                addresses.push((IpAddr::V4(addr), record.name.clone()));
+            } else if let RecordData::AAAA { addr } = data {
+                addresses.push((IpAddr::V6(addr), record.name.clone()));
+            }
+            hosts.insert(DnsRecord::with_data(
+                record.name.clone(),
+                settings.ttl,
+                data,
+            ))?;
+        }
+
+        for path in &settings.files {
+            let text = fs::read_to_string(path)
+                .map_err(|err| invalid_hosts_file(path, &err.to_string()))?;
+            for (addr, names) in parse_hosts(path, &text, invalid_hosts_file)? {
+                let data = match addr {
+                    IpAddr::V4(addr) => RecordData::A { addr },
+                    IpAddr::V6(addr) => RecordData::AAAA { addr },
+                };
+                // the first name is the canonical one, the others
+                // are aliases
+                if let Some(name) = names.first() {
+                    addresses.push((addr, name.clone()));
+                }
+                for name in names {
+                    hosts.insert(DnsRecord::with_data(name, settings.ttl, data.clone()))?;
+                }
+            }
+        }
+
+        for (addr, name) in addresses {
+            let reverse = reverse_name(addr);
+            if !hosts.records.contains_key(&reverse) {
+                let data = RecordData::PTR { host: name };
+                hosts.insert(DnsRecord::with_data(reverse, settings.ttl, data))?;
+            }
+        }
+        Ok(hosts)
+    }
+
+    /// Whether `name` has static records
+    pub fn contains(&self, name: &DnsName) -> bool {
+        self.records.contains_key(name)
+    }
+
+    /// Answers `request` authoritatively if its name has static
+    /// records. The CNAME records are followed as long as their
+    /// target has static records too.
+    ///
+    /// takes: `&DnsMessage`
+    ///
+    /// returns: `Option<DnsMessage>`, `None` for the names the
+    /// table does not have
+    pub fn handle_query(&self, request: &DnsMessage) -> Option<DnsMessage> {
+        let question = request.questions.first()?;
+        let mut records = self.records.get(&question.qname)?;
+        let mut message = DnsMessage::response_to(request)
+            .authoritative(true)
+            .recursion_available(true)
+            .build();
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            let cname = records.iter().find_map(|rec| match &rec.data {
+                RecordData::CNAME { host } => Some((rec, host)),
+                _ => None,
+            });
+            match (cname, question.qtype) {
+                (Some((record, host)), qtype)
+                    if qtype != QueryType::CNAME && qtype != QueryType::ANY =>
+                {
+                    message.answers.push(record.clone());
+                    match self.records.get(host) {
+                        Some(target) => records = target,
+                        None => return Some(message),
+                    }
+                }
+                _ => {
+                    let records = records.iter().filter(|rec| {
+                        rec.qtype() == question.qtype || question.qtype == QueryType::ANY
+                    });
+                    message.answers.extend(records.cloned());
+                    return Some(message);
+                }
+            }
+        }
+        message.header.rescode = ResultCode::SERVFAIL;
+        Some(message)
+    }
+
+    /// Adds `record`, the same record twice is kept once
+    fn insert(&mut self, record: DnsRecord) -> Result<()> {
+        let records = self.records.entry(record.domain.clone()).or_default();
+        if records.contains(&record) {
+            return Ok(());
+        }
+        let is_cname = record.qtype() == QueryType::CNAME;
+        if records
+            .iter()
+            .any(|rec| is_cname || rec.qtype() == QueryType::CNAME)
+        {
+            return Err(invalid_static_record(
+                &record.domain.to_string(),
+                "a CNAME record can not have other records",
+            ));
+        }
+        records.push(record);
+        Ok(())
+    }
+}
+
+/// Parses the `address name...` lines of a hosts file, `#`
+/// starts a comment
+///
+/// takes: `(&str, &str, fn(&str, &str) -> Error)` = (path, text,
+/// the error of the file)
+///
+/// returns: `Result<Vec<(IpAddr, Vec<DnsName>)>>`, the names of
+/// each address, an error for an invalid address or name
+pub fn parse_hosts(
+    path: &str,
+    text: &str,
+    invalid: fn(&str, &str) -> Error,
+) -> Result<Vec<(IpAddr, Vec<DnsName>)>> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let invalid = |text: &str| invalid(path, &format!("line {}: {}", number + 1, text));
+        let mut fields = line
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+        let Some(addr) = fields.next() else {
+            continue;
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid(addr))?;
+        let names = fields
+            .map(|name| name.parse().map_err(|_| invalid(name)))
+            .collect::<Result<_>>()?;
+        entries.push((addr, names));
+    }
+    Ok(entries)
+}
+
+/// The name of the PTR record of `addr`, below `in-addr.arpa`
+/// or `ip6.arpa` (RFC 1035 section 3.5, RFC 3596 section 2.5)
+pub fn reverse_name(addr: IpAddr) -> DnsName {
+    let labels: Vec<String> = match addr {
+        IpAddr::V4(addr) => addr
+            .octets()
+            .iter()
+            .rev()
+            .map(|octet| octet.to_string())
+            .chain(["in-addr".into(), "arpa".into()])
+            .collect(),
+        IpAddr::V6(addr) => addr
+            .octets()
+            .iter()
+            .rev()
+            .flat_map(|octet| [octet & 0x0F, octet >> 4])
+            .map(|nibble| format!("{:x}", nibble))
+            .chain(["ip6".into(), "arpa".into()])
+            .collect(),
+    };
+    DnsName::from_labels(labels).expect("reverse names are valid")
+}
@@ -1,38 +1,69 @@
 use crate::dns_message::dns_header::ResultCode;
-use crate::dns_message::dns_question::DnsQuestion;
+use crate::dns_message::dns_name::DnsName;
 use crate::dns_message::packet_buffer::PacketBuffer;
 use crate::dns_message::{DnsMessage, QueryType};
-use crate::errors::Result;
+use crate::errors::{failed_upstream, Result};
+use ring::rand::{SecureRandom, SystemRandom};
 use std::future::Future;
 use std::net::Ipv4Addr;
 use std::pin::Pin;
+use std::time::Duration;
 use tokio::net::UdpSocket;
+use tokio::time::timeout;
 
-pub async fn lookup(qname: &str, qtype: QueryType, server: (Ipv4Addr, u16)) -> Result<DnsMessage> {
-    let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+/// How long a name server may take to answer
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
 
-    let mut message = DnsMessage::new();
+/// Asks `server` for `qname` and `qtype` with a random id
+///
+/// takes: `(&DnsName, QueryType, (Ipv4Addr, u16))`
+///
+/// returns: `Result<DnsMessage>`, an error if the server does not
+/// answer in time, or with a malformed answer or one to another
+/// query
+pub async fn lookup(
+    qname: &DnsName,
+    qtype: QueryType,
+    server: (Ipv4Addr, u16),
+) -> Result<DnsMessage> {
+    let address = format!("{}:{}", server.0, server.1);
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
 
-    message.header.id = 6666;
-    message.header.questions = 1;
-    message.header.recursion_desired = true;
-    message
-        .questions
-        .push(DnsQuestion::new(qname.into(), qtype));
+    let mut id = [0; 2];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| failed_upstream(&address, "no random id"))?;
+    let mut message = DnsMessage::query(qname, qtype)
+        .id(u16::from_be_bytes(id))
+        .recursion_desired(true)
+        .build();
 
     let send_buffer = message.into_buf()?;
     socket
         .send_to(&send_buffer.buf[0..send_buffer.pos()], server)
         .await?;
 
-    let mut recv_buffer = PacketBuffer::new();
-    socket.recv(&mut recv_buffer.buf).await?;
+    let mut recv_buffer = PacketBuffer::with_size(u16::MAX as usize);
+    let len = match timeout(LOOKUP_TIMEOUT, socket.recv(&mut recv_buffer.buf)).await {
+        Ok(len) => len?,
+        Err(_) => return Err(failed_upstream(&address, "timed out")),
+    };
+    recv_buffer.truncate(len);
+    recv_buffer.set_strict(true);
 
-    DnsMessage::from_buf(&mut recv_buffer)
+    let response = DnsMessage::from_buf(&mut recv_buffer)?;
+    // names compare case-insensitively
+    let same_question = response.questions.len() == 1
+        && response.questions[0].qname == *qname
+        && response.questions[0].qtype == qtype;
+    if response.header.id != message.header.id || !response.header.response || !same_question {
+        return Err(failed_upstream(&address, "mismatched response"));
+    }
+    Ok(response)
 }
 
 pub fn recursive_lookup<'a>(
-    qname: &'a str,
+    qname: &'a DnsName,
     qtype: QueryType,
 ) -> Pin<Box<dyn Future<Output = Result<DnsMessage>> + Send + 'a>> {
     Box::pin(async move {
@@ -40,11 +71,11 @@ pub fn recursive_lookup<'a>(
         let mut ns = "198.41.0.4".parse::<Ipv4Addr>().unwrap();
 
         loop {
-            println!("attempting lookup of {:?} {} with ns {}", qtype, qname, ns);
+            println!("attempting lookup of {} {} with ns {}", qtype, qname, ns);
 
             let server = (ns, 53);
-            let response = lookup(qname, qtype, server).await.unwrap();
-            println!("{:#?}", response);
+            let response = lookup(qname, qtype, server).await?;
+            println!("{}", response);
 
             if !response.answers.is_empty() && response.header.rescode == ResultCode::NOERROR {
                 return Ok(response);
@@ -64,7 +95,7 @@ pub fn recursive_lookup<'a>(
                 None => return Ok(response),
             };
 
-            let recursive_response = recursive_lookup(&new_ns_name, QueryType::A).await.unwrap();
+            let recursive_response = recursive_lookup(new_ns_name, QueryType::A).await?;
 
             if let Some(new_ns) = recursive_response.random_ipv4() {
                 ns = new_ns;
@@ -1,2 +1,6 @@
+pub mod forward;
 pub mod handle_query;
+pub mod hosts;
 pub mod lookup;
+pub mod policy;
+pub mod upstream;
@@ -0,0 +1,489 @@
+//! Response policies: local blocklists and Response Policy Zones
+//! (draft-vixie-dnsop-dns-rpz).
+//!
+//! A list is a file of domains, a hosts file or a policy zone.
+//! Its rules are triggered by the name queried, by an address
+//! or a name server of the response, and answer NXDOMAIN,
+//! NODATA, local records, nothing at all, or let the response
+//! through. The files are checked periodically and reloaded once
+//! changed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tokio::time::sleep;
+
+use crate::authority::zone_file;
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::dns_resolver::hosts::parse_hosts;
+use crate::dns_server::DnsServer;
+use crate::errors::{invalid_policy_list, Result};
+use crate::settings::acl::Network;
+use crate::settings::config::{BlockAction, ListFormat, PolicyListSettings, PolicySettings};
+
+/// TTL of the records answered for the entries of a hosts file
+const HOSTS_TTL: u32 = 300;
+
+/// What a rule does with the query it is triggered by
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    NxDomain,
+    NoData,
+    /// answer these records, their owner becomes the name queried
+    LocalData(Vec<DnsRecord>),
+    /// answer normally, the later rules do not apply
+    Passthru,
+    /// no response at all
+    Drop,
+}
+
+impl From<BlockAction> for Action {
+    fn from(action: BlockAction) -> Self {
+        match action {
+            BlockAction::Nxdomain => Self::NxDomain,
+            BlockAction::Nodata => Self::NoData,
+            BlockAction::Drop => Self::Drop,
+        }
+    }
+}
+
+/// The policies of the resolver, the lists are tried in order
+/// and the first rule triggered applies
+pub struct Policy {
+    lists: RwLock<Vec<List>>,
+    reload_interval: Duration,
+}
+
+struct List {
+    settings: PolicyListSettings,
+    modified: Option<SystemTime>,
+    rules: Rules,
+}
+
+/// The rules of a list by trigger, a wildcard is keyed by the
+/// name below which it applies
+#[derive(Default)]
+struct Rules {
+    names: HashMap<DnsName, Action>,
+    wildcards: HashMap<DnsName, Action>,
+    /// the longest network containing an address applies
+    addresses: Vec<(Network, Action)>,
+    ns_names: HashMap<DnsName, Action>,
+    ns_wildcards: HashMap<DnsName, Action>,
+}
+
+/// Spawns the task reloading the lists of `server` once changed
+pub fn spawn(server: &Arc<DnsServer>) {
+    let Some(policy) = server.policy().cloned() else {
+        return;
+    };
+    tokio::spawn(async move {
+        loop {
+            sleep(policy.reload_interval).await;
+            policy.reload();
+        }
+    });
+}
+
+impl Policy {
+    /// Loads the lists of `settings`
+    ///
+    /// takes: `&PolicySettings`
+    ///
+    /// returns: `Result<Policy>`, an error if a list can not be
+    /// read or parsed
+    pub fn new(settings: &PolicySettings) -> Result<Self> {
+        let lists = settings
+            .lists
+            .iter()
+            .map(|settings| {
+                Ok(List {
+                    modified: modified(&settings.file),
+                    rules: Rules::load(settings)?,
+                    settings: settings.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            lists: RwLock::new(lists),
+            reload_interval: Duration::from_secs(settings.reload_interval),
+        })
+    }
+
+    /// Reloads the lists whose file changed since they were
+    /// loaded. A list failing to load keeps its previous rules.
+    ///
+    /// returns: `usize`, the number of lists reloaded
+    pub fn reload(&self) -> usize {
+        let changed: Vec<_> = self
+            .lists
+            .read()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, list)| {
+                let modified = modified(&list.settings.file);
+                (modified != list.modified).then(|| (index, list.settings.clone(), modified))
+            })
+            .collect();
+
+        let mut reloaded = 0;
+        for (index, settings, modified) in changed {
+            match Rules::load(&settings) {
+                Ok(rules) => {
+                    println!("Reloaded policy list {}", settings.file);
+                    let list = &mut self.lists.write().unwrap()[index];
+                    list.rules = rules;
+                    list.modified = modified;
+                    reloaded += 1;
+                }
+                Err(err) => println!("Failed to reload policy list {}: {}", settings.file, err),
+            }
+        }
+        reloaded
+    }
+
+    /// Answers `request` under the policies: the rules triggered
+    /// by the name queried apply before `resolve` is called, the
+    /// ones triggered by the response after
+    ///
+    /// takes: `(&self, &DnsMessage, F)`, `resolve` answers a
+    /// request normally
+    ///
+    /// returns: `Option<DnsMessage>`, `None` when it is dropped
+    pub async fn handle_query<F, Fut>(&self, request: &DnsMessage, resolve: F) -> Option<DnsMessage>
+    where
+        F: Fn(DnsMessage) -> Fut,
+        Fut: Future<Output = DnsMessage>,
+    {
+        let Some(question) = request.questions.first() else {
+            return Some(resolve(request.clone()).await);
+        };
+        let action = self.find(|rules| rules.qname(&question.qname));
+        match action {
+            Some(Action::Passthru) => return Some(resolve(request.clone()).await),
+            Some(action) => {
+                println!("Policy applied to {}: {:?}", question.qname, action);
+                return apply(request, action, &resolve).await;
+            }
+            None => {}
+        }
+
+        let response = resolve(request.clone()).await;
+        match self.find(|rules| rules.response(&response)) {
+            None | Some(Action::Passthru) => Some(response),
+            Some(action) => {
+                println!(
+                    "Policy applied to the answer for {}: {:?}",
+                    question.qname, action
+                );
+                apply(request, action, &resolve).await
+            }
+        }
+    }
+
+    /// The action of the first list with a rule `trigger` returns
+    fn find<T>(&self, trigger: T) -> Option<Action>
+    where
+        T: Fn(&Rules) -> Option<&Action>,
+    {
+        self.lists
+            .read()
+            .unwrap()
+            .iter()
+            .find_map(|list| trigger(&list.rules).cloned())
+    }
+}
+
+/// The response `action` makes to `request`. The local data
+/// answering a CNAME gets the answers for its target.
+async fn apply<F, Fut>(request: &DnsMessage, action: Action, resolve: &F) -> Option<DnsMessage>
+where
+    F: Fn(DnsMessage) -> Fut,
+    Fut: Future<Output = DnsMessage>,
+{
+    let mut message = DnsMessage::response_to(request)
+        .recursion_available(true)
+        .build();
+    let question = request.questions.first()?;
+    match action {
+        Action::NxDomain => message.header.rescode = ResultCode::NXDOMAIN,
+        Action::NoData => {}
+        Action::Drop => return None,
+        Action::Passthru => return Some(resolve(request.clone()).await),
+        Action::LocalData(records) => {
+            let records = records.into_iter().filter(|rec| {
+                rec.qtype() == question.qtype
+                    || rec.qtype() == QueryType::CNAME
+                    || question.qtype == QueryType::ANY
+            });
+            for mut record in records {
+                record.domain = question.qname.clone();
+                message.answers.push(record);
+            }
+            let target = match message.answers.as_slice() {
+                [DnsRecord {
+                    data: RecordData::CNAME { host },
+                    ..
+                }] if question.qtype != QueryType::CNAME => host.clone(),
+                _ => return Some(message),
+            };
+            let mut chased = request.clone();
+            chased.questions[0].qname = target;
+            let response = resolve(chased).await;
+            message.header.rescode = response.header.rescode;
+            message.answers.extend(response.answers);
+        }
+    }
+    Some(message)
+}
+
+/// The modification time of `file`, `None` if it is missing
+fn modified(file: &str) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|meta| meta.modified()).ok()
+}
+
+impl Rules {
+    fn load(settings: &PolicyListSettings) -> Result<Self> {
+        let path = &settings.file;
+        let text =
+            || fs::read_to_string(path).map_err(|err| invalid_policy_list(path, &err.to_string()));
+        match settings.format {
+            ListFormat::Domains => Self::domains(path, &text()?, settings.action.into()),
+            ListFormat::Hosts => Self::hosts(path, &text()?, settings.action.into()),
+            ListFormat::Rpz => {
+                let origin = settings
+                    .origin
+                    .as_ref()
+                    .ok_or_else(|| invalid_policy_list(path, "a zone needs an origin"))?;
+                Self::rpz(&zone_file::read(Path::new(path), origin)?, origin)
+            }
+        }
+    }
+
+    /// A name per line and `#` comments, `*.example.com` is
+    /// triggered by the names below `example.com`
+    fn domains(path: &str, text: &str, action: Action) -> Result<Self> {
+        let mut rules = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (wildcard, name) = match line.strip_prefix("*.") {
+                Some(name) => (true, name),
+                None => (false, line),
+            };
+            let name = parse_name(path, number, name)?;
+            match wildcard {
+                true => rules.wildcards.insert(name, action.clone()),
+                false => rules.names.insert(name, action.clone()),
+            };
+        }
+        Ok(rules)
+    }
+
+    /// `address name...` lines and `#` comments, the unspecified
+    /// addresses `0.0.0.0` and `::` get `action`, the others are
+    /// answered
+    fn hosts(path: &str, text: &str, action: Action) -> Result<Self> {
+        let mut rules = Self::default();
+        for (addr, names) in parse_hosts(path, text, invalid_policy_list)? {
+            let data = match addr {
+                IpAddr::V4(addr) => RecordData::A { addr },
+                IpAddr::V6(addr) => RecordData::AAAA { addr },
+            };
+            for name in names {
+                let action = match addr.is_unspecified() {
+                    true => action.clone(),
+                    false => {
+                        let record = DnsRecord::with_data(name.clone(), HOSTS_TTL, data.clone());
+                        Action::LocalData(vec![record])
+                    }
+                };
+                insert(&mut rules.names, name, action);
+            }
+        }
+        Ok(rules)
+    }
+
+    /// The rules of a policy zone: the owner of a record is the
+    /// trigger, relative to the origin, and its data the action.
+    /// `rpz-ip` and `rpz-nsdname` owners are triggered by an
+    /// address or a name server of the response, the other ones
+    /// by the name queried. A CNAME to `.` answers NXDOMAIN, to
+    /// `*.` NODATA, to `rpz-passthru.` and `rpz-drop.` lets the
+    /// response through or drops it, any other record is local
+    /// data.
+    fn rpz(records: &[DnsRecord], origin: &DnsName) -> Result<Self> {
+        let mut rules = Self::default();
+        for record in records {
+            if !record.domain.is_subdomain_of(origin) || record.domain == *origin {
+                continue;
+            }
+            let depth = record.domain.label_count() - origin.label_count();
+            let trigger = DnsName::from_labels(record.domain.labels().take(depth))?;
+            let action = match &record.data {
+                RecordData::CNAME { host } => rpz_action(host, record),
+                _ => Action::LocalData(vec![record.clone()]),
+            };
+
+            let mut labels: Vec<_> = trigger.labels().collect();
+            let is_ns_name = match labels.last() {
+                Some(label) if label.eq_ignore_ascii_case(b"rpz-ip") => {
+                    match rpz_network(&labels) {
+                        Some(network) => rules.addresses.push((network, action)),
+                        None => println!("Invalid policy trigger {}", record.domain),
+                    }
+                    continue;
+                }
+                Some(label) if label.eq_ignore_ascii_case(b"rpz-nsdname") => {
+                    labels.pop();
+                    true
+                }
+                Some(label) if label.starts_with(b"rpz-") => {
+                    println!("Unsupported policy trigger {}", record.domain);
+                    continue;
+                }
+                _ => false,
+            };
+            let is_wildcard = labels.first() == Some(&&b"*"[..]);
+            if is_wildcard {
+                labels.remove(0);
+            }
+            let map = match (is_ns_name, is_wildcard) {
+                (false, false) => &mut rules.names,
+                (false, true) => &mut rules.wildcards,
+                (true, false) => &mut rules.ns_names,
+                (true, true) => &mut rules.ns_wildcards,
+            };
+            insert(map, DnsName::from_labels(labels)?, action);
+        }
+        Ok(rules)
+    }
+
+    /// The rule triggered by the name `qname`, an exact rule
+    /// before the wildcard of the closest ancestor
+    fn qname(&self, qname: &DnsName) -> Option<&Action> {
+        lookup(&self.names, &self.wildcards, qname)
+    }
+
+    /// The rule triggered by `response`: by a name of its CNAME
+    /// chain, an address it answers or the host of an NS record
+    fn response(&self, response: &DnsMessage) -> Option<&Action> {
+        let names = response
+            .answers
+            .iter()
+            .filter_map(|rec| match &rec.data {
+                RecordData::CNAME { host } => Some(host),
+                _ => None,
+            })
+            .find_map(|host| self.qname(host));
+        let addresses = || {
+            response.answers.iter().find_map(|rec| {
+                let addr = match rec.data {
+                    RecordData::A { addr } => IpAddr::V4(addr),
+                    RecordData::AAAA { addr } => IpAddr::V6(addr),
+                    _ => return None,
+                };
+                self.addresses
+                    .iter()
+                    .filter(|(network, _)| network.contains(addr))
+                    .max_by_key(|(network, _)| network.prefix_len())
+                    .map(|(_, action)| action)
+            })
+        };
+        let ns_names = || {
+            response
+                .answers
+                .iter()
+                .chain(&response.authorities)
+                .find_map(|rec| match &rec.data {
+                    RecordData::NS { host } => lookup(&self.ns_names, &self.ns_wildcards, host),
+                    _ => None,
+                })
+        };
+        names.or_else(addresses).or_else(ns_names)
+    }
+}
+
+fn lookup<'a>(
+    names: &'a HashMap<DnsName, Action>,
+    wildcards: &'a HashMap<DnsName, Action>,
+    name: &DnsName,
+) -> Option<&'a Action> {
+    names.get(name).or_else(|| {
+        name.ancestors()
+            .skip(1)
+            .find_map(|name| wildcards.get(&name))
+    })
+}
+
+/// Adds the rule of `name`, the local data of a name already
+/// having some is added to it
+fn insert(map: &mut HashMap<DnsName, Action>, name: DnsName, action: Action) {
+    match (map.get_mut(&name), action) {
+        (Some(Action::LocalData(records)), Action::LocalData(more)) => records.extend(more),
+        (Some(_), _) => {}
+        (None, action) => {
+            map.insert(name, action);
+        }
+    }
+}
+
+fn parse_name(path: &str, number: usize, name: &str) -> Result<DnsName> {
+    name.parse()
+        .map_err(|_| invalid_policy_list(path, &format!("line {}: {}", number + 1, name)))
+}
+
+/// The action of a CNAME record of a policy zone
+fn rpz_action(host: &DnsName, record: &DnsRecord) -> Action {
+    let labels: Vec<_> = host.labels().collect();
+    match labels.as_slice() {
+        [] => Action::NxDomain,
+        [b"*"] => Action::NoData,
+        [label] if label.eq_ignore_ascii_case(b"rpz-passthru") => Action::Passthru,
+        [label] if label.eq_ignore_ascii_case(b"rpz-drop") => Action::Drop,
+        _ => Action::LocalData(vec![record.clone()]),
+    }
+}
+
+/// The network of an `rpz-ip` trigger: the prefix length, then
+/// the address with its labels reversed, IPv6 addresses writing
+/// their longest run of zeros `zz`, e.g. `24.0.2.0.192.rpz-ip`
+/// or `64.zz.db8.2001.rpz-ip`
+fn rpz_network(labels: &[&[u8]]) -> Option<Network> {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|label| std::str::from_utf8(label).ok())
+        .collect::<Option<_>>()?;
+    let [prefix_len, address @ .., _] = labels.as_slice() else {
+        return None;
+    };
+    let mut address: Vec<_> = address.iter().rev().copied().collect();
+    let text = match address.len() {
+        4 if address.iter().all(|label| label.parse::<u8>().is_ok()) => address.join("."),
+        _ => {
+            for label in &mut address {
+                if label.eq_ignore_ascii_case("zz") {
+                    *label = "";
+                }
+            }
+            match address.join(":") {
+                text if text.is_empty() => "::".to_string(),
+                text if text.starts_with(':') => format!(":{}", text),
+                text if text.ends_with(':') => format!("{}:", text),
+                text => text,
+            }
+        }
+    };
+    format!("{}/{}", text, prefix_len).parse().ok()
+}
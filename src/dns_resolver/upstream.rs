@@ -0,0 +1,292 @@
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use rustls::client::ClientConfig;
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::ServerName;
+use rustls::RootCertStore;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+use crate::dns_message::dns_question::DnsQuestion;
+use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::DnsMessage;
+use crate::dns_server::https::DNS_MESSAGE;
+use crate::dns_server::{tcp, tls};
+use crate::errors::{failed_upstream, invalid_upstream, Result};
+use crate::settings::config::{Transport, UpstreamSettings};
+
+/// How long an upstream may take to answer, connection included
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a pooled connection may take to answer before the
+/// query is sent on a new one, the upstream may have dropped it
+const POOLED_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Idle connections kept open to an upstream, for reuse
+const MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// A resolver the queries are forwarded to. The connections of
+/// stream transports are kept open and reused, the ones the
+/// upstream closed in the meantime are replaced.
+pub struct Upstream {
+    address: String,
+    transport: Transport,
+    /// the address of UDP, TCP and TLS upstreams
+    addr: Option<SocketAddr>,
+    tcp: Pool<TcpStream>,
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    tls_pool: Pool<TlsStream<TcpStream>>,
+    /// the HTTPS client pools its own connections
+    https: Option<reqwest::Client>,
+}
+
+type Pool<S> = Mutex<Vec<S>>;
+
+impl Upstream {
+    /// Builds the upstream out of its settings, the certificate
+    /// of a `tls` or `https` upstream is checked against the web
+    /// PKI unless the settings name a CA
+    ///
+    /// takes: `&UpstreamSettings`
+    ///
+    /// returns: `Result<Upstream>`, an error if the address does
+    /// not suit the transport
+    pub fn new(settings: &UpstreamSettings) -> Result<Self> {
+        let invalid = |reason: &str| invalid_upstream(&settings.address, reason);
+        let mut upstream = Self {
+            address: settings.address.clone(),
+            transport: settings.transport,
+            addr: None,
+            tcp: Mutex::new(Vec::new()),
+            tls: None,
+            tls_pool: Mutex::new(Vec::new()),
+            https: None,
+        };
+
+        if settings.transport == Transport::Https {
+            if !settings.address.starts_with("https://") {
+                return Err(invalid("not an https URL"));
+            }
+            let mut builder = reqwest::Client::builder().timeout(UPSTREAM_TIMEOUT);
+            if let Some(ref ca) = settings.ca {
+                for certificate in tls::certificates(ca)? {
+                    let certificate = reqwest::Certificate::from_der(&certificate)?;
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            upstream.https = Some(builder.build()?);
+            return Ok(upstream);
+        }
+
+        let addr: SocketAddr = settings
+            .address
+            .parse()
+            .map_err(|_| invalid("not an `ip:port` address"))?;
+        upstream.addr = Some(addr);
+        if settings.transport == Transport::Tls {
+            let name = match settings.tls_name {
+                Some(ref name) => {
+                    ServerName::try_from(name.clone()).map_err(|_| invalid("invalid TLS name"))?
+                }
+                None => ServerName::IpAddress(addr.ip().into()),
+            };
+            let config = client_config(settings.ca.as_deref())?;
+            upstream.tls = Some((TlsConnector::from(config), name));
+        }
+        Ok(upstream)
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Whether the queries reach the upstream encrypted
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self.transport, Transport::Tls | Transport::Https)
+    }
+
+    /// The connections open to the upstream, waiting for a query
+    pub fn idle_connections(&self) -> usize {
+        self.tcp.lock().unwrap().len() + self.tls_pool.lock().unwrap().len()
+    }
+
+    /// Sends `request` to the upstream and waits for its answer
+    ///
+    /// takes: `(&self, &DnsMessage)`
+    ///
+    /// returns: `Result<DnsMessage>`, an error if the upstream
+    /// fails or does not answer in time, or if the answer is not
+    /// one to `request`: another id or another question
+    pub async fn exchange(&self, request: &DnsMessage) -> Result<DnsMessage> {
+        let response = match timeout(UPSTREAM_TIMEOUT, self.send(request)).await {
+            Ok(response) => response?,
+            Err(_) => return Err(failed_upstream(&self.address, "timed out")),
+        };
+        // names compare case-insensitively
+        let same_question = |(ours, theirs): (&DnsQuestion, &DnsQuestion)| {
+            ours.qname == theirs.qname && ours.qtype == theirs.qtype && ours.qclass == theirs.qclass
+        };
+        if response.header.id != request.header.id
+            || !response.header.response
+            || response.questions.len() != request.questions.len()
+            || !request
+                .questions
+                .iter()
+                .zip(&response.questions)
+                .all(same_question)
+        {
+            return Err(failed_upstream(&self.address, "mismatched response"));
+        }
+        Ok(response)
+    }
+
+    async fn send(&self, request: &DnsMessage) -> Result<DnsMessage> {
+        let addr = self.addr;
+        let connect_tcp = || async move {
+            let addr = addr.expect("stream upstreams have an address");
+            Ok(TcpStream::connect(addr).await?)
+        };
+        match self.transport {
+            Transport::Udp => {
+                let response = self.send_udp(request).await?;
+                match response.header.truncated_message {
+                    true => exchange_stream(&self.tcp, connect_tcp, request).await,
+                    false => Ok(response),
+                }
+            }
+            Transport::Tcp => exchange_stream(&self.tcp, connect_tcp, request).await,
+            Transport::Tls => {
+                let connect_tls = || async {
+                    let (connector, name) = self.tls.as_ref().expect("tls upstreams have a name");
+                    let stream = connect_tcp().await?;
+                    Ok(connector.connect(name.clone(), stream).await?)
+                };
+                exchange_stream(&self.tls_pool, connect_tls, request).await
+            }
+            Transport::Https => self.send_https(request).await,
+        }
+    }
+
+    async fn send_udp(&self, request: &DnsMessage) -> Result<DnsMessage> {
+        let addr = self.addr.expect("udp upstreams have an address");
+        let local = match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        let buffer = request.clone().into_buf_with_size(u16::MAX as usize)?;
+        socket.send(&buffer.buf[..buffer.pos()]).await?;
+
+        let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+        let len = socket.recv(&mut buffer.buf).await?;
+        buffer.truncate(len);
+        buffer.set_strict(true);
+        DnsMessage::from_buf(&mut buffer)
+    }
+
+    /// POSTs the query (RFC 8484 section 4.1), with an id of 0
+    /// as the RFC advises for caching
+    async fn send_https(&self, request: &DnsMessage) -> Result<DnsMessage> {
+        let client = self.https.as_ref().expect("https upstreams have a client");
+        let mut query = request.clone();
+        query.header.id = 0;
+        let buffer = query.into_buf_with_size(u16::MAX as usize)?;
+        let response = client
+            .post(&self.address)
+            .header(CONTENT_TYPE, DNS_MESSAGE)
+            .header(ACCEPT, DNS_MESSAGE)
+            .body(buffer.buf[..buffer.pos()].to_vec())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(failed_upstream(
+                &self.address,
+                &format!("HTTP {}", response.status()),
+            ));
+        }
+
+        let mut buffer = PacketBuffer::from_bytes(&response.bytes().await?);
+        buffer.set_strict(true);
+        let mut response = DnsMessage::from_buf(&mut buffer)?;
+        response.header.id = request.header.id;
+        Ok(response)
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}", self.transport, self.address)
+    }
+}
+
+/// Exchanges `request` on a connection of `pool`, or on a new
+/// one when there is none or the pooled one was closed or does
+/// not answer in time. The connection goes back to the pool once
+/// answered.
+async fn exchange_stream<S, F, Fut>(
+    pool: &Pool<S>,
+    connect: F,
+    request: &DnsMessage,
+) -> Result<DnsMessage>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<S>>,
+{
+    let pooled = pool.lock().unwrap().pop();
+    if let Some(mut stream) = pooled {
+        if let Ok(Ok(response)) = timeout(POOLED_TIMEOUT, exchange_on(&mut stream, request)).await {
+            release(pool, stream);
+            return Ok(response);
+        }
+    }
+
+    let mut stream = connect().await?;
+    let response = exchange_on(&mut stream, request).await?;
+    release(pool, stream);
+    Ok(response)
+}
+
+async fn exchange_on<S>(stream: &mut S, request: &DnsMessage) -> Result<DnsMessage>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    tcp::write_message(stream, request.clone()).await?;
+    tcp::read_message(stream).await
+}
+
+fn release<S>(pool: &Pool<S>, stream: S) {
+    let mut pool = pool.lock().unwrap();
+    if pool.len() < MAX_IDLE_CONNECTIONS {
+        pool.push(stream);
+    }
+}
+
+/// The TLS configuration of DoT upstreams, offering the `dot`
+/// ALPN protocol and trusting the web PKI or the CAs of `ca`
+fn client_config(ca: Option<&str>) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    match ca {
+        Some(ca) => {
+            for certificate in tls::certificates(ca)? {
+                roots.add(certificate)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let mut config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![tls::DOT_ALPN.to_vec()];
+    Ok(Arc::new(config))
+}
@@ -0,0 +1,56 @@
+//! Answers to the CHAOS class `TXT` queries commonly used to
+//! identify a name server: `version.bind`, `version.server`,
+//! `hostname.bind` and `id.server`.
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::dns_record::{DnsRecord, RecordData};
+use crate::dns_message::{DnsClass, DnsMessage, QueryType};
+use crate::settings::config::ChaosSettings;
+
+/// Answers a CHAOS class request, names that are not served,
+/// or all of them when CHAOS is disabled, are refused.
+///
+/// takes: `(&DnsMessage, &ChaosSettings)`
+///
+/// returns: `DnsMessage`
+pub fn handle_query(request: &DnsMessage, settings: &ChaosSettings) -> DnsMessage {
+    let mut message = DnsMessage::response_to(request).build();
+    let Some(question) = request.questions.first() else {
+        message.header.rescode = ResultCode::FORMERR;
+        return message;
+    };
+
+    match lookup(&question.qname, settings) {
+        Some(value) => {
+            message.header.authoritative_answer = true;
+            if question.qtype == QueryType::TXT {
+                let data = RecordData::TXT {
+                    data: vec![value.as_bytes().to_vec()],
+                };
+                let mut record = DnsRecord::with_data(question.qname.clone(), 0, data);
+                record.class = DnsClass::CH;
+                message.answers.push(record);
+            }
+        }
+        None => message.header.rescode = ResultCode::REFUSED,
+    }
+    message
+}
+
+/// The configured value for `qname`, `None` when it is not served
+fn lookup<'a>(qname: &DnsName, settings: &'a ChaosSettings) -> Option<&'a str> {
+    if !settings.enabled {
+        return None;
+    }
+    let value = if *qname == "version.bind" || *qname == "version.server" {
+        &settings.version
+    } else if *qname == "hostname.bind" {
+        &settings.hostname
+    } else if *qname == "id.server" {
+        &settings.id
+    } else {
+        return None;
+    };
+    value.as_deref()
+}
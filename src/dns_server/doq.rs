@@ -0,0 +1,182 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::{
+    Connection, ConnectionError, Endpoint, EndpointConfig, Incoming, RecvStream, SendStream,
+    TokioRuntime, TransportConfig, VarInt,
+};
+use rustls::crypto::ring::default_provider;
+use rustls::version::TLS13;
+use rustls::ServerConfig;
+use tokio::net::UdpSocket;
+use tokio::sync::Semaphore;
+
+use crate::dns_message::dns_header::{Opcode, ResultCode};
+use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::DnsMessage;
+use crate::errors::Result;
+use crate::settings::acl::Access;
+use crate::settings::config::DoqSettings;
+
+use super::{is_transfer, tcp, tls, DnsServer};
+
+/// The ALPN protocol of DNS over QUIC (RFC 9250 section 4.1.1)
+pub const DOQ_ALPN: &[u8] = b"doq";
+
+/// The error codes closing a connection (RFC 9250 section 4.3)
+pub const DOQ_NO_ERROR: u32 = 0x0;
+pub const DOQ_PROTOCOL_ERROR: u32 = 0x2;
+
+/// A query is a single message, preceded by its length
+const MAX_STREAM_SIZE: usize = 2 + u16::MAX as usize;
+
+/// Serves DNS over QUIC (RFC 9250) on `socket`: each query comes
+/// on its own bidirectional stream, framed as over TCP. Queries
+/// sent in 0-RTT are answered if replaying them is harmless. At
+/// most `max_connections` are served at once.
+pub async fn serve(socket: UdpSocket, settings: DoqSettings, server: Arc<DnsServer>) {
+    let endpoint = match endpoint(socket, &settings) {
+        Ok(endpoint) => endpoint,
+        Err(err) => {
+            println!("Failed to start DNS over QUIC: {}", err);
+            return;
+        }
+    };
+
+    let connections = Arc::new(Semaphore::new(settings.max_connections));
+
+    loop {
+        let permit = connections
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let Some(incoming) = endpoint.accept().await else {
+            return;
+        };
+        let server = server.clone();
+        tokio::spawn(async move {
+            let src = incoming.remote_address();
+            if let Err(err) = handle_connection(incoming, server).await {
+                println!("Failed to handle QUIC connection from {}: {}", src, err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Builds the QUIC endpoint. 0-RTT requires the server to keep
+/// the sessions rather than hand out tickets, so that each
+/// session is resumed once.
+fn endpoint(socket: UdpSocket, settings: &DoqSettings) -> Result<Endpoint> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_protocol_versions(&[&TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(
+            tls::certificates(&settings.tls.certificate)?,
+            tls::private_key(&settings.tls.key)?,
+        )?;
+    config.alpn_protocols = vec![DOQ_ALPN.to_vec()];
+    config.max_early_data_size = u32::MAX;
+
+    let mut transport = TransportConfig::default();
+    transport.max_idle_timeout(Some(Duration::from_secs(settings.idle_timeout).try_into()?));
+    let mut config =
+        quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(config)?));
+    config.transport_config(Arc::new(transport));
+    Ok(Endpoint::new(
+        EndpointConfig::default(),
+        Some(config),
+        socket.into_std()?,
+        Arc::new(TokioRuntime),
+    )?)
+}
+
+/// Answers the streams of a connection concurrently, until the
+/// client closes it or it goes idle. The streams sent in 0-RTT
+/// are accepted before the handshake completes.
+async fn handle_connection(incoming: Incoming, server: Arc<DnsServer>) -> Result<()> {
+    let connection = match incoming.accept()?.into_0rtt() {
+        Ok((connection, _)) => connection,
+        Err(connecting) => connecting.await?,
+    };
+    let src = connection.remote_address();
+
+    loop {
+        let (send, recv) = match connection.accept_bi().await {
+            Ok(stream) => stream,
+            Err(
+                ConnectionError::ApplicationClosed(_)
+                | ConnectionError::LocallyClosed
+                | ConnectionError::TimedOut,
+            ) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let connection = connection.clone();
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_stream(&connection, send, recv, src, &server).await {
+                println!("Failed to handle QUIC stream from {}: {}", src, err);
+            }
+        });
+    }
+}
+
+/// Answers the query of a stream, which the client finished
+/// right after it. A query with an id other than 0 closes the
+/// connection (RFC 9250 section 4.2.1).
+async fn handle_stream(
+    connection: &Connection,
+    mut send: SendStream,
+    mut recv: RecvStream,
+    src: SocketAddr,
+    server: &DnsServer,
+) -> Result<()> {
+    let is_early = recv.is_0rtt();
+    let data = recv.read_to_end(MAX_STREAM_SIZE).await?;
+    let len = data
+        .get(..2)
+        .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize);
+    if len != data.len().checked_sub(2) {
+        connection.close(VarInt::from_u32(DOQ_PROTOCOL_ERROR), b"invalid framing");
+        return Ok(());
+    }
+    let mut buffer = PacketBuffer::from_bytes(&data[2..]);
+    buffer.set_strict(true);
+
+    let messages = match DnsMessage::from_buf(&mut buffer) {
+        Ok(request) if request.header.id != 0 => {
+            connection.close(VarInt::from_u32(DOQ_PROTOCOL_ERROR), b"nonzero message id");
+            return Ok(());
+        }
+        // RFC 9250 section 4.5, the 0-RTT data may be replayed
+        Ok(request) if is_early && !is_replayable(&request) => {
+            vec![DnsMessage::response_to(&request)
+                .rescode(ResultCode::REFUSED)
+                .build()]
+        }
+        Ok(request) => server.handle_stream_request(&request, src).await,
+        Err(_) if server.access(src.ip()) == Access::Deny => Vec::new(),
+        Err(err) => {
+            println!("Malformed query from {}: {}", src, err);
+            DnsMessage::format_error(&mut buffer).into_iter().collect()
+        }
+    };
+    for message in messages {
+        tcp::write_message(&mut send, message).await?;
+    }
+    send.finish()?;
+    Ok(())
+}
+
+/// Whether answering `request` twice does no harm: the queries
+/// but the zone transfers
+fn is_replayable(request: &DnsMessage) -> bool {
+    request.header.opcode == Opcode::QUERY
+        && !request
+            .questions
+            .iter()
+            .any(|question| is_transfer(question.qtype))
+}
@@ -0,0 +1,328 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Bytes};
+use hyper::header::{HeaderMap, CACHE_CONTROL, CONTENT_TYPE, FORWARDED};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+
+use crate::dns_message::dns_record::DnsRecord;
+use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::DnsMessage;
+use crate::errors::Result;
+use crate::settings::acl::{Access, Acl};
+use crate::settings::config::DohSettings;
+
+use super::{tls, DnsServer};
+
+/// The path of the endpoint, the one RFC 8484 uses throughout
+pub const DNS_QUERY_PATH: &str = "/dns-query";
+/// The media type of a DNS message (RFC 8484 section 6)
+pub const DNS_MESSAGE: &str = "application/dns-message";
+
+/// A message never exceeds what the TCP framing allows
+const MAX_MESSAGE_SIZE: usize = u16::MAX as usize;
+
+/// How long a client may take to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The requests of a connection being answered, and when the
+/// last one was
+struct Activity {
+    in_flight: usize,
+    last: Instant,
+}
+
+/// Serves DNS over HTTPS (RFC 8484) on `listener`, HTTP/2 or
+/// HTTP/1.1 as the client picks, over TLS unless the settings
+/// hold no certificate. At most `max_connections` are served at
+/// once, each one closed after `idle_timeout` seconds without a
+/// request.
+pub async fn serve(listener: TcpListener, settings: DohSettings, server: Arc<DnsServer>) {
+    let acceptor = match settings.tls {
+        Some(ref settings) => match tls::server_config(settings, &[b"h2", b"http/1.1"]) {
+            Ok(config) => Some(TlsAcceptor::from(config)),
+            Err(err) => {
+                println!("Failed to start DNS over HTTPS: {}", err);
+                return;
+            }
+        },
+        None => None,
+    };
+    let idle_timeout = Duration::from_secs(settings.idle_timeout);
+    let connections = Arc::new(Semaphore::new(settings.max_connections));
+    let trusted_proxies = Arc::new(settings.trusted_proxies);
+
+    loop {
+        let permit = connections
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let (stream, src) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                println!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let server = server.clone();
+        let trusted_proxies = trusted_proxies.clone();
+        tokio::spawn(async move {
+            let result = match acceptor {
+                Some(acceptor) => match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        serve_connection(stream, src, server, trusted_proxies, idle_timeout).await
+                    }
+                    Ok(Err(err)) => Err(err.into()),
+                    Err(_) => Ok(()),
+                },
+                None => serve_connection(stream, src, server, trusted_proxies, idle_timeout).await,
+            };
+            if let Err(err) = result {
+                println!("Failed to handle HTTP connection from {}: {}", src, err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Answers the requests of a connection until the client closes
+/// it, or until none has been answered nor is being answered for
+/// `idle_timeout`
+async fn serve_connection<S>(
+    stream: S,
+    src: SocketAddr,
+    server: Arc<DnsServer>,
+    trusted_proxies: Arc<Acl>,
+    idle_timeout: Duration,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let activity = Arc::new(Mutex::new(Activity {
+        in_flight: 0,
+        last: Instant::now(),
+    }));
+    let service_activity = activity.clone();
+    let service = service_fn(move |request: Request<_>| {
+        let server = server.clone();
+        let activity = service_activity.clone();
+        let client = client_addr(request.headers(), src, &trusted_proxies);
+        activity.lock().unwrap().in_flight += 1;
+        async move {
+            let response = handle_http(request, client, &server).await;
+            let mut activity = activity.lock().unwrap();
+            activity.in_flight -= 1;
+            activity.last = Instant::now();
+            Ok::<_, Infallible>(response)
+        }
+    });
+    let builder = Builder::new(TokioExecutor::new());
+    let mut connection = pin!(builder.serve_connection(TokioIo::new(stream), service));
+
+    loop {
+        let idle = {
+            let activity = activity.lock().unwrap();
+            match activity.in_flight {
+                0 => activity.last.elapsed(),
+                _ => Duration::ZERO,
+            }
+        };
+        if idle >= idle_timeout {
+            return Ok(());
+        }
+        if let Ok(result) = timeout(idle_timeout - idle, connection.as_mut()).await {
+            return result;
+        }
+    }
+}
+
+/// The address of the client of a request received from `src`.
+/// A trusted proxy tells it in the `Forwarded` header (RFC 7239)
+/// or else in `X-Forwarded-For`, each proxy appending the address
+/// it received the request from: the client is the last address
+/// that is not a trusted proxy.
+///
+/// takes: `(&HeaderMap, SocketAddr, &Acl)` = (headers, src,
+/// trusted proxies)
+///
+/// returns: `SocketAddr`, `src` unless it is a trusted proxy
+/// telling another address
+pub fn client_addr(headers: &HeaderMap, src: SocketAddr, trusted_proxies: &Acl) -> SocketAddr {
+    if !trusted_proxies.allows(src.ip()) {
+        return src;
+    }
+    let forwarded: Vec<&str> = match headers.contains_key(FORWARDED) {
+        true => headers
+            .get_all(FORWARDED)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+                    .map(|(_, value)| value.trim_matches('"'))
+            })
+            .collect(),
+        false => headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect(),
+    };
+
+    let mut client = src;
+    for node in forwarded.into_iter().rev() {
+        let Some(addr) = parse_node(node) else {
+            break;
+        };
+        client = addr;
+        if !trusted_proxies.allows(addr.ip()) {
+            break;
+        }
+    }
+    client
+}
+
+/// An address with an optional port, `[]` around an IPv6 one
+/// that has a port, as in `Forwarded`
+fn parse_node(node: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    let addr = node.trim_start_matches('[').trim_end_matches(']');
+    addr.parse::<IpAddr>()
+        .ok()
+        .map(|addr| SocketAddr::new(addr, 0))
+}
+
+/// Answers an HTTP request carrying a DNS query, either in the
+/// base64url `dns` parameter of a GET or as the body of a POST
+/// (RFC 8484 section 4.1). The response may be cached for the
+/// smallest TTL of its records.
+///
+/// takes: `(Request<B>, SocketAddr, &DnsServer)`
+///
+/// returns: `Response<Full<Bytes>>`, an HTTP error when the
+/// request does not carry a valid DNS query, `403` rather than
+/// `400` when the client is denied
+pub async fn handle_http<B>(
+    request: Request<B>,
+    src: SocketAddr,
+    server: &DnsServer,
+) -> Response<Full<Bytes>>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    if request.uri().path() != DNS_QUERY_PATH {
+        return status(StatusCode::NOT_FOUND);
+    }
+    let data = match *request.method() {
+        Method::GET => {
+            let data = query_param(request.uri().query(), "dns")
+                .and_then(|dns| URL_SAFE_NO_PAD.decode(dns.trim_end_matches('=')).ok());
+            match data {
+                Some(data) => data,
+                None => return status(StatusCode::BAD_REQUEST),
+            }
+        }
+        Method::POST => {
+            // the media type, without its parameters
+            let media_type = request
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .and_then(|content_type| content_type.split(';').next());
+            if media_type
+                .is_none_or(|media_type| !media_type.trim().eq_ignore_ascii_case(DNS_MESSAGE))
+            {
+                return status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+            }
+            match Limited::new(request.into_body(), MAX_MESSAGE_SIZE)
+                .collect()
+                .await
+            {
+                Ok(body) => body.to_bytes().to_vec(),
+                Err(err) if err.is::<LengthLimitError>() => {
+                    return status(StatusCode::PAYLOAD_TOO_LARGE)
+                }
+                Err(_) => return status(StatusCode::BAD_REQUEST),
+            }
+        }
+        _ => return status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+
+    let mut buffer = PacketBuffer::from_bytes(&data);
+    buffer.set_strict(true);
+    let request = match DnsMessage::from_buf(&mut buffer) {
+        Ok(request) => request,
+        Err(_) if server.access(src.ip()) == Access::Deny => return status(StatusCode::FORBIDDEN),
+        Err(err) => {
+            println!("Malformed query from {}: {}", src, err);
+            return status(StatusCode::BAD_REQUEST);
+        }
+    };
+    let Some(mut response) = server.handle_request(&request, src).await else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+
+    // RFC 8484 section 5.1
+    let max_age = response
+        .answers
+        .iter()
+        .chain(&response.authorities)
+        .map(DnsRecord::ttl)
+        .min();
+    let buffer = match response.into_buf_with_size(MAX_MESSAGE_SIZE) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            println!("Failed to write the response to {}: {}", src, err);
+            return status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let data = Bytes::copy_from_slice(&buffer.buf[..buffer.pos()]);
+    let mut builder = Response::builder().header(CONTENT_TYPE, DNS_MESSAGE);
+    if let Some(max_age) = max_age {
+        builder = builder.header(CACHE_CONTROL, format!("max-age={}", max_age));
+    }
+    builder
+        .body(Full::new(data))
+        .expect("the headers are valid")
+}
+
+/// The value of the parameter `name` in a query string, the
+/// base64url alphabet needs no percent-decoding
+fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::default())
+        .expect("an empty response is valid")
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+use tokio::sync::Notify;
+
+use crate::authority::catalog::Catalog;
+use crate::authority::dnssec::ZoneSigner;
+use crate::authority::{transfer, update, zone_file};
+use crate::dns_message::dns_header::{Opcode, ResultCode};
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::tsig::{self, Keyring, Signer};
+use crate::dns_message::{DnsClass, DnsMessage, QueryType};
+use crate::dns_resolver::forward::Forwarder;
+use crate::dns_resolver::hosts::Hosts;
+use crate::dns_resolver::policy::Policy;
+use crate::errors::{unknown_tsig_key, Result};
+use crate::settings::acl::Access;
+use crate::settings::config::{
+    AccessSettings, ApplicationSettings, CdnSettings, ChaosSettings, Settings, ZoneSettings,
+};
+use crate::{authority, dns_resolver, load_balancer};
+
+use rrl::RateLimiter;
+
+pub mod chaos;
+pub mod doq;
+pub mod https;
+pub mod rrl;
+pub mod tcp;
+pub mod tls;
+pub mod udp;
+
+/// State shared by the listeners, each transport parses its
+/// requests and hands them to `handle_request`.
+pub struct DnsServer {
+    pub application: ApplicationSettings,
+    pub chaos: ChaosSettings,
+    pub catalog: Catalog,
+    zones: Vec<ZoneSettings>,
+    keyring: Keyring,
+    /// the zones signed online
+    signers: HashMap<DnsName, ZoneSigner>,
+    /// wake the refresh of a secondary zone up on NOTIFY
+    notifiers: HashMap<DnsName, Arc<Notify>>,
+    /// updates are applied one at a time
+    updating: Mutex<()>,
+    /// the servers are refreshed before each load balanced query
+    cdn: RwLock<CdnSettings>,
+    /// the names answered before recursion
+    hosts: Hosts,
+    /// the upstreams resolving the queries, if forwarding
+    forwarder: Option<Forwarder>,
+    /// the blocklists applied to the resolved names, if any
+    policy: Option<Arc<Policy>>,
+    /// limits the responses sent over UDP, if enabled
+    rate_limiter: Option<RateLimiter>,
+    access: AccessSettings,
+}
+
+impl DnsServer {
+    /// Builds the server out of its settings, loading the zones
+    ///
+    /// takes: `Settings`
+    ///
+    /// returns: `Result<DnsServer>`, an error if a zone is invalid
+    /// or names an unknown or invalid key, or if a static record,
+    /// an upstream, a policy list or the rate limiting is invalid
+    pub fn new(settings: Settings) -> Result<Self> {
+        let keyring = Keyring::new(settings.tsig_keys);
+        if let Some(key) = settings
+            .zones
+            .iter()
+            .filter_map(|zone| zone.key.as_ref())
+            .find(|key| keyring.get(key).is_none())
+        {
+            return Err(unknown_tsig_key(&key.to_string()));
+        }
+
+        let catalog = Catalog::load(&settings.zones)?;
+        let mut signers = HashMap::new();
+        for zone in &settings.zones {
+            let Some(ref dnssec) = zone.dnssec else {
+                continue;
+            };
+            let signer = ZoneSigner::new(dnssec)?;
+            if let Some(current) = catalog.get(&zone.origin) {
+                let ds = signer.ksk().ds(&zone.origin, current.soa().ttl())?;
+                println!("Signing {}, DS record for the parent: {}", zone.origin, ds);
+            }
+            signers.insert(zone.origin.clone(), signer);
+        }
+
+        Ok(Self {
+            application: settings.application,
+            chaos: settings.chaos,
+            catalog,
+            notifiers: settings
+                .zones
+                .iter()
+                .filter(|zone| zone.primary.is_some())
+                .map(|zone| (zone.origin.clone(), Arc::new(Notify::new())))
+                .collect(),
+            zones: settings.zones,
+            keyring,
+            signers,
+            updating: Mutex::new(()),
+            cdn: RwLock::new(settings.cdn),
+            hosts: Hosts::new(&settings.hosts)?,
+            forwarder: settings.forward.as_ref().map(Forwarder::new).transpose()?,
+            policy: settings
+                .policy
+                .as_ref()
+                .map(Policy::new)
+                .transpose()?
+                .map(Arc::new),
+            rate_limiter: settings.rrl.as_ref().map(RateLimiter::new).transpose()?,
+            access: settings.access,
+        })
+    }
+
+    /// Answers a request received from `src`, dispatching it on
+    /// its opcode. Standard queries, NOTIFY and UPDATE are
+    /// implemented, other opcodes get NOTIMP. The response to a
+    /// TSIG signed request is signed.
+    ///
+    /// takes: `(&self, &DnsMessage, SocketAddr)`
+    ///
+    /// returns: `Option<DnsMessage>`, `None` when the request is
+    /// itself a response: answering it could start a loop between
+    /// two servers, or reflect spoofed traffic. Also `None` when
+    /// the access rules drop the request.
+    pub async fn handle_request(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+    ) -> Option<DnsMessage> {
+        if request.header.response {
+            println!("Dropped response from {}", src);
+            return None;
+        }
+        let signer = match self.keyring.verify(request) {
+            Ok(signer) => signer,
+            Err(rescode) => return Some(self.tsig_error(request, rescode, src)),
+        };
+        let key = signer.as_ref().map(|signer| &signer.key().name);
+
+        let mut response = match request.header.opcode {
+            Opcode::QUERY => self.handle_query(request, src, key).await?,
+            Opcode::NOTIFY => self.handle_notify(request, src, key),
+            Opcode::UPDATE => self.handle_update(request, src, key)?,
+            opcode => {
+                println!("Unsupported opcode {} from {}", opcode, src);
+                DnsMessage::response_to(request)
+                    .rescode(ResultCode::NOTIMP)
+                    .build()
+            }
+        };
+        if let Some(mut signer) = signer {
+            sign(&mut signer, &mut response);
+        }
+        Some(response)
+    }
+
+    /// The response to a request whose TSIG record failed with
+    /// `rescode`
+    fn tsig_error(&self, request: &DnsMessage, rescode: ResultCode, src: SocketAddr) -> DnsMessage {
+        println!(
+            "Failed TSIG verification from {}: {}",
+            src,
+            tsig::error_name(rescode)
+        );
+        self.keyring.error_response(request, rescode)
+    }
+
+    pub fn zones(&self) -> &[ZoneSettings] {
+        &self.zones
+    }
+
+    pub fn policy(&self) -> Option<&Arc<Policy>> {
+        self.policy.as_ref()
+    }
+
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+
+    /// The strictest access rule matching `addr` among the
+    /// services the server runs, for the requests none of them
+    /// answers: the CHAOS queries and the malformed ones
+    pub fn access(&self, addr: IpAddr) -> Access {
+        let mut rules = vec![&self.access.authoritative];
+        if self.application.is_load_balancer {
+            rules.push(&self.access.load_balancer);
+        } else if !self.application.is_authoritative {
+            rules.push(&self.access.recursion);
+        }
+        rules
+            .into_iter()
+            .map(|rules| rules.check(addr))
+            .fold(Access::Allow, |strictest, access| {
+                match (strictest, access) {
+                    (Access::Deny, _) | (_, Access::Deny) => Access::Deny,
+                    (Access::Refuse, _) | (_, Access::Refuse) => Access::Refuse,
+                    _ => Access::Allow,
+                }
+            })
+    }
+
+    /// Wakes the refresh of the secondary zone `origin` up
+    pub fn notifier(&self, origin: &DnsName) -> Option<Arc<Notify>> {
+        self.notifiers.get(origin).cloned()
+    }
+
+    /// Answers NOTIFY (RFC 1996): a change of a secondary zone
+    /// announced by its primary, signed with the key of the zone
+    /// if it has one, triggers a refresh. Other senders are
+    /// refused.
+    fn handle_notify(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+        key: Option<&DnsName>,
+    ) -> DnsMessage {
+        let response = DnsMessage::response_to(request);
+        let Some(question) = request.questions.first() else {
+            return response.rescode(ResultCode::FORMERR).build();
+        };
+        let settings = self.zones.iter().find(|zone| zone.origin == question.qname);
+        let primary = settings.and_then(|zone| zone.primary);
+        let (Some(settings), Some(primary), Some(notifier)) =
+            (settings, primary, self.notifier(&question.qname))
+        else {
+            return response.rescode(ResultCode::NOTAUTH).build();
+        };
+        if primary.ip().to_canonical() != src.ip().to_canonical() || !is_signed_for(settings, key) {
+            println!("Refused NOTIFY of {} from {}", question.qname, src);
+            return response.rescode(ResultCode::REFUSED).build();
+        }
+
+        println!("Received NOTIFY of {} from {}", question.qname, src);
+        notifier.notify_one();
+        response.authoritative(true).build()
+    }
+
+    /// Applies UPDATE (RFC 2136) to a primary zone, for the
+    /// clients its ACL allows only, signed with the key of the
+    /// zone if it has one. The new version of the zone is saved
+    /// to its file before it is served.
+    fn handle_update(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+        key: Option<&DnsName>,
+    ) -> Option<DnsMessage> {
+        match self.access.update.check(src.ip()) {
+            Access::Allow => {}
+            access => return denied(request, src, access),
+        }
+        Some(self.apply_update(request, src, key))
+    }
+
+    fn apply_update(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+        key: Option<&DnsName>,
+    ) -> DnsMessage {
+        let response = DnsMessage::response_to(request);
+        let origin = match update::zone_name(request) {
+            Ok(origin) => origin,
+            Err(rescode) => return response.rescode(rescode).build(),
+        };
+        let settings = self.zones.iter().find(|zone| zone.origin == *origin);
+        let Some(settings) = settings.filter(|_| self.catalog.get(origin).is_some()) else {
+            return response.rescode(ResultCode::NOTAUTH).build();
+        };
+        if settings.primary.is_some()
+            || !settings.allow_update.allows(src.ip())
+            || !is_signed_for(settings, key)
+        {
+            println!("Refused update of {} from {}", origin, src);
+            return response.rescode(ResultCode::REFUSED).build();
+        }
+
+        let _updating = self.updating.lock().unwrap();
+        let Some(zone) = self.catalog.get(origin) else {
+            return response.rescode(ResultCode::NOTAUTH).build();
+        };
+        let zone = match update::apply(request, &zone) {
+            Ok(Some(zone)) => zone,
+            Ok(None) => return response.build(),
+            Err(rescode) => return response.rescode(rescode).build(),
+        };
+        if let Err(err) = zone_file::write(Path::new(&settings.file), &zone) {
+            println!("Failed to save {}: {}", origin, err);
+            return response.rescode(ResultCode::SERVFAIL).build();
+        }
+        println!(
+            "Updated {} to serial {} from {}",
+            origin,
+            zone.serial(),
+            src
+        );
+        self.catalog.update(zone);
+        response.build()
+    }
+
+    /// Answers a request received over a stream transport, where
+    /// zone transfers are allowed and take as many messages as
+    /// they need.
+    ///
+    /// takes: `(&self, &DnsMessage, SocketAddr)`
+    ///
+    /// returns: `Vec<DnsMessage>`, empty when the request is a
+    /// response or is dropped. Each message of a signed transfer
+    /// is signed.
+    pub async fn handle_stream_request(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+    ) -> Vec<DnsMessage> {
+        let is_transfer = !request.header.response
+            && request.header.opcode == Opcode::QUERY
+            && request
+                .questions
+                .first()
+                .is_some_and(|question| is_transfer(question.qtype));
+        if is_transfer {
+            let signer = match self.keyring.verify(request) {
+                Ok(signer) => signer,
+                Err(rescode) => return vec![self.tsig_error(request, rescode, src)],
+            };
+            let key = signer.as_ref().map(|signer| &signer.key().name);
+            let mut messages = self.handle_transfer(request, src, key);
+            if let Some(mut signer) = signer {
+                for message in &mut messages {
+                    sign(&mut signer, message);
+                }
+            }
+            return messages;
+        }
+        self.handle_request(request, src)
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Transfers the zone named by the question, to the clients
+    /// its ACL allows only, signed with the key of the zone if it
+    /// has one. A name that is not the origin of a zone gets
+    /// NOTAUTH.
+    fn handle_transfer(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+        key: Option<&DnsName>,
+    ) -> Vec<DnsMessage> {
+        match self.access.transfer.check(src.ip()) {
+            Access::Allow => {}
+            access => return denied(request, src, access).into_iter().collect(),
+        }
+        let question = &request.questions[0];
+        let refuse = |rescode| vec![DnsMessage::response_to(request).rescode(rescode).build()];
+        let Some(zone) = self.catalog.get(&question.qname) else {
+            return refuse(ResultCode::NOTAUTH);
+        };
+        if !self.allows_transfer(zone.origin(), src, key) {
+            println!("Refused transfer of {} to {}", zone.origin(), src);
+            return refuse(ResultCode::REFUSED);
+        }
+
+        println!("Transfer to {}: {}", src, question);
+        let messages = match question.qtype {
+            QueryType::AXFR => transfer::axfr(request, &zone),
+            _ => transfer::ixfr(request, &zone),
+        };
+        messages.unwrap_or_else(|err| {
+            println!("Failed transfer of {}: {}", zone.origin(), err);
+            refuse(ResultCode::SERVFAIL)
+        })
+    }
+
+    fn allows_transfer(&self, origin: &DnsName, src: SocketAddr, key: Option<&DnsName>) -> bool {
+        self.zones
+            .iter()
+            .find(|zone| zone.origin == *origin)
+            .is_some_and(|zone| zone.allow_transfer.allows(src.ip()) && is_signed_for(zone, key))
+    }
+
+    /// Answers a standard query. The class of the question picks
+    /// the handler: `CH` gets the CHAOS answers and the classes
+    /// other than `IN` and `ANY` are not implemented.
+    ///
+    /// Names are answered, in order, by the load balancer for its
+    /// hostname, from the zones, and then by the load balancer,
+    /// the static records, the forwarder or the resolver unless
+    /// the server is only authoritative. The zones signed online
+    /// sign their answers, the policies apply to the static,
+    /// forwarded and resolved ones.
+    ///
+    /// The access rules of the service answering apply first, the
+    /// CHAOS queries get the strictest ones of the server.
+    async fn handle_query(
+        &self,
+        request: &DnsMessage,
+        src: SocketAddr,
+        key: Option<&DnsName>,
+    ) -> Option<DnsMessage> {
+        let Some(question) = request.questions.first() else {
+            return Some(
+                DnsMessage::response_to(request)
+                    .rescode(ResultCode::FORMERR)
+                    .build(),
+            );
+        };
+        println!("Received query from {}: {}", src, question);
+
+        match question.qclass {
+            DnsClass::IN | DnsClass::ANY => {}
+            DnsClass::CH => {
+                return match self.access(src.ip()) {
+                    Access::Allow => Some(chaos::handle_query(request, &self.chaos)),
+                    access => denied(request, src, access),
+                }
+            }
+            _ => {
+                return Some(
+                    DnsMessage::response_to(request)
+                        .rescode(ResultCode::NOTIMP)
+                        .build(),
+                )
+            }
+        }
+
+        match question.qtype {
+            // RFC 5936 section 4.2, AXFR is a stream transport
+            // affair
+            QueryType::AXFR => {
+                return Some(
+                    DnsMessage::response_to(request)
+                        .rescode(ResultCode::NOTIMP)
+                        .build(),
+                )
+            }
+            // RFC 1995 section 2, a transfer too large for a
+            // datagram is answered with the SOA alone, the client
+            // then retries over TCP
+            QueryType::IXFR => {
+                let mut messages = self.handle_transfer(request, src, key).into_iter();
+                let mut message = messages.next()?;
+                if messages.next().is_some() || message.clone().into_buf().is_err() {
+                    message.answers.truncate(1);
+                    message.header.answers = message.answers.len() as u16;
+                }
+                return Some(message);
+            }
+            _ => {}
+        }
+
+        let is_cdn_hostname = question.qname == self.cdn.read().unwrap().hostname;
+        if self.application.is_load_balancer && is_cdn_hostname {
+            return self.load_balance(request, src);
+        }
+        if let Some(zone) = self.catalog.find(&question.qname) {
+            match self.access.authoritative.check(src.ip()) {
+                Access::Allow => {}
+                access => return denied(request, src, access),
+            }
+            return Some(match self.signers.get(zone.origin()) {
+                Some(signer) => signer.handle_query(request, &zone),
+                None => authority::handle_query::handle_query(request, &zone),
+            });
+        }
+
+        if self.application.is_authoritative {
+            return Some(
+                DnsMessage::response_to(request)
+                    .rescode(ResultCode::REFUSED)
+                    .build(),
+            );
+        } else if self.application.is_load_balancer {
+            return self.load_balance(request, src);
+        }
+        match self.access.recursion.check(src.ip()) {
+            Access::Allow => {}
+            access => return denied(request, src, access),
+        }
+        let resolve = |request: DnsMessage| async move {
+            let forwarder = self.forwarder.as_ref();
+            dns_resolver::handle_query::handle_query(&request, &self.hosts, forwarder).await
+        };
+        match self.policy {
+            Some(ref policy) => policy.handle_query(request, resolve).await,
+            None => Some(resolve(request.clone()).await),
+        }
+    }
+
+    fn load_balance(&self, request: &DnsMessage, src: SocketAddr) -> Option<DnsMessage> {
+        match self.access.load_balancer.check(src.ip()) {
+            Access::Allow => {}
+            access => return denied(request, src, access),
+        }
+        let mut cdn = self.cdn.write().unwrap();
+        cdn.check_up_servers();
+        Some(load_balancer::handle_query::handle_query(
+            request, &src, &cdn,
+        ))
+    }
+}
+
+/// The response to a request the access rules do not allow:
+/// REFUSED, or none when it is dropped
+fn denied(request: &DnsMessage, src: SocketAddr, access: Access) -> Option<DnsMessage> {
+    println!("Denied request from {}: {:?}", src, access);
+    match access {
+        Access::Deny => None,
+        _ => Some(
+            DnsMessage::response_to(request)
+                .rescode(ResultCode::REFUSED)
+                .build(),
+        ),
+    }
+}
+
+fn is_transfer(qtype: QueryType) -> bool {
+    matches!(qtype, QueryType::AXFR | QueryType::IXFR)
+}
+
+/// Whether a request signed with `key` meets the key `zone`
+/// requires, if any
+fn is_signed_for(zone: &ZoneSettings, key: Option<&DnsName>) -> bool {
+    zone.key.is_none() || zone.key.as_ref() == key
+}
+
+/// Signs a response, one that can not be signed is sent as it is
+fn sign(signer: &mut Signer, response: &mut DnsMessage) {
+    if let Err(err) = signer.sign(response) {
+        println!("Failed to sign response: {}", err);
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::dns_message::dns_header::ResultCode;
+use crate::dns_message::dns_name::DnsName;
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::errors::{invalid_rrl_prefix, invalid_rrl_setting, Result};
+use crate::settings::config::RrlSettings;
+
+/// The accounts kept at most, the responses of the others share
+/// a single overflow balance until the sweep makes room
+pub const MAX_ACCOUNTS: usize = 1 << 16;
+
+/// What to do with a response to a datagram
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Send,
+    /// send it truncated, a real client retries over TCP
+    Slip,
+    Drop,
+}
+
+/// Response rate limiting, against the use of the server as a
+/// reflection amplifier: a client network receiving the same
+/// response too often gets it dropped, or truncated now and then.
+///
+/// Each client network and response has a balance, credited with
+/// `responses_per_second` a second up to as much, and debited by
+/// each response. The responses sent while it is negative are
+/// limited, and it can sink to a whole window of responses.
+/// Once a window, the accounts idle for as long are forgotten:
+/// their balance is full again.
+pub struct RateLimiter {
+    settings: RrlSettings,
+    accounts: Mutex<Accounts>,
+    dropped: AtomicU64,
+    slipped: AtomicU64,
+}
+
+struct Accounts {
+    balances: HashMap<Account, Balance>,
+    /// the balance of the accounts past `MAX_ACCOUNTS`
+    overflow: Balance,
+    swept: Instant,
+}
+
+/// The responses a client network shares a balance for
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Account {
+    network: IpAddr,
+    response: Response,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Response {
+    Answer(DnsName, QueryType),
+    /// the names that do not exist count for their zone, or the
+    /// random names of an attack would each get a balance
+    NxDomain(DnsName),
+    Error(ResultCode),
+}
+
+struct Balance {
+    responses: f64,
+    updated: Instant,
+    limited: u64,
+}
+
+impl RateLimiter {
+    /// takes: `&RrlSettings`
+    ///
+    /// returns: `Result<RateLimiter>`, an error if a prefix is
+    /// longer than the addresses, or if the rate or the window is
+    /// 0, which would never limit anything
+    pub fn new(settings: &RrlSettings) -> Result<Self> {
+        if settings.responses_per_second == 0 {
+            return Err(invalid_rrl_setting("responses_per_second"));
+        }
+        if settings.window == 0 {
+            return Err(invalid_rrl_setting("window"));
+        }
+        if settings.ipv4_prefix > 32 {
+            return Err(invalid_rrl_prefix(settings.ipv4_prefix));
+        }
+        if settings.ipv6_prefix > 128 {
+            return Err(invalid_rrl_prefix(settings.ipv6_prefix));
+        }
+        Ok(Self {
+            settings: settings.clone(),
+            accounts: Mutex::new(Accounts {
+                balances: HashMap::new(),
+                overflow: Balance::new(settings.responses_per_second as f64),
+                swept: Instant::now(),
+            }),
+            dropped: AtomicU64::new(0),
+            slipped: AtomicU64::new(0),
+        })
+    }
+
+    /// Debits the balance of `response` for the network of `src`
+    ///
+    /// takes: `(&self, IpAddr, &DnsMessage)`
+    ///
+    /// returns: `Verdict`, whether the response is sent, sent
+    /// truncated, or dropped
+    pub fn check(&self, src: IpAddr, response: &DnsMessage) -> Verdict {
+        let src = src.to_canonical();
+        if self.settings.exempt.allows(src) {
+            return Verdict::Send;
+        }
+        let account = Account {
+            network: self.network(src),
+            response: Response::of(response),
+        };
+        let rate = self.settings.responses_per_second as f64;
+        let window = Duration::from_secs(self.settings.window);
+        let now = Instant::now();
+
+        let mut accounts = self.accounts.lock().unwrap();
+        let Accounts {
+            balances,
+            overflow,
+            swept,
+        } = &mut *accounts;
+        if now.duration_since(*swept) >= window {
+            balances.retain(|_, balance| now.duration_since(balance.updated) < window);
+            *swept = now;
+        }
+        let balance = match balances.len() < MAX_ACCOUNTS || balances.contains_key(&account) {
+            true => balances
+                .entry(account)
+                .or_insert_with(|| Balance::new(rate)),
+            false => overflow,
+        };
+        let elapsed = now.duration_since(balance.updated).as_secs_f64();
+        balance.responses = (balance.responses + elapsed * rate).min(rate) - 1.0;
+        balance.responses = balance.responses.max(-rate * window.as_secs_f64());
+        balance.updated = now;
+        if balance.responses >= 0.0 {
+            return Verdict::Send;
+        }
+
+        balance.limited += 1;
+        let slip = self.settings.slip as u64;
+        if slip > 0 && balance.limited.is_multiple_of(slip) {
+            self.slipped.fetch_add(1, Ordering::Relaxed);
+            Verdict::Slip
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            Verdict::Drop
+        }
+    }
+
+    /// The accounts kept, at most `MAX_ACCOUNTS`
+    pub fn accounts(&self) -> usize {
+        self.accounts.lock().unwrap().balances.len()
+    }
+
+    /// The responses dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The responses sent truncated so far
+    pub fn slipped(&self) -> u64 {
+        self.slipped.load(Ordering::Relaxed)
+    }
+
+    fn network(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.settings.ipv4_prefix as u32)
+                    .unwrap_or(0);
+                Ipv4Addr::from(u32::from(addr) & mask).into()
+            }
+            IpAddr::V6(addr) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.settings.ipv6_prefix as u32)
+                    .unwrap_or(0);
+                Ipv6Addr::from(u128::from(addr) & mask).into()
+            }
+        }
+    }
+}
+
+impl Balance {
+    fn new(responses: f64) -> Self {
+        Self {
+            responses,
+            updated: Instant::now(),
+            limited: 0,
+        }
+    }
+}
+
+impl Response {
+    fn of(message: &DnsMessage) -> Self {
+        let Some(question) = message.questions.first() else {
+            return Self::Error(message.header.rescode);
+        };
+        match message.header.rescode {
+            ResultCode::NOERROR => Self::Answer(question.qname.clone(), question.qtype),
+            ResultCode::NXDOMAIN => {
+                let zone = message
+                    .authorities
+                    .iter()
+                    .find(|rec| rec.qtype() == QueryType::SOA)
+                    .map_or(&question.qname, |soa| &soa.domain);
+                Self::NxDomain(zone.clone())
+            }
+            rescode => Self::Error(rescode),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+use crate::dns_message::packet_buffer::PacketBuffer;
+use crate::dns_message::DnsMessage;
+use crate::errors::Result;
+use crate::settings::acl::Access;
+
+use super::DnsServer;
+
+/// How long a connection may stay without a query before it is
+/// closed (RFC 7766 section 6.2.3)
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the connections accepted on `listener`, each one in
+/// its own task. A failure only closes the connection it
+/// happened on.
+pub async fn serve(listener: TcpListener, server: Arc<DnsServer>) {
+    loop {
+        let (stream, src) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                println!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, src, &server).await {
+                println!("Failed to handle connection from {}: {}", src, err);
+            }
+        });
+    }
+}
+
+/// Answers the queries of a connection one after the other,
+/// each message preceded by its length on two bytes (RFC 1035
+/// section 4.2.2), until the client closes it or goes idle.
+pub async fn handle_connection<S>(stream: S, src: SocketAddr, server: &DnsServer) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    handle_stream(stream, src, server, IDLE_TIMEOUT).await
+}
+
+/// `handle_connection` over any stream with the same framing,
+/// e.g. TLS, closed after `idle_timeout` without a query
+pub async fn handle_stream<S>(
+    mut stream: S,
+    src: SocketAddr,
+    server: &DnsServer,
+    idle_timeout: Duration,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let mut buffer = match timeout(idle_timeout, read_frame(&mut stream)).await {
+            Err(_) => return Ok(()),
+            Ok(Err(err)) if is_eof(&*err) => return Ok(()),
+            Ok(frame) => frame?,
+        };
+        buffer.set_strict(true);
+
+        let messages = match DnsMessage::from_buf(&mut buffer) {
+            Ok(request) => server.handle_stream_request(&request, src).await,
+            Err(_) if server.access(src.ip()) == Access::Deny => Vec::new(),
+            Err(err) => {
+                println!("Malformed query from {}: {}", src, err);
+                DnsMessage::format_error(&mut buffer).into_iter().collect()
+            }
+        };
+        for message in messages {
+            write_message(&mut stream, message).await?;
+        }
+    }
+}
+
+/// Writes `message` to `stream`, preceded by its length
+pub async fn write_message<S>(stream: &mut S, mut message: DnsMessage) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let buffer = message.into_buf_with_size(u16::MAX as usize)?;
+    let data = buffer.get_range(0, buffer.pos())?;
+    stream.write_all(&(data.len() as u16).to_be_bytes()).await?;
+    stream.write_all(data).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Reads a message written by `write_message`
+pub async fn read_message<S>(stream: &mut S) -> Result<DnsMessage>
+where
+    S: AsyncRead + Unpin,
+{
+    DnsMessage::from_buf(&mut read_frame(stream).await?)
+}
+
+async fn read_frame<S>(stream: &mut S) -> Result<PacketBuffer>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).await?;
+    let mut buffer = PacketBuffer::with_size(u16::from_be_bytes(len) as usize);
+    stream.read_exact(&mut buffer.buf).await?;
+    Ok(buffer)
+}
+
+/// Whether `err` is the client closing the connection
+fn is_eof(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == ErrorKind::UnexpectedEof)
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::crypto::ring::{default_provider, Ticketer};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+
+use crate::errors::{invalid_tls_file, Result};
+use crate::settings::config::{DotSettings, TlsSettings};
+
+use super::{tcp, DnsServer};
+
+/// The ALPN protocol of DNS over TLS (RFC 7858 section 3.1)
+pub const DOT_ALPN: &[u8] = b"dot";
+
+/// Builds the TLS configuration of a listener out of the files
+/// named by `settings`, offering the `alpn` protocols. Sessions
+/// are resumed from tickets or from the server cache.
+///
+/// takes: `(&TlsSettings, &[&[u8]])`
+///
+/// returns: `Result<Arc<ServerConfig>>`
+pub fn server_config(settings: &TlsSettings, alpn: &[&[u8]]) -> Result<Arc<ServerConfig>> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(
+            certificates(&settings.certificate)?,
+            private_key(&settings.key)?,
+        )?;
+    config.ticketer = Ticketer::new()?;
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+    Ok(Arc::new(config))
+}
+
+/// The certificates of the PEM file at `path`
+pub fn certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).map_err(|err| invalid_tls_file(path, &err.to_string()))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|err| invalid_tls_file(path, &err.to_string()))?;
+    match certificates.is_empty() {
+        true => Err(invalid_tls_file(path, "no certificate")),
+        false => Ok(certificates),
+    }
+}
+
+/// The private key of the PEM file at `path`
+pub fn private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).map_err(|err| invalid_tls_file(path, &err.to_string()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| invalid_tls_file(path, &err.to_string()))?
+        .ok_or_else(|| invalid_tls_file(path, "no private key"))
+}
+
+/// Serves DNS over TLS (RFC 7858) on `listener`: the framing of
+/// TCP inside a TLS session. At most `max_connections` are
+/// served at once, each one closed after `idle_timeout` seconds
+/// without a query.
+pub async fn serve(listener: TcpListener, settings: DotSettings, server: Arc<DnsServer>) {
+    let config = match server_config(&settings.tls, &[DOT_ALPN]) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("Failed to start DNS over TLS: {}", err);
+            return;
+        }
+    };
+    let acceptor = TlsAcceptor::from(config);
+    let idle_timeout = Duration::from_secs(settings.idle_timeout);
+    let connections = Arc::new(Semaphore::new(settings.max_connections));
+
+    loop {
+        let permit = connections
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let (stream, src) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                println!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, src, &acceptor, &server, idle_timeout).await
+            {
+                println!("Failed to handle TLS connection from {}: {}", src, err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Completes the handshake, which must not take longer than the
+/// idle timeout, then answers the queries of the connection
+async fn handle_connection(
+    stream: TcpStream,
+    src: SocketAddr,
+    acceptor: &TlsAcceptor,
+    server: &DnsServer,
+    idle_timeout: Duration,
+) -> Result<()> {
+    let stream = match timeout(idle_timeout, acceptor.accept(stream)).await {
+        Ok(stream) => stream?,
+        Err(_) => return Ok(()),
+    };
+    tcp::handle_stream(stream, src, server, idle_timeout).await
+}
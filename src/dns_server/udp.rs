@@ -0,0 +1,146 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+use tokio::sync::Semaphore;
+
+use crate::dns_message::edns::DEFAULT_PAYLOAD_SIZE;
+use crate::dns_message::packet_buffer::{PacketBuffer, BUF_SIZE};
+use crate::dns_message::{DnsMessage, QueryType};
+use crate::errors::Result;
+use crate::settings::acl::Access;
+
+use super::rrl::Verdict;
+use super::DnsServer;
+
+/// The packets answered at once, the next ones wait in the
+/// receive buffer of the socket
+const MAX_IN_FLIGHT: usize = 1024;
+
+/// Serves the queries received on `socket`, each one on its own
+/// task so that a slow lookup does not hold the others up. A
+/// failure is reported and only drops the packet it happened on.
+pub async fn serve(socket: UdpSocket, server: Arc<DnsServer>) {
+    let socket = Arc::new(socket);
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+
+    loop {
+        let permit = in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let (buffer, src) = match receive(&socket).await {
+            Ok(received) => received,
+            Err(err) => {
+                println!("Failed to receive packet: {}", err);
+                continue;
+            }
+        };
+        let socket = socket.clone();
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(err) = answer(&socket, &server, buffer, src).await {
+                println!("Failed to handle packet: {}", err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Handle a single incoming packet
+pub async fn handle_packet(socket: &UdpSocket, server: &DnsServer) -> Result<()> {
+    let (buffer, src) = receive(socket).await?;
+    answer(socket, server, buffer, src).await
+}
+
+async fn receive(socket: &UdpSocket) -> Result<(PacketBuffer, SocketAddr)> {
+    // a query may be as large as a datagram, e.g. a signed UPDATE
+    let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+    let (len, src) = socket.recv_from(&mut buffer.buf).await?;
+    buffer.truncate(len);
+    Ok((buffer, src))
+}
+
+/// Answers the packet received from `src`
+async fn answer(
+    socket: &UdpSocket,
+    server: &DnsServer,
+    mut recv_buffer: PacketBuffer,
+    src: SocketAddr,
+) -> Result<()> {
+    recv_buffer.set_strict(true);
+
+    let (message, request) = match DnsMessage::from_buf(&mut recv_buffer) {
+        Ok(request) => (server.handle_request(&request, src).await, Some(request)),
+        Err(_) if server.access(src.ip()) == Access::Deny => return Ok(()),
+        Err(err) => {
+            println!("Malformed query from {}: {}", src, err);
+            (DnsMessage::format_error(&mut recv_buffer), None)
+        }
+    };
+    let request = request.as_ref();
+    let size = request.map_or(BUF_SIZE, payload_size);
+    let Some(mut message) = message else {
+        return Ok(());
+    };
+    if let Some(rate_limiter) = server.rate_limiter() {
+        match rate_limiter.check(src.ip(), &message) {
+            Verdict::Send => {}
+            Verdict::Slip => {
+                truncate(&mut message, request, server);
+            }
+            Verdict::Drop => return Ok(()),
+        }
+    }
+
+    let send_buffer = match message.clone().into_buf_with_size(size) {
+        Ok(buffer) => buffer,
+        Err(_) => truncate(&mut message, request, server).into_buf_with_size(size)?,
+    };
+    let data = send_buffer.get_range(0, send_buffer.pos())?;
+    socket.send_to(data, src).await?;
+
+    Ok(())
+}
+
+/// The largest response `request` accepts over UDP: the payload
+/// size its EDNS record advertises, capped to the default one to
+/// avoid fragmentation, or 512 bytes without EDNS
+pub fn payload_size(request: &DnsMessage) -> usize {
+    match request.edns() {
+        Some(edns) => edns
+            .udp_payload_size
+            .clamp(BUF_SIZE as u16, DEFAULT_PAYLOAD_SIZE) as usize,
+        None => BUF_SIZE,
+    }
+}
+
+/// Empties a response too large for a datagram and sets its TC
+/// bit, the client retries over TCP (RFC 2181 section 9). The
+/// response to a signed request is signed again, it must not go
+/// out unsigned (RFC 8945 section 5.3).
+fn truncate<'a>(
+    message: &'a mut DnsMessage,
+    request: Option<&DnsMessage>,
+    server: &DnsServer,
+) -> &'a mut DnsMessage {
+    let signed = message
+        .resources
+        .iter()
+        .any(|rec| rec.qtype() == QueryType::TSIG);
+    message.header.truncated_message = true;
+    message.answers.clear();
+    message.authorities.clear();
+    message
+        .resources
+        .retain(|rec| rec.qtype() == QueryType::OPT);
+
+    let signer = request.and_then(|request| server.keyring().verify(request).ok().flatten());
+    if let (true, Some(mut signer)) = (signed, signer) {
+        if let Err(err) = signer.sign(message) {
+            println!("Failed to sign response: {}", err);
+        }
+    }
+    message
+}
@@ -1,4 +1,4 @@
-type Error = Box<dyn std::error::Error>;
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub fn index_out_of_bound() -> Error {
@@ -9,10 +9,124 @@ pub fn jumps_limit(limit: u8) -> Error {
     format!("Error: Exceeded limit of {} jumps!", limit).into()
 }
 
+pub fn bad_compression_pointer(offset: usize) -> Error {
+    format!("Error: Invalid compression pointer to offset {}", offset).into()
+}
+
 pub fn label_len_limit() -> Error {
     "Error: Single label exceeds 63 characters limit".into()
 }
 
+pub fn name_len_limit() -> Error {
+    "Error: Name exceeds 255 bytes limit".into()
+}
+
+pub fn empty_label() -> Error {
+    "Error: Name contains an empty label".into()
+}
+
+pub fn character_string_len_limit() -> Error {
+    "Error: Character string exceeds 255 characters limit".into()
+}
+
+pub fn rdata_len_mismatch(qtype: &str, len: u16) -> Error {
+    format!("Error: Record data length {} is invalid for {}", len, qtype).into()
+}
+
+pub fn invalid_presentation(text: &str) -> Error {
+    format!("Error: Invalid presentation format `{}`", text).into()
+}
+
+pub fn unknown_qtype(value: &str) -> Error {
+    format!("Error: Unknown record type `{}`", value).into()
+}
+
+pub fn missing_json_rdata(name: &str) -> Error {
+    format!("Error: Record `{}` has neither rdata nor RDATAHEX", name).into()
+}
+
+pub fn zone_file_error(path: &str, line: usize, err: &str) -> Error {
+    format!(
+        "Error: {}:{}: {}",
+        path,
+        line,
+        err.trim_start_matches("Error: ")
+    )
+    .into()
+}
+
+pub fn invalid_zone(origin: &str, reason: &str) -> Error {
+    format!("Error: Zone `{}` is invalid, {}", origin, reason).into()
+}
+
+pub fn failed_transfer(origin: &str, reason: &str) -> Error {
+    format!("Error: Transfer of `{}` failed, {}", origin, reason).into()
+}
+
+pub fn invalid_network(text: &str) -> Error {
+    format!("Error: Invalid network `{}`", text).into()
+}
+
+pub fn unknown_tsig_algorithm(name: &str) -> Error {
+    format!("Error: Unknown TSIG algorithm `{}`", name).into()
+}
+
+pub fn unknown_tsig_key(name: &str) -> Error {
+    format!("Error: Unknown TSIG key `{}`", name).into()
+}
+
+pub fn invalid_dnssec_key(path: &str, reason: &str) -> Error {
+    format!("Error: Invalid DNSSEC key `{}`, {}", path, reason).into()
+}
+
+pub fn failed_signing(algorithm: &str) -> Error {
+    format!("Error: Failed to sign with {}", algorithm).into()
+}
+
+pub fn invalid_tls_file(path: &str, reason: &str) -> Error {
+    format!("Error: Invalid TLS file `{}`, {}", path, reason).into()
+}
+
+pub fn invalid_rrl_prefix(prefix_len: u8) -> Error {
+    format!(
+        "Error: Invalid rate limiting prefix length `{}`",
+        prefix_len
+    )
+    .into()
+}
+
+pub fn invalid_rrl_setting(name: &str) -> Error {
+    format!(
+        "Error: Invalid rate limiting setting `{}`, must not be 0",
+        name
+    )
+    .into()
+}
+
+pub fn invalid_hosts_file(path: &str, reason: &str) -> Error {
+    format!("Error: Invalid hosts file `{}`, {}", path, reason).into()
+}
+
+pub fn invalid_static_record(name: &str, reason: &str) -> Error {
+    format!("Error: Invalid static record `{}`, {}", name, reason).into()
+}
+
+pub fn invalid_policy_list(path: &str, reason: &str) -> Error {
+    format!("Error: Invalid policy list `{}`, {}", path, reason).into()
+}
+
+pub fn invalid_upstream(address: &str, reason: &str) -> Error {
+    format!("Error: Invalid upstream `{}`, {}", address, reason).into()
+}
+
+pub fn failed_upstream(address: &str, reason: &str) -> Error {
+    format!("Error: Upstream `{}` failed, {}", address, reason).into()
+}
+
+pub fn no_upstream_answered() -> Error {
+    "Error: No upstream answered".into()
+}
+
 pub fn failed_json_parse<'a>() -> &'a str {
     "Failed to parse JSON string"
 }
@@ -36,6 +150,10 @@ pub fn failed_socket_bind<'a>() -> &'a str {
     "Failed to bind socket address to `UdpSocket`"
 }
 
+pub fn failed_listener_bind<'a>() -> &'a str {
+    "Failed to bind socket address to `TcpListener`"
+}
+
 pub fn failed_cdn_down<'a>() -> &'a str {
     "The CDN is currently down, Try again later!"
 }
@@ -47,3 +165,7 @@ pub fn failed_current_dir<'a>() -> &'a str {
 pub fn failed_request_execution<'a>() -> &'a str {
     "Failed to execute request"
 }
+
+pub fn failed_zone_load<'a>() -> &'a str {
+    "Failed to load the zones"
+}
@@ -1,5 +1,7 @@
+pub mod authority;
 pub mod dns_message;
 pub mod dns_resolver;
+pub mod dns_server;
 pub mod errors;
 pub mod load_balancer;
 pub mod settings;
@@ -16,6 +16,12 @@ pub struct ConnectionList {
     connections: Vec<Connection>,
 }
 
+impl Default for ConnectionList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConnectionList {
     pub fn new() -> Self {
         Self {
@@ -31,10 +37,11 @@ impl ConnectionList {
         })
     }
     pub fn read_connections(path: &str) -> Self {
-        let json_file = std::fs::read_to_string(path).expect(&failed_path_read(path));
+        let json_file =
+            std::fs::read_to_string(path).unwrap_or_else(|_| panic!("{}", failed_path_read(path)));
 
-        let connections =
-            serde_json::from_str::<Vec<Connection>>(&json_file).expect(failed_json_parse());
+        let connections = serde_json::from_str::<Vec<Connection>>(&json_file)
+            .unwrap_or_else(|_| panic!("{}", failed_json_parse()));
         Self { connections }
     }
 }
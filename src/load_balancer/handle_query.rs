@@ -1,36 +1,27 @@
-use tokio::net::UdpSocket;
+use std::net::SocketAddr;
 
 use crate::dns_message::dns_header::ResultCode;
 use crate::dns_message::dns_record::DnsRecord;
-use crate::dns_message::packet_buffer::PacketBuffer;
 use crate::dns_message::DnsMessage;
-use crate::errors::{failed_cdn_down, Result};
+use crate::errors::failed_cdn_down;
 use crate::settings::config::CdnSettings;
 
 use super::connection::ConnectionList;
 
-pub async fn handle_query(socket: &UdpSocket, config: &mut CdnSettings) -> Result<()> {
-    let mut recv_buffer = PacketBuffer::new();
+/// Answers `request` with the address of the CDN server
+/// assigned to `src`
+///
+/// takes: `(&DnsMessage, &SocketAddr, &CdnSettings)`
+///
+/// returns: `DnsMessage`
+pub fn handle_query(request: &DnsMessage, src: &SocketAddr, config: &CdnSettings) -> DnsMessage {
+    let mut message = DnsMessage::response_to(request).build();
 
-    let (_, src) = socket.recv_from(&mut recv_buffer.buf).await?;
-    let src = src.to_string();
-    config.check_up_servers();
-
-    let mut request = DnsMessage::from_buf(&mut recv_buffer)?;
-
-    let mut message = DnsMessage::new();
-    message.header.id = request.header.id;
-    message.header.recursion_desired = request.header.recursion_desired;
-    message.header.response = true;
-
-    if let Some(question) = request.questions.pop() {
-        println!("Received query: {:#?}", question);
+    if let Some(question) = request.questions.first() {
         if question.qname == config.hostname {
-            message.questions.push(question);
             message.header.rescode = ResultCode::NOERROR;
 
-            construct_record(&src, &mut message, &config);
-            // message.answers.push(DnsRecord::new_a(addr, hostname))
+            construct_record(&src.ip().to_string(), &mut message, config);
         } else {
             message.header.rescode = ResultCode::SERVFAIL;
         }
@@ -38,13 +29,7 @@ pub async fn handle_query(socket: &UdpSocket, config: &mut CdnSettings) -> Resul
         message.header.rescode = ResultCode::FORMERR;
     }
 
-    let send_buffer = message.into_buf()?;
-
-    let len = send_buffer.pos();
-    let data = send_buffer.get_range(0, len)?;
-    socket.send_to(data, src).await?;
-
-    Ok(())
+    message
 }
 
 fn construct_record(src: &str, message: &mut DnsMessage, config: &CdnSettings) {
@@ -52,12 +37,13 @@ fn construct_record(src: &str, message: &mut DnsMessage, config: &CdnSettings) {
     let hostname = &config.hostname;
     let connections = ConnectionList::read_connections(&config.connections_path);
     let addr = match connections
-        .iter_servers(src.split(":").next().unwrap())
-        .filter(|server| up_servers.contains(&server.to_string()))
-        .next()
+        .iter_servers(src)
+        .find(|server| up_servers.contains(&server.to_string()))
     {
         Some(addr) => addr,
-        None => up_servers.first().expect(failed_cdn_down()),
+        None => up_servers
+            .first()
+            .unwrap_or_else(|| panic!("{}", failed_cdn_down())),
     };
     let record = DnsRecord::new_a(addr, hostname);
     message.answers.push(record)
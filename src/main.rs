@@ -1,23 +1,69 @@
-use cdn_dns::errors::{failed_config_read, failed_socket_bind};
+use std::sync::Arc;
+
+use cdn_dns::authority::secondary;
+use cdn_dns::dns_resolver::policy;
+use cdn_dns::dns_server::{doq, https, tcp, tls, udp, DnsServer};
+use cdn_dns::errors::{
+    failed_config_read, failed_listener_bind, failed_socket_bind, failed_zone_load,
+};
 use cdn_dns::settings::config::get_config;
 
-use cdn_dns::dns_resolver::handle_query::handle_query as resolver;
-use cdn_dns::load_balancer::handle_query::handle_query as load_balancer;
-use tokio::net::UdpSocket;
+use tokio::net::{TcpListener, UdpSocket};
 
+// the error messages are built by the helpers in `errors.rs`
+#[allow(clippy::expect_fun_call)]
 #[tokio::main]
 async fn main() {
-    let mut config = get_config().expect(failed_config_read());
+    let config = get_config().expect(failed_config_read());
 
     let socket_addr = format!("{}:{}", config.application.host, config.application.port);
-    let socket = UdpSocket::bind(socket_addr)
+    let socket = UdpSocket::bind(&socket_addr)
         .await
         .expect(failed_socket_bind());
-    if config.application.is_load_balancer {
-        loop {
-            load_balancer(&socket, &mut config.cdn).await.unwrap();
+    let listener = TcpListener::bind(&socket_addr)
+        .await
+        .expect(failed_listener_bind());
+    let dot = match config.dot.clone() {
+        Some(dot) => {
+            let addr = format!("{}:{}", config.application.host, dot.port);
+            let listener = TcpListener::bind(&addr)
+                .await
+                .expect(failed_listener_bind());
+            Some((listener, dot))
+        }
+        None => None,
+    };
+    let doh = match config.doh.clone() {
+        Some(doh) => {
+            let addr = format!("{}:{}", config.application.host, doh.port);
+            let listener = TcpListener::bind(&addr)
+                .await
+                .expect(failed_listener_bind());
+            Some((listener, doh))
+        }
+        None => None,
+    };
+    let doq = match config.doq.clone() {
+        Some(doq) => {
+            let addr = format!("{}:{}", config.application.host, doq.port);
+            let socket = UdpSocket::bind(&addr).await.expect(failed_socket_bind());
+            Some((socket, doq))
         }
-    } else {
-        resolver(&socket).await.unwrap();
+        None => None,
+    };
+
+    let server = Arc::new(DnsServer::new(config).expect(failed_zone_load()));
+    secondary::spawn(&server);
+    policy::spawn(&server);
+    tokio::spawn(tcp::serve(listener, server.clone()));
+    if let Some((listener, dot)) = dot {
+        tokio::spawn(tls::serve(listener, dot, server.clone()));
+    }
+    if let Some((listener, doh)) = doh {
+        tokio::spawn(https::serve(listener, doh, server.clone()));
+    }
+    if let Some((socket, doq)) = doq {
+        tokio::spawn(doq::serve(socket, doq, server.clone()));
     }
+    udp::serve(socket, server).await;
 }
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::errors::{invalid_network, Error, Result};
+
+/// A network in CIDR notation (`192.0.2.0/24`), a bare address
+/// is a network of a single host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+/// A list of networks, empty it matches no address
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Acl {
+    networks: Vec<Network>,
+}
+
+/// What an access rule does with the clients it matches
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    #[default]
+    Allow,
+    /// answer REFUSED
+    Refuse,
+    /// drop the request silently
+    Deny,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AccessRule {
+    pub networks: Acl,
+    pub action: Access,
+}
+
+/// Access rules tried in order, the first one matching a client
+/// applies. A client no rule matches is allowed.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct AccessRules {
+    rules: Vec<AccessRule>,
+}
+
+impl Network {
+    /// The length of the prefix, in bits
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Acl {
+    pub fn new(networks: Vec<Network>) -> Self {
+        Self { networks }
+    }
+
+    /// Whether `addr` lies in one of the networks, IPv4 mapped
+    /// IPv6 addresses are matched as IPv4.
+    pub fn allows(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        self.networks.iter().any(|network| network.contains(addr))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+impl AccessRules {
+    pub fn new(rules: Vec<AccessRule>) -> Self {
+        Self { rules }
+    }
+
+    /// What the first rule matching `addr` does with it
+    pub fn check(&self, addr: IpAddr) -> Access {
+        self.rules
+            .iter()
+            .find(|rule| rule.networks.allows(addr))
+            .map_or(Access::Allow, |rule| rule.action)
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid_network(s))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse::<u8>().map_err(|_| invalid_network(s))?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(invalid_network(s));
+        }
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl<'de> Deserialize<'de> for Network {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse()
+            .map_err(|err: Error| D::Error::custom(err.to_string()))
+    }
+}
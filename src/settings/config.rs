@@ -1,25 +1,67 @@
+use std::net::SocketAddr;
+
 use config::{Config, ConfigError, File};
 
 use crate::{
+    dns_message::{dns_name::DnsName, tsig::TsigKey},
     errors::{failed_current_dir, failed_env_parse},
-    settings::Request,
+    settings::{
+        acl::{AccessRules, Acl},
+        Request,
+    },
 };
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub cdn: CdnSettings,
+    #[serde(default)]
+    pub chaos: ChaosSettings,
+    #[serde(default)]
+    pub zones: Vec<ZoneSettings>,
+    /// the keys of the TSIG signed requests
+    #[serde(default)]
+    pub tsig_keys: Vec<TsigKey>,
+    /// the DNS over TLS listener, if any
+    #[serde(default)]
+    pub dot: Option<DotSettings>,
+    /// the DNS over HTTPS endpoint, if any
+    #[serde(default)]
+    pub doh: Option<DohSettings>,
+    /// the DNS over QUIC listener, if any
+    #[serde(default)]
+    pub doq: Option<DoqSettings>,
+    /// the upstreams the queries are forwarded to, rather than
+    /// resolved from the root
+    #[serde(default)]
+    pub forward: Option<ForwardSettings>,
+    /// response rate limiting over UDP, off by default
+    #[serde(default)]
+    pub rrl: Option<RrlSettings>,
+    /// who may use each service of the server, everyone by
+    /// default
+    #[serde(default)]
+    pub access: AccessSettings,
+    /// the blocklists and response policy zones of the resolver
+    #[serde(default)]
+    pub policy: Option<PolicySettings>,
+    /// the names answered locally, before recursion
+    #[serde(default)]
+    pub hosts: HostsSettings,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct ApplicationSettings {
     pub is_load_balancer: bool,
+    /// only answer from the zones, refusing every other name
+    #[serde(default)]
+    pub is_authoritative: bool,
     pub port: u16,
     pub host: String,
 }
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct CdnSettings {
-    pub hostname: String,
+    pub hostname: DnsName,
     pub connections_path: String,
     pub port: u16,
     pub servers: Vec<String>,
@@ -35,7 +77,7 @@ impl CdnSettings {
         let mut up_servers = vec![];
         let mut req = Request::new(self.port);
         for server in &self.servers {
-            req.set_addr(&server);
+            req.set_addr(server);
             if super::health_check::check(&req).await {
                 up_servers.push(server.as_str());
             }
@@ -44,6 +86,365 @@ impl CdnSettings {
     }
 }
 
+/// A zone served with authority, loaded from a master file. A
+/// secondary zone is transferred from its primary and saved to
+/// the file.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct ZoneSettings {
+    pub origin: DnsName,
+    pub file: String,
+    /// the primary server of a secondary zone
+    #[serde(default)]
+    pub primary: Option<SocketAddr>,
+    /// the clients allowed to transfer the zone, none by default
+    #[serde(default)]
+    pub allow_transfer: Acl,
+    /// the clients allowed dynamic updates, none by default
+    #[serde(default)]
+    pub allow_update: Acl,
+    /// the TSIG key transfers, NOTIFY and updates must be signed
+    /// with on top of the ACLs, a secondary signs its transfers
+    #[serde(default)]
+    pub key: Option<DnsName>,
+    /// signs the answers on the fly
+    #[serde(default)]
+    pub dnssec: Option<DnssecSettings>,
+}
+
+/// Online DNSSEC signing of a zone, with keys of algorithm
+/// ECDSAP256SHA256 (13) or ED25519 (15)
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DnssecSettings {
+    /// PKCS#8 PEM file of the key signing key, which signs the
+    /// DNSKEY RRset
+    pub ksk: String,
+    /// PKCS#8 PEM file of the zone signing key
+    pub zsk: String,
+    /// denial of existence with NSEC3 rather than NSEC
+    #[serde(default)]
+    pub nsec3: Option<Nsec3Settings>,
+    /// seconds a signature is valid, it is renewed once three
+    /// quarters of it went by
+    #[serde(default = "default_validity")]
+    pub validity: u32,
+}
+
+/// The NSEC3 parameters, no salt and no extra iteration by
+/// default as RFC 9276 advises
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct Nsec3Settings {
+    /// in hex
+    pub salt: String,
+    pub iterations: u16,
+}
+
+fn default_validity() -> u32 {
+    14 * 86400
+}
+
+/// Answers to the CHAOS class `TXT` queries identifying the
+/// server, a name left unset is refused. Disabled and empty by
+/// default, nothing about the server is disclosed unless asked.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct ChaosSettings {
+    pub enabled: bool,
+    /// `version.bind` and `version.server`
+    pub version: Option<String>,
+    /// `hostname.bind`
+    pub hostname: Option<String>,
+    /// `id.server`
+    pub id: Option<String>,
+}
+
+/// The access rules of the clients, one list per action
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct AccessSettings {
+    /// the names the resolver or the forwarder answers
+    #[serde(default)]
+    pub recursion: AccessRules,
+    /// the names the load balancer answers
+    #[serde(default)]
+    pub load_balancer: AccessRules,
+    /// the names answered from the zones
+    #[serde(default)]
+    pub authoritative: AccessRules,
+    /// AXFR and IXFR, before the ACL of the zone
+    #[serde(default)]
+    pub transfer: AccessRules,
+    /// UPDATE, before the ACL of the zone
+    #[serde(default)]
+    pub update: AccessRules,
+}
+
+/// Names answered by the resolver itself, out of hosts files
+/// (`/etc/hosts` format) and records listed here. The addresses
+/// of the hosts files and of the A and AAAA records get a PTR
+/// record, unless their reverse name has one.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HostsSettings {
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub records: Vec<StaticRecord>,
+    /// TTL of the records answered
+    #[serde(default = "default_hosts_ttl")]
+    pub ttl: u32,
+}
+
+impl Default for HostsSettings {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            records: Vec::new(),
+            ttl: default_hosts_ttl(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct StaticRecord {
+    pub name: DnsName,
+    #[serde(rename = "type")]
+    pub qtype: StaticRecordType,
+    /// the data in presentation format, e.g. `192.0.2.1` or
+    /// `www.example.com.`
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum StaticRecordType {
+    A,
+    AAAA,
+    CNAME,
+    PTR,
+}
+
+fn default_hosts_ttl() -> u32 {
+    300
+}
+
+/// Response policies applied to the answers of the resolver and
+/// the forwarder, the first list matching a query decides
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PolicySettings {
+    pub lists: Vec<PolicyListSettings>,
+    /// seconds between two checks of the files, the ones changed
+    /// are reloaded
+    #[serde(default = "default_reload_interval")]
+    pub reload_interval: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PolicyListSettings {
+    pub file: String,
+    pub format: ListFormat,
+    /// what the names of a `domains` or `hosts` list get, the
+    /// rules of a zone carry their own action
+    #[serde(default)]
+    pub action: BlockAction,
+    /// the origin of an `rpz` zone
+    #[serde(default)]
+    pub origin: Option<DnsName>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    /// a name per line, `*.` blocks the subdomains of a name
+    Domains,
+    /// `address name...` lines, `0.0.0.0` and `::` block the
+    /// names, other addresses answer them
+    Hosts,
+    /// a response policy zone
+    Rpz,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockAction {
+    #[default]
+    Nxdomain,
+    Nodata,
+    /// no response at all
+    Drop,
+}
+
+fn default_reload_interval() -> u64 {
+    60
+}
+
+/// Response rate limiting: the same response is sent to a
+/// client network at most `responses_per_second` times a second
+/// on average over `window` seconds
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RrlSettings {
+    pub responses_per_second: u32,
+    /// one limited response in `slip` is sent truncated, so that
+    /// real clients retry over TCP, the others are dropped. 0
+    /// drops them all.
+    #[serde(default = "default_slip")]
+    pub slip: u32,
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// the prefix lengths of the client networks
+    #[serde(default = "default_ipv4_prefix")]
+    pub ipv4_prefix: u8,
+    #[serde(default = "default_ipv6_prefix")]
+    pub ipv6_prefix: u8,
+    /// the clients never limited
+    #[serde(default)]
+    pub exempt: Acl,
+}
+
+fn default_slip() -> u32 {
+    2
+}
+
+fn default_window() -> u64 {
+    15
+}
+
+fn default_ipv4_prefix() -> u8 {
+    24
+}
+
+fn default_ipv6_prefix() -> u8 {
+    56
+}
+
+/// Forwarding to upstream resolvers, tried in order
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ForwardSettings {
+    pub upstreams: Vec<UpstreamSettings>,
+    #[serde(default)]
+    pub fallback: Fallback,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct UpstreamSettings {
+    /// `ip:port`, or the URL of the endpoint for `https`
+    pub address: String,
+    #[serde(default)]
+    pub transport: Transport,
+    /// the name the certificate of a `tls` upstream must hold,
+    /// its IP address by default
+    #[serde(default)]
+    pub tls_name: Option<String>,
+    /// PEM file of the authorities trusted for `tls` and
+    /// `https`, rather than the web PKI
+    #[serde(default)]
+    pub ca: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// UDP, retried over TCP when the answer is truncated
+    #[default]
+    Udp,
+    Tcp,
+    /// DNS over TLS (RFC 7858)
+    Tls,
+    /// DNS over HTTPS (RFC 8484)
+    Https,
+}
+
+/// What happens once an upstream failed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fallback {
+    /// the next upstream is tried, whatever its transport
+    #[default]
+    Any,
+    /// after an encrypted upstream, only encrypted upstreams are
+    /// tried: the query never leaves in clear
+    Encrypted,
+    /// the query fails
+    None,
+}
+
+/// A certificate chain and its private key, both PEM files
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TlsSettings {
+    pub certificate: String,
+    pub key: String,
+}
+
+/// DNS over TLS (RFC 7858), listening on the host of the
+/// application
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DotSettings {
+    #[serde(default = "default_dot_port")]
+    pub port: u16,
+    #[serde(flatten)]
+    pub tls: TlsSettings,
+    /// seconds a connection may stay without a query
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+    /// connections served at once, the next ones wait to be
+    /// accepted
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+}
+
+/// DNS over HTTPS (RFC 8484), served on `/dns-query` over
+/// HTTP/2 and HTTP/1.1. Without a certificate the endpoint is
+/// plain HTTP, for a proxy terminating TLS in front of it.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DohSettings {
+    #[serde(default = "default_doh_port")]
+    pub port: u16,
+    #[serde(flatten)]
+    pub tls: Option<TlsSettings>,
+    /// seconds a connection may stay without a request
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+    /// connections served at once, the next ones wait to be
+    /// accepted
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// the proxies whose `Forwarded` or `X-Forwarded-For` header
+    /// tells the address of the client. Any other proxy is the
+    /// client itself for the access rules and the rate limiting.
+    #[serde(default)]
+    pub trusted_proxies: Acl,
+}
+
+fn default_doh_port() -> u16 {
+    443
+}
+
+/// DNS over QUIC (RFC 9250), listening over UDP on the host of
+/// the application
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DoqSettings {
+    #[serde(default = "default_dot_port")]
+    pub port: u16,
+    #[serde(flatten)]
+    pub tls: TlsSettings,
+    /// seconds a connection may stay without a query
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+    /// connections served at once, the next ones wait to be
+    /// accepted
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+}
+
+fn default_dot_port() -> u16 {
+    853
+}
+
+fn default_idle_timeout() -> u64 {
+    10
+}
+
+fn default_max_connections() -> usize {
+    1024
+}
+
 pub enum Environment {
     Local,
     Production,
@@ -71,7 +472,7 @@ impl TryFrom<String> for Environment {
 }
 
 pub fn get_config() -> Result<Settings, ConfigError> {
-    let base_path = std::env::current_dir().expect(failed_current_dir());
+    let base_path = std::env::current_dir().unwrap_or_else(|_| panic!("{}", failed_current_dir()));
     let config_dir = base_path.join("config");
 
     let environment: Environment = std::env::var("APP_ENV")
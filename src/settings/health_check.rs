@@ -5,9 +5,9 @@ use super::Request;
 pub async fn check<'a>(request: &Request<'a>) -> bool {
     let response = request
         .client
-        .get(&format!("http://{}/health_check", request.socket_addr()))
+        .get(format!("http://{}/health_check", request.socket_addr()))
         .send()
         .await
-        .expect(failed_request_execution());
+        .unwrap_or_else(|_| panic!("{}", failed_request_execution()));
     response.status().is_success()
 }
@@ -1,5 +1,6 @@
 use reqwest::Client;
 
+pub mod acl;
 pub mod config;
 pub mod health_check;
 
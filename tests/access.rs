@@ -0,0 +1,154 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdn_dns::dns_message::dns_header::{Opcode, ResultCode};
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsClass, DnsMessage, QueryType};
+use cdn_dns::dns_server::{udp, DnsServer};
+use cdn_dns::settings::acl::{Access, AccessRule, AccessRules, Acl};
+use cdn_dns::settings::config::{AccessSettings, ZoneSettings};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+fn rules(rules: &[(&str, Access)]) -> AccessRules {
+    AccessRules::new(
+        rules
+            .iter()
+            .map(|(network, action)| AccessRule {
+                networks: Acl::new(vec![network.parse().unwrap()]),
+                action: *action,
+            })
+            .collect(),
+    )
+}
+
+fn server(access: AccessSettings) -> DnsServer {
+    let mut config = common::config(vec![ZoneSettings {
+        allow_transfer: Acl::new(vec!["0.0.0.0/0".parse().unwrap()]),
+        allow_update: Acl::new(vec!["0.0.0.0/0".parse().unwrap()]),
+        ..common::zone("example.com", "example.com.zone")
+    }]);
+    config.application.is_load_balancer = false;
+    config.access = access;
+    DnsServer::new(config).unwrap()
+}
+
+fn query(qname: &str, qtype: QueryType) -> DnsMessage {
+    DnsMessage::query(&qname.parse().unwrap(), qtype)
+        .id(7)
+        .build()
+}
+
+fn src(ip: &str) -> SocketAddr {
+    SocketAddr::new(ip.parse().unwrap(), 5353)
+}
+
+#[test]
+fn access_rules_test() {
+    let rules = rules(&[
+        ("192.0.2.1", Access::Allow),
+        ("192.0.2.0/24", Access::Deny),
+        ("::/0", Access::Refuse),
+    ]);
+    assert_eq!(rules.check("192.0.2.1".parse().unwrap()), Access::Allow);
+    assert_eq!(rules.check("192.0.2.2".parse().unwrap()), Access::Deny);
+    assert_eq!(
+        rules.check("::ffff:192.0.2.2".parse().unwrap()),
+        Access::Deny
+    );
+    assert_eq!(rules.check("2001:db8::1".parse().unwrap()), Access::Refuse);
+    assert_eq!(rules.check("198.51.100.1".parse().unwrap()), Access::Allow);
+    assert_eq!(
+        AccessRules::default().check("192.0.2.1".parse().unwrap()),
+        Access::Allow
+    );
+}
+
+#[tokio::test]
+async fn access_test() {
+    let server = server(AccessSettings {
+        recursion: rules(&[
+            ("192.0.2.0/24", Access::Deny),
+            ("0.0.0.0/0", Access::Refuse),
+        ]),
+        authoritative: rules(&[("192.0.2.0/24", Access::Deny)]),
+        transfer: rules(&[("198.51.100.0/24", Access::Refuse)]),
+        update: rules(&[("198.51.100.0/24", Access::Deny)]),
+        ..Default::default()
+    });
+
+    // the resolver is closed, the zones are open to everyone else
+    let request = query("www.example.net", QueryType::A);
+    let response = server.handle_request(&request, src("198.51.100.1")).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::REFUSED);
+    assert!(server
+        .handle_request(&request, src("192.0.2.1"))
+        .await
+        .is_none());
+
+    let request = query("web.example.com", QueryType::A);
+    let response = server.handle_request(&request, src("198.51.100.1")).await;
+    assert_eq!(response.unwrap().answers.len(), 1);
+    assert!(server
+        .handle_request(&request, src("192.0.2.1"))
+        .await
+        .is_none());
+
+    let request = query("example.com", QueryType::AXFR);
+    let messages = server
+        .handle_stream_request(&request, src("198.51.100.1"))
+        .await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].header.rescode, ResultCode::REFUSED);
+    let messages = server
+        .handle_stream_request(&request, src("203.0.113.1"))
+        .await;
+    assert_eq!(messages[0].header.rescode, ResultCode::NOERROR);
+
+    let mut request = query("example.com", QueryType::SOA);
+    request.header.opcode = Opcode::UPDATE;
+    assert!(server
+        .handle_request(&request, src("198.51.100.1"))
+        .await
+        .is_none());
+
+    // CHAOS gets the strictest rules of the server
+    let mut request = query("version.bind", QueryType::TXT);
+    request.questions[0].qclass = DnsClass::CH;
+    let response = server.handle_request(&request, src("198.51.100.1")).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::REFUSED);
+    assert!(server
+        .handle_request(&request, src("192.0.2.1"))
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn malformed_access_test() {
+    let server = Arc::new(server(AccessSettings {
+        authoritative: rules(&[("127.0.0.2", Access::Deny)]),
+        ..Default::default()
+    }));
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(udp::serve(socket, server));
+
+    // a header announcing a question it does not hold
+    let malformed = [0, 7, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for (client, answered) in [("127.0.0.1:0", true), ("127.0.0.2:0", false)] {
+        let client = UdpSocket::bind(client).await.unwrap();
+        client.connect(addr).await.unwrap();
+        client.send(&malformed).await.unwrap();
+        let mut buffer = PacketBuffer::new();
+        let received = timeout(Duration::from_millis(300), client.recv(&mut buffer.buf)).await;
+        assert_eq!(received.is_ok(), answered);
+        if let Ok(len) = received {
+            buffer.truncate(len.unwrap());
+            let response = DnsMessage::from_buf(&mut buffer).unwrap();
+            assert_eq!(response.header.rescode, ResultCode::FORMERR);
+        }
+    }
+}
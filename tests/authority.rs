@@ -0,0 +1,264 @@
+mod common;
+
+use std::path::Path;
+
+use cdn_dns::authority::handle_query::handle_query;
+use cdn_dns::authority::zone::Zone;
+use cdn_dns::authority::zone_file;
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::dns_record::RecordData;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::DnsServer;
+
+fn example_zone() -> Zone {
+    let origin = "example.com".parse().unwrap();
+    Zone::load(origin, Path::new("tests/zones/example.com.zone")).unwrap()
+}
+
+fn ask(zone: &Zone, qname: &str, qtype: QueryType) -> DnsMessage {
+    let request = DnsMessage::query(&qname.parse().unwrap(), qtype)
+        .id(7)
+        .build();
+    handle_query(&request, zone)
+}
+
+#[test]
+fn zone_file_test() {
+    let zone = example_zone();
+    assert_eq!(zone.serial(), 2024010101);
+
+    let soa = zone.soa();
+    assert_eq!(soa.ttl(), 3600);
+    assert_eq!(
+        soa.data.to_string(),
+        "ns1.example.com. hostmaster.example.com. 2024010101 7200 900 604800 300"
+    );
+
+    let records: Vec<String> = zone.records().map(|rec| rec.to_string()).collect();
+    for record in [
+        "example.com.\t3600\tIN\tTXT\t\"v=spf1 mx -all\" \"; not a comment\"",
+        "example.com.\t3600\tIN\tNS\tns2.example.net.",
+        "mail.example.com.\t600\tIN\tA\t192.0.2.25",
+        "mail.example.com.\t3600\tIN\tAAAA\t2001:db8::25",
+        "_sip._udp.example.com.\t3600\tIN\tSRV\t10 60 5060 sip.example.com.",
+        "printer.lan.example.com.\t60\tIN\tA\t192.0.2.100",
+    ] {
+        assert!(records.contains(&record.to_string()), "missing {}", record);
+    }
+
+    // the origin is restored after $INCLUDE
+    let origin = "example.com".parse::<DnsName>().unwrap();
+    let records = zone_file::parse(
+        "$TTL 10\n$INCLUDE tests/zones/hosts.inc lan\n@ SOA ns1 admin 1 2 3 4 5\n",
+        &origin,
+    )
+    .unwrap();
+    assert_eq!(records.last().unwrap().domain, "example.com");
+}
+
+#[test]
+fn zone_file_error_test() {
+    let origin = "example.com".parse::<DnsName>().unwrap();
+    let texts = [
+        ("@ 60 IN A 192.0.2.1\n( 60 IN A 192.0.2.2\n", "<zone>:2"),
+        (
+            "@ 60 IN A 192.0.2.1\n@ 60 IN A not.an.address\n",
+            "<zone>:2",
+        ),
+        ("@ IN A 192.0.2.1\n", "<zone>:1"),
+        ("$BOGUS 1\n", "<zone>:1"),
+        ("@ 60 IN TXT \"unterminated\n", "<zone>:1"),
+    ];
+    for (text, position) in texts {
+        let err = zone_file::parse(text, &origin).unwrap_err().to_string();
+        assert!(err.contains(position), "{} for {:?}", err, text);
+    }
+}
+
+#[test]
+fn invalid_zone_test() {
+    let origin = "example.com".parse::<DnsName>().unwrap();
+    let soa = "@ 60 SOA ns1 admin 1 2 3 4 5\n@ 60 NS ns1\nns1 60 A 192.0.2.1\n";
+    let zones = [
+        ("@ 60 NS ns1\n", "SOA"),
+        (&format!("{}@ 60 SOA ns2 admin 1 2 3 4 5\n", soa), "SOA"),
+        ("@ 60 SOA ns1 admin 1 2 3 4 5\n", "NS"),
+        (
+            &format!("{}www 60 CNAME web\nwww 60 A 192.0.2.2\n", soa),
+            "CNAME",
+        ),
+        (
+            &format!("{}www.example.org. 60 A 192.0.2.2\n", soa),
+            "out of zone",
+        ),
+        (
+            &format!("{}sub 60 NS ns.sub\nhost.sub 60 TXT \"x\"\n", soa),
+            "zone cut",
+        ),
+        (&format!("{}www 60 CH A 192.0.2.2\n", soa), "class"),
+    ];
+    for (text, reason) in zones {
+        let records = zone_file::parse(text, &origin).unwrap();
+        let err = Zone::new(origin.clone(), records).unwrap_err().to_string();
+        assert!(err.contains(reason), "{} for {:?}", err, text);
+    }
+}
+
+#[test]
+fn authoritative_answer_test() {
+    let zone = example_zone();
+
+    let response = ask(&zone, "web.example.com", QueryType::A);
+    assert!(response.header.authoritative_answer);
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert_eq!(response.header.id, 7);
+    assert_eq!(response.answers[0].data.to_string(), "192.0.2.80");
+
+    // the MX target comes along in the additional section
+    let response = ask(&zone, "EXAMPLE.com", QueryType::MX);
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(response.resources.len(), 2);
+
+    // CNAME chains are followed inside the zone only
+    let response = ask(&zone, "alias.example.com", QueryType::A);
+    let answers: Vec<QueryType> = response.answers.iter().map(|rec| rec.qtype()).collect();
+    assert_eq!(answers, [QueryType::CNAME, QueryType::CNAME, QueryType::A]);
+    let response = ask(&zone, "outside.example.com", QueryType::A);
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+}
+
+#[test]
+fn negative_answer_test() {
+    let zone = example_zone();
+
+    let response = ask(&zone, "nowhere.example.com", QueryType::A);
+    assert_eq!(response.header.rescode, ResultCode::NXDOMAIN);
+    assert!(response.header.authoritative_answer);
+    assert!(response.answers.is_empty());
+    assert_eq!(response.authorities[0].qtype(), QueryType::SOA);
+    // negative caching TTL, the SOA minimum
+    assert_eq!(response.authorities[0].ttl(), 300);
+
+    // NODATA for an existing name and for an empty non-terminal
+    for qname in ["web.example.com", "b.c.example.com"] {
+        let response = ask(&zone, qname, QueryType::AAAA);
+        assert_eq!(response.header.rescode, ResultCode::NOERROR);
+        assert!(response.answers.is_empty());
+        assert_eq!(response.authorities[0].qtype(), QueryType::SOA);
+    }
+}
+
+#[test]
+fn referral_test() {
+    let zone = example_zone();
+
+    for qname in ["sub.example.com", "deep.host.sub.example.com"] {
+        let response = ask(&zone, qname, QueryType::A);
+        assert!(!response.header.authoritative_answer);
+        assert_eq!(response.header.rescode, ResultCode::NOERROR);
+        assert!(response.answers.is_empty());
+        assert_eq!(response.authorities[0].qtype(), QueryType::NS);
+        match response.resources[0].data {
+            RecordData::A { addr } => assert_eq!(addr.to_string(), "192.0.2.53"),
+            ref data => panic!("Unexpected glue {}", data),
+        }
+    }
+}
+
+#[tokio::test]
+async fn authoritative_server_test() {
+    let mut config = common::config(vec![common::zone("example.com", "example.com.zone")]);
+    config.application.is_authoritative = true;
+    let server = DnsServer::new(config).expect("Failed to load the zones");
+    let src = "127.0.0.1:5353".parse().unwrap();
+
+    let request = DnsMessage::query(&"www.example.com".parse().unwrap(), QueryType::A).build();
+    let response = server.handle_request(&request, src).await.unwrap();
+    assert!(response.header.authoritative_answer);
+    assert_eq!(response.answers.len(), 2);
+
+    // names outside of the zones are refused
+    let request = DnsMessage::query(&"example.org".parse().unwrap(), QueryType::A).build();
+    let response = server.handle_request(&request, src).await.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::REFUSED);
+
+    // invalid zones are rejected at load time
+    let config = common::config(vec![common::zone("example.org", "example.com.zone")]);
+    assert!(DnsServer::new(config).is_err());
+}
+
+fn wildcard_zone() -> Zone {
+    let origin = "example".parse().unwrap();
+    Zone::load(origin, Path::new("tests/zones/wildcard.example.zone")).unwrap()
+}
+
+#[test]
+fn wildcard_test() {
+    let zone = wildcard_zone();
+
+    // the cases of RFC 4592 section 2.2.1, synthesized answers
+    // are owned by the query name
+    for (qname, qtype) in [
+        ("host3.example", QueryType::MX),
+        ("foo.bar.example", QueryType::TXT),
+    ] {
+        let response = ask(&zone, qname, qtype);
+        assert_eq!(response.header.rescode, ResultCode::NOERROR);
+        assert!(response.header.authoritative_answer);
+        assert_eq!(response.answers.len(), 1, "{}", qname);
+        assert_eq!(response.answers[0].domain, qname);
+        assert_eq!(response.answers[0].qtype(), qtype);
+    }
+    let response = ask(&zone, "host3.example", QueryType::MX);
+    assert_eq!(response.resources[0].domain, "host1.example");
+
+    // NODATA: the wildcard has no A, explicit names and the
+    // names below `*` are not matched
+    for (qname, qtype) in [
+        ("host3.example", QueryType::A),
+        ("host1.example", QueryType::MX),
+        ("sub.*.example", QueryType::MX),
+        ("_tcp.host1.example", QueryType::SRV),
+    ] {
+        let response = ask(&zone, qname, qtype);
+        assert_eq!(response.header.rescode, ResultCode::NOERROR, "{}", qname);
+        assert!(response.answers.is_empty(), "{}", qname);
+        assert_eq!(response.authorities[0].qtype(), QueryType::SOA);
+    }
+
+    // NXDOMAIN: the closest encloser has no wildcard below it
+    for qname in ["_telnet._tcp.host1.example", "ghost.*.example"] {
+        let response = ask(&zone, qname, QueryType::SRV);
+        assert_eq!(response.header.rescode, ResultCode::NXDOMAIN, "{}", qname);
+    }
+    assert_eq!(
+        zone.closest_encloser(&"_telnet._tcp.host1.example".parse().unwrap()),
+        "_tcp.host1.example"
+    );
+    assert_eq!(
+        zone.closest_encloser(&"ghost.*.example".parse().unwrap()),
+        "*.example"
+    );
+
+    // wildcards do not apply below a zone cut
+    let response = ask(&zone, "host.subdel.example", QueryType::A);
+    assert_eq!(response.authorities[0].qtype(), QueryType::NS);
+    assert!(response.answers.is_empty());
+}
+
+#[test]
+fn wildcard_cname_test() {
+    let zone = wildcard_zone();
+
+    let response = ask(&zone, "customer42.cdn.example", QueryType::A);
+    let answers: Vec<String> = response.answers.iter().map(|rec| rec.to_string()).collect();
+    assert_eq!(
+        answers,
+        [
+            "customer42.cdn.example.\t300\tIN\tCNAME\tcdn.example.",
+            "cdn.example.\t300\tIN\tA\t192.0.2.80",
+        ]
+    );
+}
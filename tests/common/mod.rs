@@ -0,0 +1,95 @@
+//! Fixtures shared by the integration tests, each test crate
+//! uses some of them only
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use cdn_dns::dns_server::DnsServer;
+use cdn_dns::settings::config::{get_config, Settings, TlsSettings, ZoneSettings};
+use rustls::pki_types::CertificateDer;
+
+/// A file of the temporary directory, removed once dropped
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Writes `contents` to a file named after `name` and the
+    /// test process
+    pub fn new(name: &str, contents: impl AsRef<[u8]>) -> Self {
+        let file = Self::reserve(name);
+        std::fs::write(&file.path, contents).unwrap();
+        file
+    }
+
+    /// The path of a file named after `name` and the test
+    /// process, left for the code under test to create
+    pub fn reserve(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("cdn-dns-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        Self { path }
+    }
+
+    pub fn path(&self) -> String {
+        self.path.to_string_lossy().into()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A self-signed certificate for `localhost`, its files are
+/// removed once dropped
+pub struct Certificate {
+    pub settings: TlsSettings,
+    pub der: CertificateDer<'static>,
+    _files: [TempFile; 2],
+}
+
+impl Certificate {
+    /// The path of the certificate, for a client to trust it
+    pub fn ca(&self) -> String {
+        self.settings.certificate.clone()
+    }
+}
+
+/// Writes a self-signed certificate for `localhost` and its key
+/// to temporary files named after `name`
+pub fn certificate(name: &str) -> Certificate {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let pem = TempFile::new(&format!("{}.pem", name), certified.cert.pem());
+    let key = TempFile::new(&format!("{}.key", name), certified.key_pair.serialize_pem());
+    Certificate {
+        settings: TlsSettings {
+            certificate: pem.path(),
+            key: key.path(),
+        },
+        der: certified.cert.der().clone(),
+        _files: [pem, key],
+    }
+}
+
+/// A zone of `tests/zones`, e.g. `zone("example.com",
+/// "example.com.zone")`
+pub fn zone(origin: &str, file: &str) -> ZoneSettings {
+    ZoneSettings {
+        origin: origin.parse().unwrap(),
+        file: format!("tests/zones/{}", file),
+        ..Default::default()
+    }
+}
+
+/// The test configuration, serving `zones`
+pub fn config(zones: Vec<ZoneSettings>) -> Settings {
+    let mut config = get_config().expect("Failed to read configuration");
+    config.zones = zones;
+    config
+}
+
+/// A server of `zones` with the test configuration
+pub fn server(zones: Vec<ZoneSettings>) -> DnsServer {
+    DnsServer::new(config(zones)).expect("Failed to load the zones")
+}
@@ -3,6 +3,6 @@ use cdn_dns::settings::config::get_config;
 
 #[test]
 fn config_test() {
-    let config = get_config().expect(failed_config_read());
+    let config = get_config().unwrap_or_else(|_| panic!("{}", failed_config_read()));
     println!("{:#?}", config);
 }
@@ -1,9 +1,13 @@
 use std::io::Read;
 use std::{fs::File, net::UdpSocket};
 
-use cdn_dns::dns_message::dns_question::DnsQuestion;
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::dns_record::{DnsRecord, RecordData};
+use cdn_dns::dns_message::edns::Edns;
+use cdn_dns::dns_message::message_ref::DnsMessageRef;
 use cdn_dns::dns_message::packet_buffer::PacketBuffer;
-use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_message::{DnsClass, DnsMessage, QueryType};
 
 #[test]
 fn dns_message_text_test() {
@@ -12,7 +16,8 @@ fn dns_message_text_test() {
 
     let mut file = File::open(path).expect("Failed to open file.");
     let mut buffer = PacketBuffer::new();
-    file.read(&mut buffer.buf)
+    let _ = file
+        .read(&mut buffer.buf)
         .expect("Failed to read into buffer.");
 
     let message =
@@ -39,18 +44,14 @@ fn dns_message_text_test() {
 #[test]
 fn dns_message_socket_test() {
     let qtype = QueryType::MX;
-    let qname = "www.yahoo.com";
+    let qname = "www.yahoo.com".parse::<DnsName>().unwrap();
     let server = ("8.8.8.8", 53);
     let socket = UdpSocket::bind(("0.0.0.0", 0)).unwrap();
 
-    let mut message = DnsMessage::new();
-
-    message.header.id = 6969;
-    message.header.questions = 1;
-    message.header.recursion_desired = true;
-    message
-        .questions
-        .push(DnsQuestion::new(qname.into(), qtype));
+    let mut message = DnsMessage::query(&qname, qtype)
+        .id(6969)
+        .recursion_desired(true)
+        .build();
 
     let buffer = message.into_buf().unwrap();
     socket
@@ -75,3 +76,380 @@ fn dns_message_socket_test() {
         println!("{:#?}", rec);
     }
 }
+
+#[test]
+fn record_types_round_trip_test() {
+    let records = [
+        ("CAA", "0 issue \"letsencrypt.org\""),
+        ("TXT", "\"v=spf1 -all\" \"second string\""),
+        // bytes that are not UTF-8 are kept as they are
+        ("TXT", "\"caf\\233\" \"\\255\\254\""),
+        ("CAA", "128 issue \"\\200ca\""),
+        (
+            "SOA",
+            "ns1.example.com. admin.example.com. 2024010101 7200 900 604800 300",
+        ),
+        ("SRV", "10 60 5060 sip.example.com."),
+        ("PTR", "host.example.com."),
+        (
+            "TLSA",
+            "3 1 1 0C72AC70B745AC19998811B131D662C9AC69DBDBE7CB23E5B514B56664C5D3D6",
+        ),
+        (
+            "SSHFP",
+            "1 2 BD5C1A6F3B0A1C2D3E4F5A6B7C8D9E0F11223344556677889900AABBCCDDEEFF",
+        ),
+        (
+            "NAPTR",
+            "100 10 \"U\" \"E2U+sip\" \"!^.*$!sip:info@example.com!\" .",
+        ),
+        (
+            "TSIG",
+            "hmac-sha256. 1700000000 300 32 3q2+796tvu/erb7v3q2+796tvu/erb7v3q2+796tvu8= 4660 18 6 AABlU/EA",
+        ),
+        (
+            "DS",
+            "60485 13 2 D4B7D520E7BB5F0F67674A0CCEB1E3E0614B93C4F9E99B8383F6A1E4469DA50A",
+        ),
+        (
+            "RRSIG",
+            "A 13 2 300 20240131235959 20240101000000 60485 example.com. AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0+Pw==",
+        ),
+        ("NSEC", "www.example.com. A NS SOA RRSIG NSEC DNSKEY TYPE1234"),
+        ("DNSKEY", "257 3 13 ZGVmZ2hpamtsbW5vcHFyc3R1dnd4eXp7fH1+f4CBgoOEhYaHiImKi4yNjo+QkZKTlJWWl5iZmpucnZ6foKGiow=="),
+        (
+            "NSEC3",
+            "1 0 10 AABBCCDD 2t7b4g4vsa5smi47k61mv5bv1a22bojr A RRSIG",
+        ),
+        ("NSEC3", "1 0 0 - 2t7b4g4vsa5smi47k61mv5bv1a22bojr"),
+        ("NSEC3PARAM", "1 0 10 AABBCCDD"),
+        ("TYPE65534", "\\# 3 ABCDEF"),
+    ];
+
+    for (qtype, rdata) in records {
+        let qtype = qtype.parse::<QueryType>().unwrap();
+        let data = RecordData::from_presentation(qtype, rdata).unwrap();
+        assert_eq!(data.to_string(), rdata);
+
+        let mut message = DnsMessage::new();
+        message.answers.push(DnsRecord::with_data(
+            "example.com".parse().unwrap(),
+            300,
+            data,
+        ));
+        let mut buffer = message.into_buf().unwrap();
+        buffer.seek(0).unwrap();
+
+        let parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+        assert_eq!(parsed.answers[0].qtype(), qtype);
+        assert_eq!(parsed.answers[0].data.to_string(), rdata);
+    }
+
+    // a full string of them still fits its length octet
+    let data = RecordData::TXT {
+        data: vec![vec![0xFF; 255]],
+    };
+    let mut message = DnsMessage::new();
+    message.answers.push(DnsRecord::with_data(
+        "example.com".parse().unwrap(),
+        300,
+        data.clone(),
+    ));
+    let mut buffer = message.into_buf().unwrap();
+    buffer.seek(0).unwrap();
+    let mut parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(parsed.answers[0].data, data);
+    assert!(parsed.into_buf().is_ok());
+
+    // the generic notation of known types, which must span the
+    // whole data
+    let data = RecordData::from_presentation(QueryType::A, "\\# 4 C0000201").unwrap();
+    assert_eq!(data.to_string(), "192.0.2.1");
+    assert!(RecordData::from_presentation(QueryType::A, "\\# 5 C000020100").is_err());
+    let strings = format!("C7{}", "41".repeat(199)).repeat(3);
+    let data =
+        RecordData::from_presentation(QueryType::TXT, &format!("\\# 600 {}", strings)).unwrap();
+    assert!(matches!(data, RecordData::TXT { ref data } if data.len() == 3));
+}
+
+#[test]
+fn dns_class_test() {
+    let record = "version.bind.\t0\tCH\tTXT\t\"cdn-dns\""
+        .parse::<DnsRecord>()
+        .unwrap();
+    assert_eq!(record.class, DnsClass::CH);
+    assert_eq!(record.to_string(), "version.bind.\t0\tCH\tTXT\t\"cdn-dns\"");
+
+    let mut message = DnsMessage::new();
+    message
+        .questions
+        .push("version.bind. HS TXT".parse().unwrap());
+    message.answers.push(record);
+    let mut buffer = message.into_buf().unwrap();
+    buffer.seek(0).unwrap();
+
+    let parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(parsed.questions[0].qclass, DnsClass::HS);
+    assert_eq!(parsed.answers[0].class, DnsClass::CH);
+
+    assert_eq!(
+        "CLASS42".parse::<DnsClass>().unwrap(),
+        DnsClass::UNKNOWN(42)
+    );
+    assert_eq!(DnsClass::from(255).to_string(), "ANY");
+}
+
+#[test]
+fn dns_message_presentation_test() {
+    let text = "\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 6666
+;; flags: qr rd ra; QUERY: 1, ANSWER: 2, AUTHORITY: 0, ADDITIONAL: 0
+
+;; QUESTION SECTION:
+;www.example.com.\t\tIN\tA
+
+;; ANSWER SECTION:
+www.example.com.\t300\tIN\tCNAME\texample.com.
+example.com.\t300\tIN\tA\t1.2.3.4";
+
+    let message = text.parse::<DnsMessage>().unwrap();
+    assert_eq!(message.header.id, 6666);
+    assert!(message.header.response && message.header.recursion_available);
+    assert_eq!(message.questions[0].qname, "www.example.com");
+    assert_eq!(
+        message.answers[1].to_string(),
+        "example.com.\t300\tIN\tA\t1.2.3.4"
+    );
+    assert_eq!(message.to_string(), text);
+
+    let record = "cdn.esi.dz. IN 60 MX 10 mail.esi.dz."
+        .parse::<DnsRecord>()
+        .unwrap();
+    assert_eq!(record.ttl(), 60);
+    assert_eq!(
+        record.to_string(),
+        "cdn.esi.dz.\t60\tIN\tMX\t10 mail.esi.dz."
+    );
+}
+
+#[test]
+fn dns_message_json_test() {
+    let text = "\
+;; ->>HEADER<<- opcode: QUERY, status: NXDOMAIN, id: 42
+;; flags: qr aa rd; QUERY: 1, ANSWER: 3, AUTHORITY: 0, ADDITIONAL: 1
+
+;; QUESTION SECTION:
+;esi.dz.\t\tIN\tCAA
+
+;; ANSWER SECTION:
+esi.dz.\t300\tIN\tCAA\t0 issue \"letsencrypt.org\"
+esi.dz.\t300\tIN\tMX\t10 mail.esi.dz.
+esi.dz.\t300\tCH\tTYPE65534\t\\# 2 BEEF
+
+;; ADDITIONAL SECTION:
+mail.esi.dz.\t60\tIN\tAAAA\t2001:db8::1";
+    let message = text.parse::<DnsMessage>().unwrap();
+
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["RCODE"], 3);
+    assert_eq!(json["answerRRs"][1]["rdataMX"], "10 mail.esi.dz.");
+    assert_eq!(json["answerRRs"][2]["RDATAHEX"], "BEEF");
+    assert_eq!(json["additionalRRs"][0]["NAME"], "mail.esi.dz.");
+
+    let parsed = serde_json::from_value::<DnsMessage>(json).unwrap();
+    assert_eq!(parsed.to_string(), text);
+}
+
+#[test]
+fn dns_message_json_strings_test() {
+    let hostname = "esi.dz".parse::<DnsName>().unwrap();
+    let request = DnsMessage::query(&hostname, QueryType::TXT)
+        .edns(Edns::new())
+        .build();
+    let mut message = DnsMessage::response_to(&request)
+        .rescode(ResultCode::BADVERS)
+        .build();
+    let records = [
+        ("TXT", "\"v=spf1 -all\" \"caf\\233\""),
+        (
+            "NAPTR",
+            "100 10 \"U\" \"E2U+sip\" \"!^.*$!sip:info@example.com!\" .",
+        ),
+        ("CAA", "0 issue \"\\200\\255ca\""),
+    ];
+    for (qtype, rdata) in records {
+        let data = RecordData::from_presentation(qtype.parse().unwrap(), rdata).unwrap();
+        message
+            .answers
+            .push(DnsRecord::with_data(hostname.clone(), 300, data));
+    }
+
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["RCODE"], 16);
+    assert_eq!(json["answerRRs"][0]["rdataTXT"], records[0].1);
+    assert_eq!(
+        json["answerRRs"][0]["RDATAHEX"],
+        "0B763D73706631202D616C6C04636166E9"
+    );
+    assert!(json["answerRRs"][1].get("RDATAHEX").is_none());
+    assert_eq!(json["answerRRs"][2]["rdataCAA"], records[2].1);
+
+    let mut parsed = serde_json::from_value::<DnsMessage>(json).unwrap();
+    assert_eq!(parsed.header.rescode, ResultCode::BADVERS);
+    assert_eq!(parsed.answers, message.answers);
+    assert_eq!(parsed.to_string(), message.to_string());
+
+    let mut buffer = parsed.into_buf().unwrap();
+    buffer.seek(0).unwrap();
+    let parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(parsed.header.rescode, ResultCode::BADVERS);
+    assert_eq!(parsed.answers, message.answers);
+}
+
+#[test]
+fn dns_message_builder_test() {
+    let hostname = "cdn.esi.dz".parse::<DnsName>().unwrap();
+    let mut query = DnsMessage::query(&hostname, QueryType::AAAA)
+        .id(1234)
+        .recursion_desired(true)
+        .edns(Edns::new())
+        .build();
+    assert_eq!(query.header.questions, 1);
+    assert_eq!(query.header.resource_entries, 1);
+
+    let mut buffer = query.into_buf().unwrap();
+    buffer.seek(0).unwrap();
+    let request = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(request.edns(), Some(Edns::new()));
+
+    let response = DnsMessage::response_to(&request)
+        .authoritative(true)
+        .answer(DnsRecord::new_a("1.1.1.1", &hostname))
+        .build();
+    assert_eq!(response.header.id, 1234);
+    assert!(response.header.response && response.header.recursion_desired);
+    assert_eq!(response.questions[0].qname, "cdn.esi.dz");
+    assert_eq!(response.questions[0].qtype, QueryType::AAAA);
+    assert_eq!(response.header.answers, 1);
+    assert!(response.edns().is_some());
+}
+
+#[test]
+fn extended_rcode_test() {
+    let hostname = "cdn.esi.dz".parse::<DnsName>().unwrap();
+    let request = DnsMessage::query(&hostname, QueryType::A)
+        .edns(Edns::new())
+        .build();
+    let mut response = DnsMessage::response_to(&request)
+        .rescode(ResultCode::BADVERS)
+        .build();
+
+    let mut buffer = response.into_buf().unwrap();
+    // 16 is 1 in the OPT record and 0 in the header
+    assert_eq!(buffer.buf[3] & 0x0F, 0);
+    assert_eq!(response.edns().unwrap().extended_rcode, 1);
+
+    buffer.seek(0).unwrap();
+    let parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(parsed.header.rescode, ResultCode::BADVERS);
+
+    // unassigned codes are preserved
+    buffer.buf[3] = (buffer.buf[3] & 0xF0) | 12;
+    buffer.seek(0).unwrap();
+    let parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(parsed.header.rescode, ResultCode::UNKNOWN(28));
+    assert_eq!(parsed.header.rescode.to_string(), "RCODE28");
+
+    for rescode in ["YXDOMAIN", "NOTAUTH", "NOTZONE", "BADCOOKIE", "RCODE4000"] {
+        assert_eq!(rescode.parse::<ResultCode>().unwrap().to_string(), rescode);
+    }
+    assert_eq!(u16::from(ResultCode::NOTZONE), 10);
+
+    // without an OPT record the code can not be sent
+    let request = DnsMessage::query(&hostname, QueryType::A).build();
+    let mut response = DnsMessage::response_to(&request)
+        .rescode(ResultCode::BADCOOKIE)
+        .build();
+    let mut buffer = response.into_buf().unwrap();
+    assert_eq!(buffer.buf[3] & 0x0F, 2);
+    buffer.seek(0).unwrap();
+    let parsed = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(parsed.header.rescode, ResultCode::SERVFAIL);
+}
+
+#[test]
+fn dns_message_ref_test() {
+    let text = "\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 7
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 1, ADDITIONAL: 1
+
+;; QUESTION SECTION:
+;cdn.esi.dz.\t\tIN\tA
+
+;; ANSWER SECTION:
+cdn.esi.dz.\t100\tIN\tA\t172.16.1.2
+
+;; AUTHORITY SECTION:
+esi.dz.\t3600\tIN\tNS\tns1.esi.dz.
+
+;; ADDITIONAL SECTION:
+ns1.esi.dz.\t3600\tIN\tA\t10.0.0.1";
+    let mut message = text.parse::<DnsMessage>().unwrap();
+    let buffer = message.into_buf().unwrap();
+
+    let view = DnsMessageRef::new(&buffer.buf[..buffer.pos()]).unwrap();
+    assert_eq!(view.header().id, 7);
+    let question = view.first_question().unwrap().unwrap();
+    assert!(question.qname.eq_name(&"CDN.esi.dz".parse().unwrap()));
+    assert!(!question.qname.eq_name(&"esi.dz".parse().unwrap()));
+    assert_eq!(question.qtype, QueryType::A);
+
+    let authority = view.authorities().next().unwrap().unwrap();
+    assert_eq!(authority.ttl, 3600);
+    assert_eq!(
+        authority.to_owned().unwrap().to_string(),
+        "esi.dz.\t3600\tIN\tNS\tns1.esi.dz."
+    );
+    let glue = view.resources().next().unwrap().unwrap();
+    assert_eq!(glue.domain.to_name().unwrap(), "ns1.esi.dz");
+    assert_eq!(glue.rdata, [10, 0, 0, 1]);
+    assert_eq!(view.to_owned().unwrap().to_string(), text);
+
+    let truncated = DnsMessageRef::new(&buffer.buf[..buffer.pos() - 2]).unwrap();
+    assert!(truncated.answers().next().unwrap().is_ok());
+    assert!(truncated.resources().next().unwrap().is_err());
+
+    // counts summing past a u16 are an error, not an overflow
+    let mut bytes = buffer.buf[..buffer.pos()].to_vec();
+    bytes[6..8].copy_from_slice(&[0xFF, 0xFF]);
+    let view = DnsMessageRef::new(&bytes).unwrap();
+    assert!(view.resources().next().unwrap().is_err());
+}
+
+#[test]
+fn empty_rdata_test() {
+    // one answer: an `A` record of the root without data
+    let packet = |opcode: u8, class: u8| {
+        let mut packet = vec![0, 1, opcode << 3, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        packet.extend([0, 0, 1, 0, class, 0, 0, 0, 0, 0, 0]);
+        packet
+    };
+    let parse = |packet: Vec<u8>, strict: bool| {
+        let mut buffer = PacketBuffer::from_bytes(&packet);
+        buffer.set_strict(strict);
+        DnsMessage::from_buf(&mut buffer)
+    };
+
+    assert!(parse(packet(0, 1), true).is_err());
+    assert!(parse(packet(0, 255), true).is_err());
+    assert!(parse(packet(5, 1), true).is_err());
+    assert!(parse(packet(0, 1), false).is_ok());
+
+    // a prerequisite or update standing for the whole RRset
+    for class in [254, 255] {
+        let message = parse(packet(5, class), true).unwrap();
+        assert!(
+            matches!(message.answers[0].data, RecordData::UNKNOWN { ref data, .. } if data.is_empty())
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+
+use cdn_dns::dns_message::dns_name::DnsName;
+
+fn name(text: &str) -> DnsName {
+    text.parse().unwrap()
+}
+
+#[test]
+fn dns_name_parse_test() {
+    assert_eq!(name("www.esi.dz").to_string(), "www.esi.dz.");
+    assert_eq!(name("www.esi.dz."), name("WWW.Esi.DZ"));
+    assert!(name(".").is_root());
+    assert_eq!(name("a\\.b.esi.dz").label_count(), 3);
+    assert_eq!(
+        name("a\\.b\\032c.esi.dz").to_string(),
+        "a\\.b\\032c.esi.dz."
+    );
+
+    assert!("www..esi.dz".parse::<DnsName>().is_err());
+    assert!(".esi.dz".parse::<DnsName>().is_err());
+    assert!(format!("{}.dz", "a".repeat(64)).parse::<DnsName>().is_err());
+    let long = vec!["a".repeat(63); 4].join(".");
+    assert!(long.parse::<DnsName>().is_err());
+
+    let origin = name("esi.dz");
+    assert_eq!(
+        DnsName::parse_relative("www", &origin).unwrap(),
+        name("www.esi.dz")
+    );
+    assert_eq!(DnsName::parse_relative("@", &origin).unwrap(), origin);
+    assert_eq!(
+        DnsName::parse_relative("www.", &origin).unwrap(),
+        name("www")
+    );
+}
+
+#[test]
+fn dns_name_hierarchy_test() {
+    let www = name("www.Esi.dz");
+    let zone = name("esi.DZ");
+    assert!(www.is_subdomain_of(&zone));
+    assert!(zone.is_subdomain_of(&zone));
+    assert!(!zone.is_subdomain_of(&www));
+    assert!(!name("fesi.dz").is_subdomain_of(&zone));
+    assert_eq!(www.parent(), Some(zone.clone()));
+    assert_eq!(DnsName::root().parent(), None);
+    assert_eq!(www.common_ancestor(&name("mail.esi.dz")), zone);
+
+    let ancestors: Vec<String> = www.ancestors().map(|name| name.to_string()).collect();
+    assert_eq!(ancestors, ["www.Esi.dz.", "Esi.dz.", "dz.", "."]);
+
+    let set: HashSet<DnsName> = [name("ESI.dz"), name("esi.dz")].into_iter().collect();
+    assert_eq!(set.len(), 1);
+}
+
+#[test]
+fn dns_name_canonical_order_test() {
+    // RFC 4034 section 6.1
+    let expected = [
+        "example",
+        "a.example",
+        "yljkjljk.a.example",
+        "Z.a.example",
+        "zABC.a.EXAMPLE",
+        "z.example",
+        "\\001.z.example",
+        "*.z.example",
+        "\\200.z.example",
+    ];
+    let mut names: Vec<DnsName> = expected.iter().rev().map(|text| name(text)).collect();
+    names.sort();
+    let sorted: Vec<DnsName> = expected.iter().map(|text| name(text)).collect();
+    assert_eq!(names, sorted);
+}
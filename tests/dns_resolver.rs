@@ -1,4 +1,11 @@
-use cdn_dns::dns_resolver::handle_query::handle_query;
+use std::net::Ipv4Addr;
+
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_resolver::lookup::lookup;
+use cdn_dns::dns_server::{udp, DnsServer};
+use cdn_dns::settings::config::get_config;
 use tokio::net::UdpSocket;
 
 #[tokio::test]
@@ -7,5 +14,52 @@ async fn dns_resolver_test() {
         .await
         .expect("Failed to bind UdpSocket");
 
-    handle_query(&socket).await.unwrap();
+    let mut config = get_config().expect("Failed to read configuration");
+    config.application.is_load_balancer = false;
+    let server = DnsServer::new(config).expect("Failed to load the zones");
+
+    udp::handle_packet(&socket, &server).await.unwrap();
+}
+
+#[tokio::test]
+async fn lookup_test() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let server = (Ipv4Addr::LOCALHOST, addr.port());
+    let qname: DnsName = "www.example.com".parse().unwrap();
+
+    // answers the first query with garbage, the second one with
+    // another id, the third one with another question, the fourth
+    // one rightly and the last one not at all
+    tokio::spawn(async move {
+        for reply in 0.. {
+            let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+            let (len, src) = socket.recv_from(&mut buffer.buf).await.unwrap();
+            buffer.truncate(len);
+            let request = DnsMessage::from_buf(&mut buffer).unwrap();
+            let mut response = DnsMessage::response_to(&request).build();
+            match reply {
+                0 => {
+                    socket.send_to(&[0; 7], src).await.unwrap();
+                    continue;
+                }
+                1 => response.header.id = request.header.id.wrapping_add(1),
+                2 => response.questions[0].qtype = QueryType::AAAA,
+                3 => {}
+                _ => continue,
+            }
+            let buffer = response.into_buf().unwrap();
+            socket
+                .send_to(&buffer.buf[..buffer.pos()], src)
+                .await
+                .unwrap();
+        }
+    });
+
+    for _ in 0..3 {
+        assert!(lookup(&qname, QueryType::A, server).await.is_err());
+    }
+    let response = lookup(&qname, QueryType::A, server).await.unwrap();
+    assert_eq!(response.questions[0].qname, qname);
+    assert!(lookup(&qname, QueryType::A, server).await.is_err());
 }
@@ -0,0 +1,117 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use cdn_dns::dns_message::dns_header::{Opcode, ResultCode};
+use cdn_dns::dns_message::dns_record::RecordData;
+use cdn_dns::dns_message::{DnsClass, DnsMessage, QueryType};
+use cdn_dns::dns_server::DnsServer;
+use cdn_dns::settings::config::ChaosSettings;
+
+fn server(chaos: ChaosSettings) -> DnsServer {
+    let mut config = common::config(Vec::new());
+    config.chaos = chaos;
+    DnsServer::new(config).expect("Failed to load the zones")
+}
+
+fn query(qname: &str, qclass: DnsClass) -> DnsMessage {
+    let mut request = DnsMessage::query(&qname.parse().unwrap(), QueryType::TXT)
+        .id(42)
+        .build();
+    request.questions[0].qclass = qclass;
+    request
+}
+
+fn src() -> SocketAddr {
+    "127.0.0.1:5353".parse().unwrap()
+}
+
+#[tokio::test]
+async fn chaos_test() {
+    let server = server(ChaosSettings {
+        enabled: true,
+        version: Some("cdn-dns test".into()),
+        ..ChaosSettings::default()
+    });
+
+    let response = server
+        .handle_request(&query("VERSION.bind", DnsClass::CH), src())
+        .await
+        .unwrap();
+    assert_eq!(response.header.id, 42);
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert!(response.header.authoritative_answer);
+    assert_eq!(response.answers[0].class, DnsClass::CH);
+    match response.answers[0].data {
+        RecordData::TXT { ref data } => assert_eq!(data, &[b"cdn-dns test"]),
+        ref data => panic!("Unexpected record data {}", data),
+    }
+
+    // hostname.bind is not configured
+    let response = server
+        .handle_request(&query("hostname.bind", DnsClass::CH), src())
+        .await
+        .unwrap();
+    assert_eq!(response.header.rescode, ResultCode::REFUSED);
+    assert!(response.answers.is_empty());
+
+    let response = server
+        .handle_request(&query("example.bind", DnsClass::CH), src())
+        .await
+        .unwrap();
+    assert_eq!(response.header.rescode, ResultCode::REFUSED);
+}
+
+#[tokio::test]
+async fn chaos_disabled_test() {
+    // disabled by default
+    let server = server(ChaosSettings {
+        version: Some("cdn-dns test".into()),
+        ..ChaosSettings::default()
+    });
+
+    let response = server
+        .handle_request(&query("version.bind", DnsClass::CH), src())
+        .await
+        .unwrap();
+    assert_eq!(response.header.rescode, ResultCode::REFUSED);
+    assert!(response.answers.is_empty());
+}
+
+#[tokio::test]
+async fn unsupported_class_test() {
+    let server = server(ChaosSettings::default());
+
+    for qclass in [DnsClass::HS, DnsClass::NONE, DnsClass::UNKNOWN(42)] {
+        let response = server
+            .handle_request(&query("example.com", qclass), src())
+            .await
+            .unwrap();
+        assert_eq!(response.header.rescode, ResultCode::NOTIMP);
+        assert_eq!(response.questions[0].qclass, qclass);
+    }
+}
+
+#[tokio::test]
+async fn opcode_test() {
+    let server = server(ChaosSettings::default());
+
+    for opcode in [
+        Opcode::IQUERY,
+        Opcode::STATUS,
+        Opcode::DSO,
+        Opcode::UNKNOWN(9),
+    ] {
+        let mut request = query("version.bind", DnsClass::CH);
+        request.header.opcode = opcode;
+        let response = server.handle_request(&request, src()).await.unwrap();
+        assert_eq!(response.header.rescode, ResultCode::NOTIMP);
+        assert_eq!(response.header.opcode, opcode);
+        assert!(response.header.response);
+    }
+
+    // responses are dropped, whatever their opcode
+    let mut request = query("version.bind", DnsClass::CH);
+    request.header.response = true;
+    assert!(server.handle_request(&request, src()).await.is_none());
+}
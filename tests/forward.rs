@@ -0,0 +1,312 @@
+mod common;
+
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_resolver::forward::Forwarder;
+use cdn_dns::dns_server::{https, tcp, tls, udp, DnsServer};
+use cdn_dns::settings::acl::Acl;
+use cdn_dns::settings::config::{
+    DohSettings, DotSettings, Fallback, ForwardSettings, Transport, UpstreamSettings, ZoneSettings,
+};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::time::{sleep, timeout};
+
+/// The upstream listening on every transport, serving
+/// example.com and a zone whose RRset does not fit a datagram
+struct Listening {
+    udp: SocketAddr,
+    tcp: SocketAddr,
+    dot: SocketAddr,
+    doh: SocketAddr,
+    ca: String,
+    _certificate: common::Certificate,
+}
+
+async fn listen() -> Listening {
+    let mut zone = String::from(
+        "$TTL 1h\n@ IN SOA ns1 hostmaster 1 2h 15m 1w 300\n@ IN NS ns1\nns1 IN A 192.0.2.1\n",
+    );
+    for i in 0..120 {
+        writeln!(zone, "many IN A 198.51.100.{}", i).unwrap();
+    }
+    let zone = common::TempFile::new("forward-example.org.zone", zone);
+    let server = Arc::new(common::server(vec![
+        common::zone("example.com", "example.com.zone"),
+        ZoneSettings {
+            origin: "example.org".parse().unwrap(),
+            file: zone.path(),
+            ..Default::default()
+        },
+    ]));
+
+    let certificate = common::certificate("forward");
+    let settings = certificate.settings.clone();
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let udp = socket.local_addr().unwrap();
+    let udp_server = server.clone();
+    tokio::spawn(udp::serve(socket, udp_server));
+
+    // on the port of UDP, where truncated answers are retried
+    let listener = TcpListener::bind(udp).await.unwrap();
+    let tcp = listener.local_addr().unwrap();
+    tokio::spawn(tcp::serve(listener, server.clone()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dot = listener.local_addr().unwrap();
+    let dot_settings = DotSettings {
+        port: 0,
+        tls: settings.clone(),
+        idle_timeout: 10,
+        max_connections: 16,
+    };
+    tokio::spawn(tls::serve(listener, dot_settings, server.clone()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let doh = listener.local_addr().unwrap();
+    let doh_settings = DohSettings {
+        port: 0,
+        tls: Some(settings),
+        idle_timeout: 10,
+        max_connections: 16,
+        trusted_proxies: Acl::default(),
+    };
+    tokio::spawn(https::serve(listener, doh_settings, server));
+
+    Listening {
+        udp,
+        tcp,
+        dot,
+        doh,
+        ca: certificate.ca(),
+        _certificate: certificate,
+    }
+}
+
+fn upstream(address: String, transport: Transport, ca: &str) -> UpstreamSettings {
+    UpstreamSettings {
+        address,
+        transport,
+        tls_name: Some("localhost".into()),
+        ca: Some(ca.into()),
+    }
+}
+
+fn forward_to(upstreams: Vec<UpstreamSettings>, fallback: Fallback) -> Forwarder {
+    Forwarder::new(&ForwardSettings {
+        upstreams,
+        fallback,
+    })
+    .unwrap()
+}
+
+fn query(qname: &str) -> DnsMessage {
+    DnsMessage::query(&qname.parse().unwrap(), QueryType::A)
+        .id(42)
+        .recursion_desired(true)
+        .build()
+}
+
+#[tokio::test]
+async fn forward_test() {
+    let listening = listen().await;
+    let ca = &listening.ca;
+
+    for upstream in [
+        upstream(listening.udp.to_string(), Transport::Udp, ca),
+        upstream(listening.tcp.to_string(), Transport::Tcp, ca),
+        upstream(listening.dot.to_string(), Transport::Tls, ca),
+        upstream(
+            format!("https://localhost:{}/dns-query", listening.doh.port()),
+            Transport::Https,
+            ca,
+        ),
+    ] {
+        let transport = upstream.transport;
+        let forwarder = forward_to(vec![upstream], Fallback::None);
+        for _ in 0..2 {
+            let response = forwarder.handle_query(&query("web.example.com")).await;
+            assert_eq!(response.header.id, 42);
+            assert_eq!(
+                response.header.rescode,
+                ResultCode::NOERROR,
+                "{:?}",
+                transport
+            );
+            assert!(response.header.recursion_available);
+            assert_eq!(response.answers[0].data.to_string(), "192.0.2.80");
+        }
+
+        // the stream connections are reused
+        let idle = match transport {
+            Transport::Tcp | Transport::Tls => 1,
+            _ => 0,
+        };
+        assert_eq!(forwarder.upstreams()[0].idle_connections(), idle);
+    }
+
+    // the truncated answer is retried over TCP
+    let forwarder = forward_to(
+        vec![upstream(listening.udp.to_string(), Transport::Udp, ca)],
+        Fallback::None,
+    );
+    let response = forwarder.handle_query(&query("many.example.org")).await;
+    assert!(!response.header.truncated_message);
+    assert_eq!(response.answers.len(), 120);
+    assert_eq!(forwarder.upstreams()[0].idle_connections(), 1);
+}
+
+#[tokio::test]
+async fn forward_fallback_test() {
+    let listening = listen().await;
+    let ca = &listening.ca;
+
+    // the certificate does not hold the name
+    let mut wrong_name = upstream(listening.dot.to_string(), Transport::Tls, ca);
+    wrong_name.tls_name = Some("dns.example.net".into());
+    let clear = upstream(listening.udp.to_string(), Transport::Udp, ca);
+
+    let forwarder = forward_to(vec![wrong_name.clone(), clear.clone()], Fallback::Any);
+    let response = forwarder.handle_query(&query("web.example.com")).await;
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+
+    // the query never leaves in clear once TLS failed
+    let forwarder = forward_to(vec![wrong_name.clone(), clear.clone()], Fallback::Encrypted);
+    let response = forwarder.handle_query(&query("web.example.com")).await;
+    assert_eq!(response.header.rescode, ResultCode::SERVFAIL);
+
+    // but another encrypted upstream is tried
+    let dot = upstream(listening.dot.to_string(), Transport::Tls, ca);
+    let forwarder = forward_to(vec![wrong_name.clone(), clear, dot], Fallback::Encrypted);
+    let response = forwarder.handle_query(&query("web.example.com")).await;
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+
+    let forwarder = forward_to(
+        vec![
+            wrong_name,
+            upstream(listening.tcp.to_string(), Transport::Tcp, ca),
+        ],
+        Fallback::None,
+    );
+    let response = forwarder.handle_query(&query("web.example.com")).await;
+    assert_eq!(response.header.rescode, ResultCode::SERVFAIL);
+
+    // an answer to another question is not taken
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let spoofing = upstream(socket.local_addr().unwrap().to_string(), Transport::Udp, ca);
+    tokio::spawn(async move {
+        loop {
+            let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+            let (len, src) = socket.recv_from(&mut buffer.buf).await.unwrap();
+            buffer.truncate(len);
+            let request = DnsMessage::from_buf(&mut buffer).unwrap();
+            let mut response = DnsMessage::response_to(&request).build();
+            response.questions[0].qname = "www.example.net".parse().unwrap();
+            let buffer = response.into_buf().unwrap();
+            socket
+                .send_to(&buffer.buf[..buffer.pos()], src)
+                .await
+                .unwrap();
+        }
+    });
+    let forwarder = forward_to(vec![spoofing], Fallback::None);
+    let response = forwarder.handle_query(&query("web.example.com")).await;
+    assert_eq!(response.header.rescode, ResultCode::SERVFAIL);
+
+    // but the case of the name may differ
+    let mut request = query("WEB.Example.com");
+    request.header.id = 43;
+    let forwarder = forward_to(
+        vec![upstream(listening.udp.to_string(), Transport::Udp, ca)],
+        Fallback::None,
+    );
+    let response = forwarder.handle_query(&request).await;
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+
+    assert!(Forwarder::new(&ForwardSettings {
+        upstreams: vec![upstream("dns.example.net".into(), Transport::Tcp, ca)],
+        fallback: Fallback::Any,
+    })
+    .is_err());
+}
+
+#[tokio::test]
+async fn forward_stale_connection_test() {
+    let listening = listen().await;
+
+    // relays the first query of each connection to the upstream,
+    // then keeps the connection open without answering
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let relay = upstream(
+        listener.local_addr().unwrap().to_string(),
+        Transport::Tcp,
+        &listening.ca,
+    );
+    let target = upstream(listening.tcp.to_string(), Transport::Tcp, &listening.ca);
+    tokio::spawn(async move {
+        let forwarder = Arc::new(forward_to(vec![target], Fallback::None));
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let forwarder = forwarder.clone();
+            tokio::spawn(async move {
+                let request = tcp::read_message(&mut stream).await.unwrap();
+                let response = forwarder.handle_query(&request).await;
+                tcp::write_message(&mut stream, response).await.unwrap();
+                let _ = tcp::read_message(&mut stream).await;
+                sleep(Duration::from_secs(60)).await;
+            });
+        }
+    });
+
+    // the pooled connection does not answer, a new one does
+    let forwarder = forward_to(vec![relay], Fallback::None);
+    for _ in 0..2 {
+        let response = forwarder.handle_query(&query("web.example.com")).await;
+        assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    }
+}
+
+#[tokio::test]
+async fn udp_concurrency_test() {
+    // an upstream that never answers
+    let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut config = common::config(vec![common::zone("example.com", "example.com.zone")]);
+    config.application.is_load_balancer = false;
+    config.forward = Some(ForwardSettings {
+        upstreams: vec![upstream(
+            silent.local_addr().unwrap().to_string(),
+            Transport::Udp,
+            "",
+        )],
+        fallback: Fallback::None,
+    });
+    let server = Arc::new(DnsServer::new(config).unwrap());
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(udp::serve(socket, server));
+
+    // a forwarded query waiting for the upstream does not hold
+    // an authoritative one up
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.connect(addr).await.unwrap();
+    for qname in ["www.example.net", "web.example.com"] {
+        let buffer = query(qname).into_buf().unwrap();
+        client.send(&buffer.buf[..buffer.pos()]).await.unwrap();
+    }
+    let mut buffer = PacketBuffer::new();
+    let len = timeout(Duration::from_secs(1), client.recv(&mut buffer.buf))
+        .await
+        .expect("The forwarded query held the others up")
+        .unwrap();
+    buffer.truncate(len);
+    let response = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(response.questions[0].qname, "web.example.com");
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    drop(silent);
+}
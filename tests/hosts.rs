@@ -0,0 +1,162 @@
+mod common;
+
+use std::sync::Arc;
+
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_record::RecordData;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_resolver::hosts::{reverse_name, Hosts};
+use cdn_dns::dns_server::{udp, DnsServer};
+use cdn_dns::settings::config::{
+    Fallback, ForwardSettings, HostsSettings, StaticRecord, StaticRecordType, Transport,
+    UpstreamSettings,
+};
+use tokio::net::UdpSocket;
+
+fn record(name: &str, qtype: StaticRecordType, data: &str) -> StaticRecord {
+    StaticRecord {
+        name: name.parse().unwrap(),
+        qtype,
+        data: data.into(),
+    }
+}
+
+fn settings() -> HostsSettings {
+    HostsSettings {
+        files: vec!["tests/zones/lab.hosts".into()],
+        records: vec![
+            record("cdn.esi.dz", StaticRecordType::A, "10.0.0.80"),
+            record("www.esi.dz", StaticRecordType::CNAME, "cdn.esi.dz."),
+            record("shop.esi.dz", StaticRecordType::CNAME, "web.example.com."),
+            record(
+                "11.2.0.192.in-addr.arpa",
+                StaticRecordType::PTR,
+                "build.esi.dz.",
+            ),
+        ],
+        ttl: 60,
+    }
+}
+
+fn query(qname: &str, qtype: QueryType) -> DnsMessage {
+    DnsMessage::query(&qname.parse().unwrap(), qtype)
+        .id(7)
+        .build()
+}
+
+fn answers(hosts: &Hosts, qname: &str, qtype: QueryType) -> Vec<String> {
+    let response = hosts.handle_query(&query(qname, qtype)).unwrap();
+    assert!(response.header.authoritative_answer);
+    response
+        .answers
+        .iter()
+        .map(|rec| rec.data.to_string())
+        .collect()
+}
+
+#[test]
+fn hosts_test() {
+    let hosts = Hosts::new(&settings()).unwrap();
+
+    assert_eq!(answers(&hosts, "lab1.esi.dz", QueryType::A), ["192.0.2.10"]);
+    assert_eq!(
+        answers(&hosts, "LAB1.esi.dz", QueryType::AAAA),
+        ["2001:db8::10"]
+    );
+    assert_eq!(
+        answers(&hosts, "printer.esi.dz", QueryType::A),
+        ["192.0.2.10"]
+    );
+    assert!(answers(&hosts, "lab2.esi.dz", QueryType::AAAA).is_empty());
+    assert_eq!(
+        answers(&hosts, "www.esi.dz", QueryType::A),
+        ["cdn.esi.dz.", "10.0.0.80"]
+    );
+    assert_eq!(
+        answers(&hosts, "www.esi.dz", QueryType::CNAME),
+        ["cdn.esi.dz."]
+    );
+    assert!(hosts
+        .handle_query(&query("lab3.esi.dz", QueryType::A))
+        .is_none());
+
+    // the canonical name of a host, unless the reverse name has a
+    // record of its own
+    let ptr = |addr: &str| reverse_name(addr.parse().unwrap()).to_string();
+    assert_eq!(ptr("192.0.2.10"), "10.2.0.192.in-addr.arpa.");
+    assert_eq!(
+        answers(&hosts, &ptr("192.0.2.10"), QueryType::PTR),
+        ["lab1.esi.dz."]
+    );
+    assert_eq!(
+        answers(&hosts, &ptr("192.0.2.11"), QueryType::PTR),
+        ["build.esi.dz."]
+    );
+    assert_eq!(
+        answers(&hosts, &ptr("10.0.0.80"), QueryType::PTR),
+        ["cdn.esi.dz."]
+    );
+    assert_eq!(
+        ptr("2001:db8::10"),
+        "0.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+    );
+    assert_eq!(
+        answers(&hosts, &ptr("2001:db8::10"), QueryType::PTR),
+        ["lab1.esi.dz."]
+    );
+
+    let mut invalid = settings();
+    invalid.records.push(record(
+        "cdn.esi.dz",
+        StaticRecordType::CNAME,
+        "lab1.esi.dz.",
+    ));
+    assert!(Hosts::new(&invalid).is_err());
+    let mut invalid = settings();
+    invalid.files.push("tests/zones/example.com.zone".into());
+    assert!(Hosts::new(&invalid).is_err());
+}
+
+#[tokio::test]
+async fn hosts_server_test() {
+    let upstream = Arc::new(common::server(vec![common::zone(
+        "example.com",
+        "example.com.zone",
+    )]));
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(udp::serve(socket, upstream));
+
+    let mut config = common::config(Vec::new());
+    config.application.is_load_balancer = false;
+    config.hosts = settings();
+    config.forward = Some(ForwardSettings {
+        upstreams: vec![UpstreamSettings {
+            address: addr.to_string(),
+            transport: Transport::Udp,
+            tls_name: None,
+            ca: None,
+        }],
+        fallback: Fallback::Any,
+    });
+    let server = DnsServer::new(config).unwrap();
+    let src = "127.0.0.1:5353".parse().unwrap();
+
+    let request = query("cdn.esi.dz", QueryType::A);
+    let response = server.handle_request(&request, src).await.unwrap();
+    assert!(response.header.authoritative_answer);
+    assert_eq!(response.answers[0].ttl(), 60);
+
+    // the target of a CNAME out of the table is resolved
+    let request = query("shop.esi.dz", QueryType::A);
+    let response = server.handle_request(&request, src).await.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert!(!response.header.authoritative_answer);
+    assert_eq!(response.answers.len(), 2);
+    assert_eq!(
+        response.answers[1].data,
+        RecordData::A {
+            addr: "192.0.2.80".parse().unwrap()
+        }
+    );
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::https::{self, client_addr, handle_http, DNS_MESSAGE};
+use cdn_dns::dns_server::DnsServer;
+use cdn_dns::settings::acl::{Access, AccessRule, AccessRules, Acl};
+use cdn_dns::settings::config::DohSettings;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_TYPE, FORWARDED};
+use hyper::{Method, Request, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::client::ClientConfig;
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::ServerName;
+use rustls::RootCertStore;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+fn server() -> DnsServer {
+    common::server(vec![common::zone("example.com", "example.com.zone")])
+}
+
+fn src() -> SocketAddr {
+    "127.0.0.1:5353".parse().unwrap()
+}
+
+fn query(qname: &str) -> Vec<u8> {
+    // the id is 0 for caching (RFC 8484 section 4.1)
+    let mut request = DnsMessage::query(&qname.parse().unwrap(), QueryType::A)
+        .id(0)
+        .build();
+    let buffer = request.into_buf().unwrap();
+    buffer.buf[..buffer.pos()].to_vec()
+}
+
+fn get(uri: &str) -> Request<Full<Bytes>> {
+    Request::get(uri).body(Full::default()).unwrap()
+}
+
+fn post(content_type: &str, body: Vec<u8>) -> Request<Full<Bytes>> {
+    Request::post(https::DNS_QUERY_PATH)
+        .header(CONTENT_TYPE, content_type)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+async fn read_body(body: Full<Bytes>) -> DnsMessage {
+    let body = body.collect().await.unwrap().to_bytes();
+    DnsMessage::from_buf(&mut PacketBuffer::from_bytes(&body)).unwrap()
+}
+
+#[tokio::test]
+async fn doh_test() {
+    let server = server();
+
+    let uri = format!(
+        "/dns-query?ct&dns={}",
+        URL_SAFE_NO_PAD.encode(query("web.example.com"))
+    );
+    let response = handle_http(get(&uri), src(), &server).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], DNS_MESSAGE);
+    assert_eq!(response.headers()[CACHE_CONTROL], "max-age=3600");
+    let message = read_body(response.into_body()).await;
+    assert_eq!(message.answers[0].data.to_string(), "192.0.2.80");
+
+    // a negative answer is cached for the TTL of its SOA
+    let request = post(DNS_MESSAGE, query("nope.example.com"));
+    let response = handle_http(request, src(), &server).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CACHE_CONTROL], "max-age=300");
+    let message = read_body(response.into_body()).await;
+    assert_eq!(message.header.rescode, ResultCode::NXDOMAIN);
+
+    for (request, status) in [
+        (get("/resolve?dns=AAAA"), StatusCode::NOT_FOUND),
+        (get("/dns-query"), StatusCode::BAD_REQUEST),
+        (get("/dns-query?dns=not*base64"), StatusCode::BAD_REQUEST),
+        (get("/dns-query?dns=AAAA"), StatusCode::BAD_REQUEST),
+        (
+            post("application/json", query("web.example.com")),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ),
+        (
+            post(
+                "application/dns-message; charset=utf-8",
+                query("web.example.com"),
+            ),
+            StatusCode::OK,
+        ),
+        (
+            post("Application/DNS-Message", query("web.example.com")),
+            StatusCode::OK,
+        ),
+        (
+            Request::put("/dns-query").body(Full::default()).unwrap(),
+            StatusCode::METHOD_NOT_ALLOWED,
+        ),
+    ] {
+        let uri = request.uri().clone();
+        let response = handle_http(request, src(), &server).await;
+        assert_eq!(response.status(), status, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn doh_access_test() {
+    let mut config = common::config(vec![common::zone("example.com", "example.com.zone")]);
+    config.access.authoritative = AccessRules::new(vec![AccessRule {
+        networks: Acl::new(vec!["127.0.0.2".parse().unwrap()]),
+        action: Access::Deny,
+    }]);
+    let server = DnsServer::new(config).unwrap();
+
+    // a header announcing a question it does not hold
+    let malformed = vec![0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for (client, status) in [
+        ("127.0.0.1:5353", StatusCode::BAD_REQUEST),
+        ("127.0.0.2:5353", StatusCode::FORBIDDEN),
+    ] {
+        let request = post(DNS_MESSAGE, malformed.clone());
+        let response = handle_http(request, client.parse().unwrap(), &server).await;
+        assert_eq!(response.status(), status, "{}", client);
+    }
+}
+
+#[tokio::test]
+async fn doh_http2_test() {
+    let certificate = common::certificate("doh");
+    let settings = DohSettings {
+        port: 0,
+        tls: Some(certificate.settings.clone()),
+        idle_timeout: 10,
+        max_connections: 16,
+        trusted_proxies: Acl::default(),
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate.der.clone()).unwrap();
+    // the files live as long as the listener
+    tokio::spawn(async move {
+        https::serve(listener, settings, Arc::new(server())).await;
+        drop(certificate);
+    });
+
+    let mut config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(
+            ServerName::try_from("localhost").unwrap(),
+            TcpStream::connect(addr).await.unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(b"h2".as_slice()));
+
+    let (mut sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(connection);
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("https://localhost/dns-query")
+        .header(CONTENT_TYPE, DNS_MESSAGE)
+        .body(Full::new(Bytes::from(query("web.example.com"))))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let message = DnsMessage::from_buf(&mut PacketBuffer::from_bytes(&body)).unwrap();
+    assert_eq!(message.answers[0].data.to_string(), "192.0.2.80");
+}
+
+#[test]
+fn client_addr_test() {
+    const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+    let proxies = Acl::new(vec![
+        "127.0.0.1".parse().unwrap(),
+        "10.0.0.0/8".parse().unwrap(),
+    ]);
+    let headers = |name: HeaderName, value| {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    };
+    let client = |headers: &HeaderMap, src: &str| {
+        client_addr(headers, src.parse().unwrap(), &proxies)
+            .ip()
+            .to_string()
+    };
+
+    let forwarded = headers(
+        FORWARDED,
+        "for=192.0.2.60;proto=https, for=\"[2001:db8::1]:4711\", for=10.0.0.2",
+    );
+    assert_eq!(client(&forwarded, "127.0.0.1:5353"), "2001:db8::1");
+    // only a trusted proxy tells the client
+    assert_eq!(client(&forwarded, "198.51.100.1:5353"), "198.51.100.1");
+
+    let mut both = headers(X_FORWARDED_FOR, "203.0.113.9, 192.0.2.1, 10.1.2.3");
+    assert_eq!(client(&both, "127.0.0.1:5353"), "192.0.2.1");
+    both.insert(FORWARDED, HeaderValue::from_static("for=192.0.2.77"));
+    assert_eq!(client(&both, "127.0.0.1:5353"), "192.0.2.77");
+
+    let hidden = headers(FORWARDED, "for=_hidden, for=10.0.0.2");
+    assert_eq!(client(&hidden, "127.0.0.1:5353"), "10.0.0.2");
+    assert_eq!(client(&HeaderMap::new(), "127.0.0.1:5353"), "127.0.0.1");
+}
+
+#[tokio::test]
+async fn doh_idle_timeout_test() {
+    let settings = DohSettings {
+        port: 0,
+        tls: None,
+        idle_timeout: 1,
+        max_connections: 1,
+        trusted_proxies: Acl::default(),
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(https::serve(listener, settings, Arc::new(server())));
+
+    // a connection without a request is closed
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let read = timeout(Duration::from_secs(3), stream.read(&mut [0; 16])).await;
+    assert_eq!(read.unwrap().unwrap(), 0);
+
+    // which lets the next one in
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let read = timeout(Duration::from_secs(3), stream.read(&mut [0; 16])).await;
+    assert_eq!(read.unwrap().unwrap(), 0);
+}
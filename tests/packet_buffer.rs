@@ -0,0 +1,85 @@
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+
+/// Header with the id 0xBEEF, RD set and `qd` questions and `an` answers
+fn header(qd: u8, an: u8) -> Vec<u8> {
+    vec![0xBE, 0xEF, 0x01, 0x00, 0, qd, 0, an, 0, 0, 0, 0]
+}
+
+fn parse(bytes: &[u8], strict: bool) -> cdn_dns::errors::Result<DnsMessage> {
+    let mut buffer = PacketBuffer::from_bytes(bytes);
+    buffer.set_strict(strict);
+    DnsMessage::from_buf(&mut buffer)
+}
+
+#[test]
+fn truncated_packet_test() {
+    let qname = "www.esi.dz".parse::<DnsName>().unwrap();
+    let mut message = DnsMessage::query(&qname, QueryType::A).id(1).build();
+    let buffer = message.into_buf().unwrap();
+    let bytes = &buffer.buf[..buffer.pos()];
+
+    assert!(parse(bytes, true).is_ok());
+    for len in 0..bytes.len() {
+        assert!(parse(&bytes[..len], true).is_err());
+    }
+}
+
+#[test]
+fn compression_pointer_test() {
+    // question name pointing forward to "esi.dz" at offset 18
+    let mut forward = header(1, 0);
+    forward.extend([0xC0, 18, 0, 1, 0, 1]);
+    forward.extend([3, b'e', b's', b'i', 2, b'd', b'z', 0]);
+    assert!(parse(&forward, false).is_ok());
+    assert!(parse(&forward, true).is_err());
+
+    // question name pointing to itself
+    let mut own = header(1, 0);
+    own.extend([0xC0, 12, 0, 1, 0, 1]);
+    assert!(parse(&own, false).is_err());
+    assert!(parse(&own, true).is_err());
+
+    // question name pointing into the header
+    let mut into_header = header(1, 0);
+    into_header.extend([0xC0, 2, 0, 1, 0, 1]);
+    assert!(parse(&into_header, true).is_err());
+
+    // answer name pointing back to the question name
+    let mut backward = header(1, 1);
+    backward.extend([3, b'e', b's', b'i', 2, b'd', b'z', 0, 0, 1, 0, 1]);
+    backward.extend([0xC0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 1, 2, 3, 4]);
+    let message = parse(&backward, true).unwrap();
+    assert_eq!(message.answers[0].domain, "esi.dz");
+}
+
+#[test]
+fn rdata_length_test() {
+    // A record claiming 5 bytes of data
+    let mut packet = header(0, 1);
+    packet.extend([0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 5, 1, 2, 3, 4, 5]);
+    assert!(parse(&packet, false).is_ok());
+    assert!(parse(&packet, true).is_err());
+
+    // A record claiming more data than the packet holds
+    let mut packet = header(0, 1);
+    packet.extend([0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 9, 1, 2, 3, 4]);
+    assert!(parse(&packet, false).is_err());
+}
+
+#[test]
+fn format_error_test() {
+    let mut packet = header(1, 0);
+    packet.extend([0xC0, 12]);
+    assert!(parse(&packet, true).is_err());
+
+    let mut buffer = PacketBuffer::from_bytes(&packet);
+    let response = DnsMessage::format_error(&mut buffer).unwrap();
+    assert_eq!(response.header.id, 0xBEEF);
+    assert_eq!(response.header.rescode, ResultCode::FORMERR);
+    assert!(response.header.response && response.header.recursion_desired);
+
+    assert!(DnsMessage::format_error(&mut PacketBuffer::from_bytes(&packet[..5])).is_none());
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use std::fs::{self, File};
+use std::time::{Duration, SystemTime};
+
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_record::{DnsRecord, RecordData};
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_resolver::policy::Policy;
+use cdn_dns::settings::config::{BlockAction, ListFormat, PolicyListSettings, PolicySettings};
+use common::TempFile;
+
+const RPZ: &str = "\
+$TTL 300
+@ SOA localhost. hostmaster.localhost. 1 3600 600 86400 300
+@ NS localhost.
+allowed.example.net CNAME rpz-passthru.
+nodata.example.net CNAME *.
+dropped.example.net CNAME rpz-drop.
+*.ads.example.net CNAME .
+portal.example.net A 192.0.2.10
+portal.example.net AAAA 2001:db8::10
+alias.example.net CNAME www.example.org.
+24.0.100.51.198.rpz-ip CNAME .
+32.1.100.51.198.rpz-ip CNAME rpz-passthru.
+64.zz.db8.2001.rpz-ip CNAME *.
+ns.evil.example.rpz-nsdname CNAME .
+*.sinkhole.example.rpz-nsdname CNAME rpz-drop.
+";
+
+/// A list of `text` written to a temporary file, removed once
+/// the `TempFile` is dropped
+fn list(name: &str, text: &str, format: ListFormat) -> (PolicyListSettings, TempFile) {
+    let file = TempFile::new(name, text);
+    let settings = PolicyListSettings {
+        file: file.path(),
+        format,
+        action: BlockAction::Nxdomain,
+        origin: Some("rpz.local".parse().unwrap()),
+    };
+    (settings, file)
+}
+
+fn policy(lists: Vec<PolicyListSettings>) -> Policy {
+    Policy::new(&PolicySettings {
+        lists,
+        reload_interval: 60,
+    })
+    .unwrap()
+}
+
+fn query(qname: &str, qtype: QueryType) -> DnsMessage {
+    DnsMessage::query(&qname.parse().unwrap(), qtype)
+        .id(7)
+        .build()
+}
+
+/// Answers every query with `records`, their owner the name
+/// queried
+async fn resolve(request: DnsMessage, records: Vec<RecordData>) -> DnsMessage {
+    let question = &request.questions[0];
+    let mut response = DnsMessage::response_to(&request).build();
+    for data in records {
+        let record = DnsRecord::with_data(question.qname.clone(), 300, data);
+        response.answers.push(record);
+    }
+    response
+}
+
+async fn answer(policy: &Policy, qname: &str, records: Vec<RecordData>) -> Option<DnsMessage> {
+    let request = query(qname, QueryType::A);
+    policy
+        .handle_query(&request, |request| resolve(request, records.clone()))
+        .await
+}
+
+fn a(addr: &str) -> RecordData {
+    RecordData::A {
+        addr: addr.parse().unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn blocklist_test() {
+    let (domains, _domains) = list(
+        "domains",
+        "# malware\nmalware.example.com\n*.tracker.example.com # and below\n",
+        ListFormat::Domains,
+    );
+    let (mut hosts, _hosts) = list(
+        "hosts",
+        "0.0.0.0 ads.example.com\n192.0.2.1 lab.example.com\n2001:db8::1 lab.example.com\n",
+        ListFormat::Hosts,
+    );
+    hosts.action = BlockAction::Nodata;
+    let policy = policy(vec![domains, hosts]);
+
+    let response = answer(&policy, "malware.example.com", vec![a("203.0.113.1")]).await;
+    let response = response.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NXDOMAIN);
+    assert!(response.header.recursion_available);
+    assert!(response.answers.is_empty());
+
+    // the wildcard is triggered by the subdomains only
+    let response = answer(&policy, "a.b.tracker.example.com", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NXDOMAIN);
+    let response = answer(&policy, "tracker.example.com", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().answers.len(), 1);
+
+    let response = answer(&policy, "ADS.example.com", vec![a("203.0.113.1")]).await;
+    let response = response.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert!(response.answers.is_empty());
+
+    let response = answer(&policy, "lab.example.com", vec![a("203.0.113.1")]).await;
+    let answers = response.unwrap().answers;
+    assert_eq!(answers.len(), 1);
+    assert_eq!(answers[0].data, a("192.0.2.1"));
+}
+
+#[tokio::test]
+async fn rpz_test() {
+    let (allowed, _allowed) = list(
+        "allowed.rpz",
+        "$TTL 300\n@ SOA localhost. hostmaster.localhost. 1 3600 600 86400 300\nwww.ads.example.net CNAME rpz-passthru.\n",
+        ListFormat::Rpz,
+    );
+    let (rpz, _rpz) = list("policy.rpz", RPZ, ListFormat::Rpz);
+    let policy = policy(vec![allowed, rpz]);
+
+    // the first list triggered applies
+    let response = answer(&policy, "www.ads.example.net", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NOERROR);
+    let response = answer(&policy, "img.ads.example.net", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NXDOMAIN);
+    let response = answer(&policy, "nodata.example.net", vec![a("203.0.113.1")]).await;
+    assert!(response.unwrap().answers.is_empty());
+    assert!(answer(&policy, "dropped.example.net", vec![])
+        .await
+        .is_none());
+
+    // the local data of the type queried, or NODATA
+    let response = answer(&policy, "portal.example.net", vec![a("203.0.113.1")]).await;
+    let answers = response.unwrap().answers;
+    assert_eq!(answers.len(), 1);
+    assert_eq!(answers[0].data, a("192.0.2.10"));
+    assert_eq!(answers[0].domain, "portal.example.net");
+    let request = query("portal.example.net", QueryType::MX);
+    let response = policy
+        .handle_query(&request, |request| resolve(request, vec![]))
+        .await;
+    assert!(response.unwrap().answers.is_empty());
+
+    // a CNAME is chased
+    let response = answer(&policy, "alias.example.net", vec![a("203.0.113.8")]).await;
+    let answers = response.unwrap().answers;
+    assert_eq!(answers.len(), 2);
+    assert_eq!(answers[0].qtype(), QueryType::CNAME);
+    assert_eq!(answers[1].domain, "www.example.org");
+
+    // the longest network containing an address of the answer
+    let response = answer(&policy, "www.example.com", vec![a("198.51.100.7")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NXDOMAIN);
+    let response = answer(&policy, "www.example.com", vec![a("198.51.100.1")]).await;
+    assert_eq!(response.unwrap().answers.len(), 1);
+    let aaaa = RecordData::AAAA {
+        addr: "2001:db8::7".parse().unwrap(),
+    };
+    let response = answer(&policy, "www.example.com", vec![aaaa]).await;
+    let response = response.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert!(response.answers.is_empty());
+
+    let ns = |host: &str| RecordData::NS {
+        host: host.parse().unwrap(),
+    };
+    let response = answer(&policy, "example.com", vec![ns("ns.evil.example")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NXDOMAIN);
+    assert!(
+        answer(&policy, "example.com", vec![ns("a.sinkhole.example")])
+            .await
+            .is_none()
+    );
+    let response = answer(&policy, "example.com", vec![ns("ns.example.com")]).await;
+    assert_eq!(response.unwrap().answers.len(), 1);
+}
+
+#[tokio::test]
+async fn policy_reload_test() {
+    let (domains, file) = list("reload", "blocked.example.com\n", ListFormat::Domains);
+    let policy = policy(vec![domains]);
+    assert_eq!(policy.reload(), 0);
+
+    fs::write(file.path(), "other.example.com\n").unwrap();
+    let modified = SystemTime::now() + Duration::from_secs(1);
+    File::options()
+        .write(true)
+        .open(file.path())
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert_eq!(policy.reload(), 1);
+    let response = answer(&policy, "blocked.example.com", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NOERROR);
+    let response = answer(&policy, "other.example.com", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NXDOMAIN);
+
+    // a list failing to load keeps its rules
+    fs::write(file.path(), "not a..name\n").unwrap();
+    File::options()
+        .write(true)
+        .open(file.path())
+        .unwrap()
+        .set_modified(modified + Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(policy.reload(), 0);
+    let response = answer(&policy, "other.example.com", vec![a("203.0.113.1")]).await;
+    assert_eq!(response.unwrap().header.rescode, ResultCode::NXDOMAIN);
+}
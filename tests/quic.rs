@@ -0,0 +1,186 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::doq::{self, DOQ_ALPN, DOQ_NO_ERROR, DOQ_PROTOCOL_ERROR};
+use cdn_dns::dns_server::{tcp, DnsServer};
+use cdn_dns::settings::acl::{Access, AccessRule, AccessRules, Acl};
+use cdn_dns::settings::config::{DoqSettings, Settings, ZoneSettings};
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{Connection, ConnectionError, Endpoint, VarInt};
+use rustls::client::ClientConfig;
+use rustls::crypto::ring::default_provider;
+use rustls::RootCertStore;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+fn config() -> Settings {
+    common::config(vec![ZoneSettings {
+        allow_transfer: Acl::new(vec!["127.0.0.0/8".parse().unwrap()]),
+        ..common::zone("example.com", "example.com.zone")
+    }])
+}
+
+/// Starts a DoQ listener of `config` with a self-signed
+/// certificate for `localhost`, and a client endpoint trusting it
+async fn listen(name: &str, config: Settings, max_connections: usize) -> (SocketAddr, Endpoint) {
+    let server = Arc::new(DnsServer::new(config).unwrap());
+
+    let certificate = common::certificate(name);
+    let settings = DoqSettings {
+        port: 0,
+        tls: certificate.settings.clone(),
+        idle_timeout: 10,
+        max_connections,
+    };
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate.der.clone()).unwrap();
+    // the files live as long as the listener
+    tokio::spawn(async move {
+        doq::serve(socket, settings, server).await;
+        drop(certificate);
+    });
+
+    let mut config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![DOQ_ALPN.to_vec()];
+    config.enable_early_data = true;
+    let config = QuicClientConfig::try_from(config).unwrap();
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(config)));
+    (addr, endpoint)
+}
+
+/// Sends `request` on a new stream and reads the answers until
+/// the server finishes it
+async fn exchange(connection: &Connection, request: DnsMessage) -> Vec<DnsMessage> {
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    tcp::write_message(&mut send, request).await.unwrap();
+    send.finish().unwrap();
+
+    let data = recv.read_to_end(u16::MAX as usize * 16).await.unwrap();
+    let mut messages = Vec::new();
+    let mut data = &data[..];
+    while let [high, low, rest @ ..] = data {
+        let len = u16::from_be_bytes([*high, *low]) as usize;
+        let mut buffer = PacketBuffer::from_bytes(&rest[..len]);
+        messages.push(DnsMessage::from_buf(&mut buffer).unwrap());
+        data = &rest[len..];
+    }
+    messages
+}
+
+fn query(qname: &str, qtype: QueryType) -> DnsMessage {
+    DnsMessage::query(&qname.parse().unwrap(), qtype)
+        .id(0)
+        .build()
+}
+
+#[tokio::test]
+async fn doq_test() {
+    let (addr, endpoint) = listen("doq", config(), 1024).await;
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+
+    // each query is on its own stream, answered concurrently
+    let (web, mail) = tokio::join!(
+        exchange(&connection, query("web.example.com", QueryType::A)),
+        exchange(&connection, query("mail.example.com", QueryType::A)),
+    );
+    assert_eq!(web[0].header.id, 0);
+    assert_eq!(web[0].answers[0].data.to_string(), "192.0.2.80");
+    assert_eq!(mail[0].answers[0].data.to_string(), "192.0.2.25");
+
+    let messages = exchange(&connection, query("example.com", QueryType::AXFR)).await;
+    assert_eq!(messages[0].header.rescode, ResultCode::NOERROR);
+    assert_eq!(messages[0].answers[0].qtype(), QueryType::SOA);
+
+    // a nonzero message id is a protocol error
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+    let request = DnsMessage::query(&"web.example.com".parse().unwrap(), QueryType::A)
+        .id(42)
+        .build();
+    tcp::write_message(&mut send, request).await.unwrap();
+    send.finish().unwrap();
+    match connection.closed().await {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, VarInt::from_u32(DOQ_PROTOCOL_ERROR))
+        }
+        err => panic!("Unexpected close {}", err),
+    }
+}
+
+#[tokio::test]
+async fn doq_0rtt_test() {
+    let (addr, endpoint) = listen("doq-0rtt", config(), 1024).await;
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    exchange(&connection, query("web.example.com", QueryType::A)).await;
+    connection.close(VarInt::from_u32(DOQ_NO_ERROR), b"");
+
+    // the session of the first connection is resumed in 0-RTT
+    let connecting = endpoint.connect(addr, "localhost").unwrap();
+    let Ok((connection, accepted)) = connecting.into_0rtt() else {
+        panic!("No 0-RTT session to resume");
+    };
+    let (web, transfer) = tokio::join!(
+        exchange(&connection, query("web.example.com", QueryType::A)),
+        exchange(&connection, query("example.com", QueryType::AXFR)),
+    );
+    assert!(accepted.await);
+    assert_eq!(web[0].answers[0].data.to_string(), "192.0.2.80");
+    // a transfer is not replayable
+    assert_eq!(transfer.len(), 1);
+    assert_eq!(transfer[0].header.rescode, ResultCode::REFUSED);
+}
+
+#[tokio::test]
+async fn doq_access_test() {
+    let mut config = config();
+    config.access.authoritative = AccessRules::new(vec![AccessRule {
+        networks: Acl::new(vec!["127.0.0.1".parse().unwrap()]),
+        action: Access::Deny,
+    }]);
+    let (addr, endpoint) = listen("doq-access", config, 1024).await;
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+
+    // a header announcing a question it does not hold, from a
+    // denied client, is not answered
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    send.write_all(&[0, 12, 0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+        .await
+        .unwrap();
+    send.finish().unwrap();
+    assert!(recv
+        .read_to_end(u16::MAX as usize)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn doq_max_connections_test() {
+    let (addr, endpoint) = listen("doq-max-connections", config(), 1).await;
+    let first = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+
+    // the second connection waits for the first one to close
+    let mut second = Box::pin(endpoint.connect(addr, "localhost").unwrap());
+    assert!(timeout(Duration::from_millis(300), second.as_mut())
+        .await
+        .is_err());
+    first.close(VarInt::from_u32(DOQ_NO_ERROR), b"");
+    let second = timeout(Duration::from_secs(5), second)
+        .await
+        .unwrap()
+        .unwrap();
+    let answers = exchange(&second, query("web.example.com", QueryType::A)).await;
+    assert_eq!(answers[0].answers[0].data.to_string(), "192.0.2.80");
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_record::{DnsRecord, RecordData};
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::rrl::{RateLimiter, Verdict, MAX_ACCOUNTS};
+use cdn_dns::dns_server::{udp, DnsServer};
+use cdn_dns::settings::acl::Acl;
+use cdn_dns::settings::config::RrlSettings;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+fn settings(responses_per_second: u32) -> RrlSettings {
+    RrlSettings {
+        responses_per_second,
+        slip: 2,
+        window: 15,
+        ipv4_prefix: 24,
+        ipv6_prefix: 56,
+        exempt: Acl::new(vec!["192.0.2.53".parse().unwrap()]),
+    }
+}
+
+fn response(qname: &str) -> DnsMessage {
+    let request = DnsMessage::query(&qname.parse().unwrap(), QueryType::A).build();
+    DnsMessage::response_to(&request).build()
+}
+
+fn nxdomain(qname: &str) -> DnsMessage {
+    let mut response = response(qname);
+    response.header.rescode = ResultCode::NXDOMAIN;
+    let soa = RecordData::SOA {
+        mname: "ns1.example.com".parse().unwrap(),
+        rname: "hostmaster.example.com".parse().unwrap(),
+        serial: 1,
+        refresh: 7200,
+        retry: 900,
+        expire: 604800,
+        minimum: 300,
+    };
+    let zone = "example.com".parse().unwrap();
+    response
+        .authorities
+        .push(DnsRecord::with_data(zone, 300, soa));
+    response
+}
+
+fn ip(text: &str) -> IpAddr {
+    text.parse().unwrap()
+}
+
+#[test]
+fn rrl_test() {
+    let limiter = RateLimiter::new(&settings(3)).unwrap();
+    let web = response("web.example.com");
+
+    let verdicts: Vec<_> = (0..6)
+        .map(|_| limiter.check(ip("192.0.2.1"), &web))
+        .collect();
+    assert_eq!(
+        verdicts,
+        [
+            Verdict::Send,
+            Verdict::Send,
+            Verdict::Send,
+            Verdict::Drop,
+            Verdict::Slip,
+            Verdict::Drop
+        ]
+    );
+    // the /24 shares the balance, the exempt clients have none
+    assert_eq!(limiter.check(ip("192.0.2.200"), &web), Verdict::Slip);
+    assert_eq!(limiter.check(ip("192.0.2.53"), &web), Verdict::Send);
+    assert_eq!(limiter.check(ip("198.51.100.1"), &web), Verdict::Send);
+    assert_eq!(
+        limiter.check(ip("192.0.2.1"), &response("mail.example.com")),
+        Verdict::Send
+    );
+
+    // the names that do not exist share the balance of their zone
+    let limiter = RateLimiter::new(&settings(1)).unwrap();
+    assert_eq!(
+        limiter.check(ip("2001:db8::1"), &nxdomain("a.example.com")),
+        Verdict::Send
+    );
+    assert_eq!(
+        limiter.check(ip("2001:db8::2"), &nxdomain("b.example.com")),
+        Verdict::Drop
+    );
+    assert_eq!(
+        limiter.check(ip("2001:db8:0:100::1"), &nxdomain("c.example.com")),
+        Verdict::Send
+    );
+    assert_eq!(limiter.dropped(), 1);
+    assert_eq!(limiter.slipped(), 0);
+
+    let mut invalid = settings(1);
+    invalid.ipv4_prefix = 33;
+    assert!(RateLimiter::new(&invalid).is_err());
+}
+
+#[test]
+fn rrl_settings_test() {
+    assert!(RateLimiter::new(&settings(0)).is_err());
+    let mut invalid = settings(1);
+    invalid.window = 0;
+    assert!(RateLimiter::new(&invalid).is_err());
+}
+
+#[test]
+fn rrl_accounts_test() {
+    let limiter = RateLimiter::new(&settings(1)).unwrap();
+    for i in 0..MAX_ACCOUNTS {
+        let qname = format!("host{}.example.com", i);
+        assert_eq!(
+            limiter.check(ip("192.0.2.1"), &response(&qname)),
+            Verdict::Send
+        );
+    }
+    assert_eq!(limiter.accounts(), MAX_ACCOUNTS);
+
+    // the new accounts share the overflow balance
+    let first = response("new1.example.com");
+    let second = response("new2.example.com");
+    assert_eq!(limiter.check(ip("192.0.2.1"), &first), Verdict::Send);
+    assert_eq!(limiter.check(ip("198.51.100.1"), &second), Verdict::Drop);
+    assert_eq!(limiter.accounts(), MAX_ACCOUNTS);
+
+    // while the known ones keep theirs
+    let known = response(&format!("host{}.example.com", MAX_ACCOUNTS - 1));
+    assert_eq!(limiter.check(ip("192.0.2.1"), &known), Verdict::Drop);
+    assert_eq!(limiter.check(ip("192.0.2.2"), &first), Verdict::Slip);
+}
+
+#[tokio::test]
+async fn rrl_udp_test() {
+    let mut config = common::config(vec![common::zone("example.com", "example.com.zone")]);
+    config.rrl = Some(settings(2));
+    let server = Arc::new(DnsServer::new(config).unwrap());
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let udp_server = server.clone();
+    tokio::spawn(udp::serve(socket, udp_server));
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.connect(addr).await.unwrap();
+    let mut responses = Vec::new();
+    for id in 1..=4 {
+        let mut request = DnsMessage::query(&"web.example.com".parse().unwrap(), QueryType::A)
+            .id(id)
+            .build();
+        let buffer = request.into_buf().unwrap();
+        client.send(&buffer.buf[..buffer.pos()]).await.unwrap();
+
+        let mut buffer = PacketBuffer::new();
+        match timeout(Duration::from_millis(300), client.recv(&mut buffer.buf)).await {
+            Ok(len) => {
+                buffer.truncate(len.unwrap());
+                responses.push(Some(DnsMessage::from_buf(&mut buffer).unwrap()));
+            }
+            Err(_) => responses.push(None),
+        }
+    }
+
+    assert_eq!(responses[0].as_ref().unwrap().answers.len(), 1);
+    assert_eq!(responses[1].as_ref().unwrap().answers.len(), 1);
+    assert!(responses[2].is_none());
+    let slipped = responses[3].as_ref().unwrap();
+    assert!(slipped.header.truncated_message);
+    assert!(slipped.answers.is_empty());
+
+    let limiter = server.rate_limiter().unwrap();
+    assert_eq!((limiter.dropped(), limiter.slipped()), (1, 1));
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdn_dns::authority::secondary::{refresh, run};
+use cdn_dns::authority::zone::Zone;
+use cdn_dns::authority::zone_file;
+use cdn_dns::dns_message::dns_header::{Opcode, ResultCode};
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::{tcp, DnsServer};
+use cdn_dns::settings::acl::Acl;
+use cdn_dns::settings::config::ZoneSettings;
+use common::TempFile;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+fn origin() -> DnsName {
+    "example.com".parse().unwrap()
+}
+
+/// A version of example.com, `text` adds to its records
+fn zone(serial: u32, text: &str) -> Zone {
+    let text = format!(
+        "$TTL 300\n@ SOA ns1 admin {} 3600 1 2 5\n@ NS ns1\nns1 A 192.0.2.1\n{}",
+        serial, text
+    );
+    Zone::new(origin(), zone_file::parse(&text, &origin()).unwrap()).unwrap()
+}
+
+/// A primary serving `zone` over TCP on a local port
+async fn primary(zone: Zone) -> (Arc<DnsServer>, SocketAddr, JoinHandle<()>) {
+    let server = Arc::new(common::server(vec![ZoneSettings {
+        allow_transfer: Acl::new(vec!["127.0.0.1".parse().unwrap()]),
+        ..common::zone("example.com", "example.com.zone")
+    }]));
+    server.catalog.insert(zone);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let task = tokio::spawn(tcp::serve(listener, server.clone()));
+    (server, addr, task)
+}
+
+/// The settings of a secondary of `primary`, saving the zone
+/// to a temporary file
+fn settings(primary: SocketAddr, name: &str) -> (ZoneSettings, TempFile) {
+    let file = TempFile::reserve(&format!("{}.zone", name));
+    let settings = ZoneSettings {
+        origin: origin(),
+        file: file.path(),
+        primary: Some(primary),
+        ..Default::default()
+    };
+    (settings, file)
+}
+
+fn secondary_server(settings: &ZoneSettings) -> DnsServer {
+    common::server(vec![settings.clone()])
+}
+
+#[tokio::test]
+async fn refresh_test() {
+    let (primary, addr, _task) = primary(zone(1, "www A 192.0.2.80\n")).await;
+    let (settings, _file) = settings(addr, "refresh");
+    let secondary = secondary_server(&settings);
+    assert!(secondary.catalog.get(&origin()).is_none());
+
+    // the first transfer is a full one, saved to the file
+    assert!(refresh(&secondary.catalog, &settings, None).await.unwrap());
+    assert_eq!(secondary.catalog.get(&origin()).unwrap().serial(), 1);
+    let saved = Zone::load(origin(), settings.file.as_ref()).unwrap();
+    assert_eq!(saved.records().count(), 4);
+
+    // up to date
+    assert!(!refresh(&secondary.catalog, &settings, None).await.unwrap());
+
+    // then incremental ones, from the journal of the primary
+    primary.catalog.update(zone(2, "www A 192.0.2.81\n"));
+    primary
+        .catalog
+        .update(zone(3, "www A 192.0.2.81\nmail A 192.0.2.25\n"));
+    assert!(refresh(&secondary.catalog, &settings, None).await.unwrap());
+    let zone = secondary.catalog.get(&origin()).unwrap();
+    assert_eq!(zone.serial(), 3);
+    let records: Vec<String> = zone.records().map(|rec| rec.to_string()).collect();
+    assert!(records.contains(&"mail.example.com.\t300\tIN\tA\t192.0.2.25".to_string()));
+    assert!(records.contains(&"www.example.com.\t300\tIN\tA\t192.0.2.81".to_string()));
+    assert!(!records.contains(&"www.example.com.\t300\tIN\tA\t192.0.2.80".to_string()));
+
+    // a restarted secondary loads the saved zone
+    let restarted = secondary_server(&settings);
+    assert_eq!(restarted.catalog.get(&origin()).unwrap().serial(), 3);
+}
+
+#[tokio::test]
+async fn notify_test() {
+    let (primary, addr, task) = primary(zone(1, "")).await;
+    let (settings, _file) = settings(addr, "notify");
+    let secondary = Arc::new(secondary_server(&settings));
+    tokio::spawn(run(secondary.clone(), settings.clone()));
+    wait_for(|| {
+        secondary
+            .catalog
+            .get(&origin())
+            .is_some_and(|zone| zone.serial() == 1)
+    })
+    .await;
+
+    // NOTIFY is only accepted from the primary
+    let notify = DnsMessage::query(&origin(), QueryType::SOA)
+        .opcode(Opcode::NOTIFY)
+        .id(5)
+        .build();
+    let stranger = "192.0.2.1:53".parse().unwrap();
+    let response = secondary.handle_request(&notify, stranger).await.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::REFUSED);
+
+    primary.catalog.update(zone(2, "www A 192.0.2.80\n"));
+    let response = secondary.handle_request(&notify, addr).await.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert_eq!(response.header.opcode, Opcode::NOTIFY);
+    assert_eq!(response.header.id, 5);
+    wait_for(|| {
+        secondary
+            .catalog
+            .get(&origin())
+            .is_some_and(|zone| zone.serial() == 2)
+    })
+    .await;
+
+    // without its primary, the zone expires
+    task.abort();
+    secondary.handle_request(&notify, addr).await.unwrap();
+    wait_for(|| secondary.catalog.get(&origin()).is_none()).await;
+}
+
+async fn wait_for(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("Timed out");
+}
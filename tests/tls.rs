@@ -0,0 +1,97 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::{tcp, tls};
+use cdn_dns::settings::config::DotSettings;
+use rustls::client::ClientConfig;
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::ServerName;
+use rustls::{HandshakeKind, RootCertStore};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+async fn listen(
+    name: &str,
+    idle_timeout: u64,
+    max_connections: usize,
+) -> (SocketAddr, TlsConnector) {
+    let server = Arc::new(common::server(vec![common::zone(
+        "example.com",
+        "example.com.zone",
+    )]));
+
+    let certificate = common::certificate(name);
+    let settings = DotSettings {
+        port: 0,
+        tls: certificate.settings.clone(),
+        idle_timeout,
+        max_connections,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate.der.clone()).unwrap();
+    // the files live as long as the listener
+    tokio::spawn(async move {
+        tls::serve(listener, settings, server).await;
+        drop(certificate);
+    });
+
+    let mut config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![tls::DOT_ALPN.to_vec()];
+    (addr, TlsConnector::from(Arc::new(config)))
+}
+
+async fn connect(addr: SocketAddr, connector: &TlsConnector) -> TlsStream<TcpStream> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    connector.connect(name, stream).await.unwrap()
+}
+
+#[tokio::test]
+async fn dot_test() {
+    let (addr, connector) = listen("dot", 10, 16).await;
+
+    let mut stream = connect(addr, &connector).await;
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(tls::DOT_ALPN));
+    for id in 1..=2 {
+        let request = DnsMessage::query(&"web.example.com".parse().unwrap(), QueryType::A)
+            .id(id)
+            .build();
+        tcp::write_message(&mut stream, request).await.unwrap();
+        let response = tcp::read_message(&mut stream).await.unwrap();
+        assert_eq!(response.header.id, id);
+        assert_eq!(response.answers[0].data.to_string(), "192.0.2.80");
+    }
+    drop(stream);
+
+    // the ticket received on the first connection resumes the session
+    let stream = connect(addr, &connector).await;
+    assert_eq!(
+        stream.get_ref().1.handshake_kind(),
+        Some(HandshakeKind::Resumed)
+    );
+}
+
+#[tokio::test]
+async fn dot_limits_test() {
+    let (addr, connector) = listen("dot-limits", 1, 1).await;
+
+    // the second connection waits for the first one to go idle
+    let mut first = connect(addr, &connector).await;
+    let second = timeout(Duration::from_secs(5), connect(addr, &connector)).await;
+    assert!(second.is_ok());
+    let mut buffer = [0; 1];
+    assert_eq!(first.read(&mut buffer).await.unwrap_or_default(), 0);
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use cdn_dns::authority::catalog::Catalog;
+use cdn_dns::authority::transfer::{axfr, ixfr, MAX_TRANSFER_MESSAGE};
+use cdn_dns::authority::zone::{serial_lt, Zone};
+use cdn_dns::authority::zone_file;
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::dns_record::DnsRecord;
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::{tcp, DnsServer};
+use cdn_dns::settings::acl::{Acl, Network};
+use cdn_dns::settings::config::ZoneSettings;
+
+fn origin() -> DnsName {
+    "example.com".parse().unwrap()
+}
+
+/// A version of example.com, `text` adds to its records
+fn zone(serial: u32, text: &str) -> Zone {
+    let text = format!(
+        "$TTL 300\n@ SOA ns1 admin {} 2 3 4 5\n@ NS ns1\nns1 A 192.0.2.1\n{}",
+        serial, text
+    );
+    Zone::new(origin(), zone_file::parse(&text, &origin()).unwrap()).unwrap()
+}
+
+fn ixfr_request(serial: u32) -> DnsMessage {
+    let soa = zone(serial, "").soa().clone();
+    DnsMessage::query(&origin(), QueryType::IXFR)
+        .id(9)
+        .authority(soa)
+        .build()
+}
+
+fn answers(messages: &[DnsMessage]) -> Vec<String> {
+    messages
+        .iter()
+        .flat_map(|message| &message.answers)
+        .map(DnsRecord::to_string)
+        .collect()
+}
+
+#[test]
+fn axfr_test() {
+    let hosts: String = (0..2000)
+        .map(|i| format!("host{} A 192.0.2.{}\n", i, i % 256))
+        .collect();
+    let zone = zone(1, &hosts);
+    let request = DnsMessage::query(&origin(), QueryType::AXFR).id(9).build();
+
+    let messages = axfr(&request, &zone).unwrap();
+    assert!(messages.len() > 1);
+    let records: Vec<&DnsRecord> = messages.iter().flat_map(|m| &m.answers).collect();
+    assert_eq!(records.len(), zone.records().count() + 1);
+    assert_eq!(records[0].qtype(), QueryType::SOA);
+    assert_eq!(records.last().unwrap().qtype(), QueryType::SOA);
+
+    for (i, message) in messages.iter().enumerate() {
+        assert_eq!(message.header.id, 9);
+        assert!(message.header.authoritative_answer);
+        assert_eq!(message.questions.len(), usize::from(i == 0));
+        let len = message
+            .clone()
+            .into_buf_with_size(u16::MAX as usize)
+            .unwrap();
+        assert!(len.pos() <= MAX_TRANSFER_MESSAGE);
+    }
+}
+
+#[test]
+fn ixfr_test() {
+    let catalog = Catalog::new();
+    catalog.insert(zone(1, "www A 192.0.2.80\n"));
+    catalog.update(zone(2, "www A 192.0.2.81\n"));
+    catalog.update(zone(3, "www A 192.0.2.81\nmail A 192.0.2.25\n"));
+    let zone = catalog.get(&origin()).unwrap();
+    assert_eq!(zone.journal().len(), 2);
+
+    let messages = ixfr(&ixfr_request(1), &zone).unwrap();
+    let records = answers(&messages);
+    let soa = |serial| {
+        format!(
+            "example.com.\t300\tIN\tSOA\tns1.example.com. admin.example.com. {} 2 3 4 5",
+            serial
+        )
+    };
+    assert_eq!(
+        records,
+        [
+            soa(3),
+            soa(1),
+            "www.example.com.\t300\tIN\tA\t192.0.2.80".into(),
+            soa(2),
+            "www.example.com.\t300\tIN\tA\t192.0.2.81".into(),
+            soa(2),
+            soa(3),
+            "mail.example.com.\t300\tIN\tA\t192.0.2.25".into(),
+            soa(3),
+        ]
+    );
+
+    // up to date, the SOA alone
+    assert_eq!(answers(&ixfr(&ixfr_request(3), &zone).unwrap()), [soa(3)]);
+
+    // older than the journal, the whole zone
+    let records = answers(&ixfr(&ixfr_request(0), &zone).unwrap());
+    assert_eq!(records.len(), zone.records().count() + 1);
+
+    // the client SOA is required
+    let request = DnsMessage::query(&origin(), QueryType::IXFR).build();
+    let messages = ixfr(&request, &zone).unwrap();
+    assert_eq!(messages[0].header.rescode, ResultCode::FORMERR);
+
+    // serials wrap around
+    assert!(serial_lt(u32::MAX, 1));
+    assert!(!serial_lt(1, u32::MAX));
+}
+
+#[test]
+fn acl_test() {
+    let acl = Acl::new(vec![
+        "192.0.2.0/24".parse().unwrap(),
+        "2001:db8::1".parse().unwrap(),
+    ]);
+    for (addr, allowed) in [
+        ("192.0.2.200", true),
+        ("192.0.3.1", false),
+        ("::ffff:192.0.2.1", true),
+        ("2001:db8::1", true),
+        ("2001:db8::2", false),
+    ] {
+        assert_eq!(acl.allows(addr.parse().unwrap()), allowed, "{}", addr);
+    }
+    assert!(!Acl::default().allows("192.0.2.1".parse().unwrap()));
+
+    for network in ["192.0.2.0/33", "192.0.2.0/", "host/24"] {
+        assert!(network.parse::<Network>().is_err(), "{}", network);
+    }
+}
+
+fn server() -> DnsServer {
+    common::server(vec![ZoneSettings {
+        allow_transfer: Acl::new(vec!["127.0.0.0/8".parse().unwrap()]),
+        ..common::zone("example.com", "example.com.zone")
+    }])
+}
+
+#[tokio::test]
+async fn transfer_server_test() {
+    let server = server();
+    let allowed: SocketAddr = "127.0.0.1:5353".parse().unwrap();
+    let denied: SocketAddr = "192.0.2.1:5353".parse().unwrap();
+    let request = DnsMessage::query(&origin(), QueryType::AXFR).build();
+
+    let messages = server.handle_stream_request(&request, allowed).await;
+    assert_eq!(messages[0].header.rescode, ResultCode::NOERROR);
+    assert_eq!(
+        answers(&messages).len(),
+        server.catalog.get(&origin()).unwrap().records().count() + 1
+    );
+
+    let messages = server.handle_stream_request(&request, denied).await;
+    assert_eq!(messages[0].header.rescode, ResultCode::REFUSED);
+    assert!(messages[0].answers.is_empty());
+
+    // only the origin of a zone can be transferred
+    let request = DnsMessage::query(&"www.example.com".parse().unwrap(), QueryType::AXFR).build();
+    let messages = server.handle_stream_request(&request, allowed).await;
+    assert_eq!(messages[0].header.rescode, ResultCode::NOTAUTH);
+
+    // no AXFR over UDP, IXFR too large for it is the SOA alone
+    let request = DnsMessage::query(&origin(), QueryType::AXFR).build();
+    let response = server.handle_request(&request, allowed).await.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NOTIMP);
+    let response = server
+        .handle_request(&ixfr_request(1), allowed)
+        .await
+        .unwrap();
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(response.answers[0].qtype(), QueryType::SOA);
+}
+
+#[tokio::test]
+async fn tcp_test() {
+    let server = server();
+    let (mut client, stream) = tokio::io::duplex(1 << 16);
+    let src = "127.0.0.1:5353".parse().unwrap();
+    let connection = tokio::spawn(async move {
+        tcp::handle_connection(stream, src, &server).await.unwrap();
+    });
+
+    // several queries on the same connection
+    let request = DnsMessage::query(&"web.example.com".parse().unwrap(), QueryType::A)
+        .id(1)
+        .build();
+    tcp::write_message(&mut client, request).await.unwrap();
+    let request = DnsMessage::query(&origin(), QueryType::AXFR).id(2).build();
+    tcp::write_message(&mut client, request).await.unwrap();
+
+    let response = tcp::read_message(&mut client).await.unwrap();
+    assert_eq!(response.header.id, 1);
+    assert_eq!(response.answers[0].data.to_string(), "192.0.2.80");
+
+    let response = tcp::read_message(&mut client).await.unwrap();
+    assert_eq!(response.header.id, 2);
+    assert_eq!(response.answers[0].qtype(), QueryType::SOA);
+    assert_eq!(response.answers.last().unwrap().qtype(), QueryType::SOA);
+
+    drop(client);
+    connection.await.unwrap();
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use cdn_dns::authority::secondary::transfer;
+use cdn_dns::authority::zone::Zone;
+use cdn_dns::authority::zone_file;
+use cdn_dns::dns_message::dns_header::ResultCode;
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::dns_record::RecordData;
+use cdn_dns::dns_message::edns::{Edns, EdnsOption};
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::tsig::{Algorithm, Keyring, Signer, TsigKey};
+use cdn_dns::dns_message::{DnsMessage, QueryType};
+use cdn_dns::dns_server::{tcp, udp, DnsServer};
+use cdn_dns::settings::acl::Acl;
+use cdn_dns::settings::config::ZoneSettings;
+use tokio::net::{TcpListener, UdpSocket};
+
+fn origin() -> DnsName {
+    "example.com".parse().unwrap()
+}
+
+fn key(name: &str, algorithm: Algorithm) -> TsigKey {
+    TsigKey {
+        name: name.parse().unwrap(),
+        algorithm,
+        secret: b"a secret shared by both ends".to_vec(),
+    }
+}
+
+/// `message` as received from the wire, where the data covered
+/// by its TSIG record is kept
+fn wire(mut message: DnsMessage) -> DnsMessage {
+    let buffer = message.into_buf_with_size(u16::MAX as usize).unwrap();
+    let mut buffer = PacketBuffer::from_bytes(buffer.get_range(0, buffer.pos()).unwrap());
+    buffer.set_strict(true);
+    DnsMessage::from_buf(&mut buffer).unwrap()
+}
+
+fn signed(message: &DnsMessage, key: &TsigKey) -> DnsMessage {
+    let mut message = message.clone();
+    Signer::new(key.clone()).sign(&mut message).unwrap();
+    wire(message)
+}
+
+fn tsig_error(message: &DnsMessage) -> u16 {
+    match message.resources.last().map(|rec| &rec.data) {
+        Some(RecordData::TSIG { error, .. }) => *error,
+        _ => panic!("No TSIG record"),
+    }
+}
+
+#[test]
+fn verify_test() {
+    let request = DnsMessage::query(&origin(), QueryType::SOA).id(7).build();
+
+    for algorithm in [
+        Algorithm::HmacSha256,
+        Algorithm::HmacSha384,
+        Algorithm::HmacSha512,
+    ] {
+        let key = key("key.example.", algorithm);
+        let keyring = Keyring::new(vec![key.clone()]);
+        let signer = keyring.verify(&signed(&request, &key)).unwrap().unwrap();
+        assert_eq!(signer.key().name, key.name);
+    }
+
+    let key = key("key.example.", Algorithm::HmacSha256);
+    let keyring = Keyring::new(vec![key.clone()]);
+    assert!(keyring.verify(&wire(request.clone())).unwrap().is_none());
+
+    // another secret, another name, a change on the way
+    let mut forged = key.clone();
+    forged.secret = b"not the secret".to_vec();
+    let message = signed(&request, &forged);
+    assert_eq!(keyring.verify(&message).unwrap_err(), ResultCode::BADSIG);
+    let response = keyring.error_response(&message, ResultCode::BADSIG);
+    assert_eq!(response.header.rescode, ResultCode::NOTAUTH);
+    assert_eq!(tsig_error(&response), u16::from(ResultCode::BADSIG));
+
+    let other = self::key("other.example.", Algorithm::HmacSha256);
+    let message = signed(&request, &other);
+    assert_eq!(keyring.verify(&message).unwrap_err(), ResultCode::BADKEY);
+
+    let mut message = signed(&request, &key);
+    message.header.id = 8;
+    message.questions[0].qtype = QueryType::A;
+    let message = wire(message);
+    assert_eq!(keyring.verify(&message).unwrap_err(), ResultCode::BADSIG);
+
+    // a TSIG record out of place
+    let mut message = signed(&request, &key);
+    message.answers.push(message.resources[0].clone());
+    let message = wire(message);
+    assert_eq!(keyring.verify(&message).unwrap_err(), ResultCode::FORMERR);
+
+    // signed too long ago, the error response is signed
+    let mut client = Signer::new(key.clone());
+    let mut message = request.clone();
+    client.sign_at(&mut message, 1_000_000).unwrap();
+    let message = wire(message);
+    assert_eq!(keyring.verify(&message).unwrap_err(), ResultCode::BADTIME);
+    let response = wire(keyring.error_response(&message, ResultCode::BADTIME));
+    assert_eq!(tsig_error(&response), u16::from(ResultCode::BADTIME));
+    assert_eq!(client.verify(&response).unwrap_err(), ResultCode::BADTIME);
+}
+
+fn server(key_name: &str) -> DnsServer {
+    let mut config = common::config(vec![ZoneSettings {
+        allow_transfer: Acl::new(vec!["127.0.0.0/8".parse().unwrap()]),
+        key: Some(key_name.parse().unwrap()),
+        ..common::zone("example.com", "example.com.zone")
+    }]);
+    config.tsig_keys = vec![key(key_name, Algorithm::HmacSha256)];
+    let server = DnsServer::new(config).expect("Failed to load the zones");
+    let hosts: String = (0..2000)
+        .map(|i| format!("host{} A 192.0.2.{}\n", i, i % 256))
+        .collect();
+    let text = format!(
+        "$TTL 300\n@ SOA ns1 admin 1 3600 1 2 5\n@ NS ns1\nns1 A 192.0.2.1\n{}",
+        hosts
+    );
+    let zone = Zone::new(origin(), zone_file::parse(&text, &origin()).unwrap()).unwrap();
+    server.catalog.insert(zone);
+    server
+}
+
+#[tokio::test]
+async fn signed_transfer_test() {
+    let server = server("transfer.example.");
+    let key = key("transfer.example.", Algorithm::HmacSha256);
+    let src: SocketAddr = "127.0.0.1:5353".parse().unwrap();
+    let request = DnsMessage::query(&origin(), QueryType::AXFR).id(4).build();
+
+    // the zone requires its key
+    let messages = server.handle_stream_request(&request, src).await;
+    assert_eq!(messages[0].header.rescode, ResultCode::REFUSED);
+    let other = self::key("other.example.", Algorithm::HmacSha256);
+    let messages = server
+        .handle_stream_request(&signed(&request, &other), src)
+        .await;
+    assert_eq!(messages[0].header.rescode, ResultCode::NOTAUTH);
+    assert_eq!(tsig_error(&messages[0]), u16::from(ResultCode::BADKEY));
+
+    // every message is signed, chained to the previous one
+    let mut client = Signer::new(key.clone());
+    let mut signed_request = request.clone();
+    client.sign(&mut signed_request).unwrap();
+    let messages = server
+        .handle_stream_request(&wire(signed_request), src)
+        .await;
+    assert!(messages.len() > 1);
+    for message in &messages {
+        assert_eq!(message.header.rescode, ResultCode::NOERROR);
+        client.verify(&wire(message.clone())).unwrap();
+    }
+
+    // out of order, the chain breaks
+    let mut client = Signer::new(key.clone());
+    let mut signed_request = request.clone();
+    client.sign(&mut signed_request).unwrap();
+    let messages = server
+        .handle_stream_request(&wire(signed_request), src)
+        .await;
+    client.verify(&wire(messages[0].clone())).unwrap();
+    assert_eq!(
+        client.verify(&wire(messages[2].clone())).unwrap_err(),
+        ResultCode::BADSIG
+    );
+}
+
+#[tokio::test]
+async fn signed_udp_test() {
+    let server = Arc::new(server("transfer.example."));
+    let big: String = (0..50)
+        .map(|i| format!("big A 198.51.100.{}\n", i))
+        .collect();
+    let text = format!("$TTL 300\n@ SOA ns1 admin 1 3600 1 2 5\n@ NS ns1\n{}", big);
+    let zone = Zone::new(origin(), zone_file::parse(&text, &origin()).unwrap()).unwrap();
+    server.catalog.insert(zone);
+    let key = key("transfer.example.", Algorithm::HmacSha256);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let udp_server = server.clone();
+    tokio::spawn(udp::serve(socket, udp_server));
+
+    // a query larger than 512 bytes, its answer does not fit
+    let mut edns = Edns::new();
+    edns.udp_payload_size = 512;
+    edns.options.push(EdnsOption {
+        code: 12,
+        data: vec![0; 600],
+    });
+    let mut request = DnsMessage::query(&"big.example.com".parse().unwrap(), QueryType::A)
+        .id(9)
+        .edns(edns)
+        .build();
+    let mut client = Signer::new(key);
+    client.sign(&mut request).unwrap();
+    let buffer = request.into_buf_with_size(u16::MAX as usize).unwrap();
+    let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client_socket.connect(addr).await.unwrap();
+    client_socket
+        .send(&buffer.buf[..buffer.pos()])
+        .await
+        .unwrap();
+
+    let mut buffer = PacketBuffer::with_size(u16::MAX as usize);
+    let len = client_socket.recv(&mut buffer.buf).await.unwrap();
+    buffer.truncate(len);
+    let response = DnsMessage::from_buf(&mut buffer).unwrap();
+    assert_eq!(response.header.rescode, ResultCode::NOERROR);
+    assert!(response.header.truncated_message);
+    assert!(response.answers.is_empty());
+    client.verify(&response).unwrap();
+}
+
+#[tokio::test]
+async fn secondary_transfer_test() {
+    let server = Arc::new(server("transfer.example."));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(tcp::serve(listener, server.clone()));
+
+    let key = key("transfer.example.", Algorithm::HmacSha256);
+    let zone = transfer(addr, &origin(), None, Some(&key))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(zone.records().count(), 2003);
+
+    assert!(transfer(addr, &origin(), None, None).await.is_err());
+    let mut forged = key.clone();
+    forged.secret = b"not the secret".to_vec();
+    let err = transfer(addr, &origin(), None, Some(&forged))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("BADSIG"), "{}", err);
+}
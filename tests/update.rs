@@ -0,0 +1,231 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use cdn_dns::authority::zone::Zone;
+use cdn_dns::dns_message::dns_header::{Opcode, ResultCode};
+use cdn_dns::dns_message::dns_name::DnsName;
+use cdn_dns::dns_message::dns_record::{DnsRecord, RecordData};
+use cdn_dns::dns_message::packet_buffer::PacketBuffer;
+use cdn_dns::dns_message::{DnsClass, DnsMessage, QueryType};
+use cdn_dns::dns_server::DnsServer;
+use cdn_dns::settings::acl::Acl;
+use cdn_dns::settings::config::ZoneSettings;
+use common::TempFile;
+
+fn origin() -> DnsName {
+    "example.com".parse().unwrap()
+}
+
+fn src() -> SocketAddr {
+    "127.0.0.1:5353".parse().unwrap()
+}
+
+/// A server of example.com saved to a temporary file, updates
+/// allowed from the loopback
+fn server(name: &str) -> (DnsServer, TempFile) {
+    let file = TempFile::new(
+        &format!("{}.zone", name),
+        "$TTL 300\n@ SOA ns1 admin 10 3600 600 86400 60\n@ NS ns1\nns1 A 192.0.2.1\n\
+         www A 192.0.2.80\nwww A 192.0.2.81\nalias CNAME www\n",
+    );
+    let server = common::server(vec![ZoneSettings {
+        origin: origin(),
+        file: file.path(),
+        allow_update: Acl::new(vec!["127.0.0.0/8".parse().unwrap()]),
+        ..Default::default()
+    }]);
+    (server, file)
+}
+
+fn rr(text: &str) -> DnsRecord {
+    text.parse().unwrap()
+}
+
+/// A record without data standing for an RRset, or a name
+/// with `QueryType::ANY`
+fn rrset(name: &str, class: DnsClass, qtype: QueryType) -> DnsRecord {
+    let data = RecordData::UNKNOWN {
+        qtype: qtype.into(),
+        data: Vec::new(),
+    };
+    let mut record = DnsRecord::with_data(name.parse().unwrap(), 0, data);
+    record.class = class;
+    record
+}
+
+/// An UPDATE of example.com, sent through the wire format
+fn update(prerequisites: Vec<DnsRecord>, updates: Vec<DnsRecord>) -> DnsMessage {
+    let mut message = DnsMessage::query(&origin(), QueryType::SOA)
+        .opcode(Opcode::UPDATE)
+        .id(3)
+        .build();
+    message.answers = prerequisites;
+    message.authorities = updates;
+    let mut buffer = message.into_buf_with_size(u16::MAX as usize).unwrap();
+    buffer.seek(0).unwrap();
+    let mut buffer = PacketBuffer::from_bytes(buffer.get_range(0, buffer.len()).unwrap());
+    buffer.set_strict(true);
+    DnsMessage::from_buf(&mut buffer).unwrap()
+}
+
+async fn send(server: &DnsServer, request: DnsMessage) -> ResultCode {
+    let response = server.handle_request(&request, src()).await.unwrap();
+    assert_eq!(response.header.opcode, Opcode::UPDATE);
+    assert_eq!(response.header.id, 3);
+    response.header.rescode
+}
+
+fn records(server: &DnsServer) -> Vec<String> {
+    let zone = server.catalog.get(&origin()).unwrap();
+    zone.records().map(|rec| rec.to_string()).collect()
+}
+
+fn serial(server: &DnsServer) -> u32 {
+    server.catalog.get(&origin()).unwrap().serial()
+}
+
+#[tokio::test]
+async fn update_test() {
+    let (server, file) = server("update");
+
+    // add, the serial grows and the file is rewritten
+    let request = update(vec![], vec![rr("edge1.example.com. 60 IN A 192.0.2.10")]);
+    assert_eq!(send(&server, request).await, ResultCode::NOERROR);
+    assert_eq!(serial(&server), 11);
+    let saved = Zone::load(origin(), file.path().as_ref()).unwrap();
+    assert_eq!(saved.serial(), 11);
+    assert!(saved.records().any(|rec| rec.domain == "edge1.example.com"));
+
+    // delete a record, an RRset and a name
+    let request = update(vec![], vec![rr("www.example.com. 0 NONE A 192.0.2.80")]);
+    assert_eq!(send(&server, request).await, ResultCode::NOERROR);
+    assert!(!records(&server).contains(&"www.example.com.\t300\tIN\tA\t192.0.2.80".to_string()));
+    assert!(records(&server).contains(&"www.example.com.\t300\tIN\tA\t192.0.2.81".to_string()));
+
+    let request = update(
+        vec![],
+        vec![rrset("www.example.com", DnsClass::ANY, QueryType::A)],
+    );
+    assert_eq!(send(&server, request).await, ResultCode::NOERROR);
+    let request = update(
+        vec![],
+        vec![rrset("edge1.example.com", DnsClass::ANY, QueryType::ANY)],
+    );
+    assert_eq!(send(&server, request).await, ResultCode::NOERROR);
+    assert!(!records(&server)
+        .iter()
+        .any(|rec| rec.starts_with("www.") || rec.starts_with("edge1.")));
+    assert_eq!(serial(&server), 14);
+
+    // no change, no new serial
+    let request = update(vec![], vec![rr("www.example.com. 0 NONE A 192.0.2.80")]);
+    assert_eq!(send(&server, request).await, ResultCode::NOERROR);
+    assert_eq!(serial(&server), 14);
+
+    // the journal follows the updates
+    let zone = server.catalog.get(&origin()).unwrap();
+    assert_eq!(zone.journal().since(10).unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn prerequisite_test() {
+    let (server, _file) = server("prerequisite");
+    let add = || vec![rr("edge2.example.com. 60 IN A 192.0.2.20")];
+
+    for (prerequisite, rescode) in [
+        (
+            rrset("nowhere.example.com", DnsClass::ANY, QueryType::ANY),
+            ResultCode::NXDOMAIN,
+        ),
+        (
+            rrset("www.example.com", DnsClass::ANY, QueryType::AAAA),
+            ResultCode::NXRRSET,
+        ),
+        (
+            rrset("www.example.com", DnsClass::NONE, QueryType::ANY),
+            ResultCode::YXDOMAIN,
+        ),
+        (
+            rrset("www.example.com", DnsClass::NONE, QueryType::A),
+            ResultCode::YXRRSET,
+        ),
+        // the RRset holds another record too
+        (
+            rr("www.example.com. 0 IN A 192.0.2.80"),
+            ResultCode::NXRRSET,
+        ),
+        (
+            rr("www.example.org. 0 IN A 192.0.2.80"),
+            ResultCode::NOTZONE,
+        ),
+    ] {
+        let request = update(vec![prerequisite], add());
+        assert_eq!(send(&server, request).await, rescode);
+    }
+    assert_eq!(serial(&server), 10);
+
+    let prerequisites = vec![
+        rrset("www.example.com", DnsClass::ANY, QueryType::ANY),
+        rrset("edge2.example.com", DnsClass::NONE, QueryType::ANY),
+        rr("www.example.com. 0 IN A 192.0.2.80"),
+        rr("www.example.com. 0 IN A 192.0.2.81"),
+    ];
+    assert_eq!(
+        send(&server, update(prerequisites, add())).await,
+        ResultCode::NOERROR
+    );
+    assert_eq!(serial(&server), 11);
+}
+
+#[tokio::test]
+async fn atomic_update_test() {
+    let (server, _file) = server("atomic");
+    let before = records(&server);
+
+    // the second update is out of the zone, the first is undone
+    let request = update(
+        vec![],
+        vec![
+            rr("edge3.example.com. 60 IN A 192.0.2.30"),
+            rr("edge3.example.org. 60 IN A 192.0.2.30"),
+        ],
+    );
+    assert_eq!(send(&server, request).await, ResultCode::NOTZONE);
+    assert_eq!(records(&server), before);
+
+    // the apex keeps its SOA and last NS, aliases keep apart
+    // from other data
+    let request = update(
+        vec![],
+        vec![
+            rrset("example.com", DnsClass::ANY, QueryType::ANY),
+            rr("example.com. 0 NONE NS ns1.example.com."),
+            rr("alias.example.com. 60 IN A 192.0.2.1"),
+            rr("www.example.com. 60 IN CNAME web.example.com."),
+        ],
+    );
+    assert_eq!(send(&server, request).await, ResultCode::NOERROR);
+    assert_eq!(records(&server), before);
+    assert_eq!(serial(&server), 10);
+}
+
+#[tokio::test]
+async fn update_permission_test() {
+    let (server, _file) = server("permission");
+    let request = update(vec![], vec![rr("edge4.example.com. 60 IN A 192.0.2.40")]);
+
+    let stranger = "192.0.2.1:5353".parse().unwrap();
+    let response = server.handle_request(&request, stranger).await.unwrap();
+    assert_eq!(response.header.rescode, ResultCode::REFUSED);
+
+    let mut other = request.clone();
+    other.questions[0].qname = "example.org".parse().unwrap();
+    assert_eq!(send(&server, other).await, ResultCode::NOTAUTH);
+
+    let mut malformed = request.clone();
+    malformed.questions[0].qtype = QueryType::A;
+    assert_eq!(send(&server, malformed).await, ResultCode::FORMERR);
+
+    assert_eq!(serial(&server), 10);
+}